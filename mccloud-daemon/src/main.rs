@@ -3,7 +3,11 @@
 use std::{net::SocketAddr, path::PathBuf};
 
 use clap::Parser;
-use mccloud::{config::Config, IntoTargetAddr};
+use mccloud::{
+    config::{Bootstrap, Config, Discovery, Relationship},
+    keystore::Keystore,
+    IntoTargetAddr,
+};
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -16,8 +20,26 @@ struct Args {
     data: PathBuf,
     #[arg(long)]
     conn: Vec<String>,
+    /// A well known peer, which is connected again while there are no connections.
+    #[arg(long)]
+    seed: Vec<String>,
+    /// A peer, which is reconnected forever, whenever the connection is lost.
+    #[arg(long)]
+    persistent: Vec<String>,
+    /// Find other peers in the local network via udp multicast.
+    #[arg(long)]
+    discovery: bool,
+    /// Index the data in the blockchain by author and signature.
+    #[arg(long)]
+    index_data: bool,
     #[arg(long, default_value = "debug")]
     log: String,
+    /// A keystore file to load the node key from. The passphrase is read from MCCLOUD_PASSPHRASE.
+    #[arg(long)]
+    keystore: Option<PathBuf>,
+    /// The name of the keystore entry to use as node key.
+    #[arg(long, default_value = "node")]
+    key: String,
 }
 
 #[tokio::main]
@@ -25,12 +47,31 @@ async fn main() {
     let args = Args::parse();
     tracing_subscriber::fmt().with_env_filter(&args.log).init();
 
+    let defaults = Config::default();
     let cfg = Config {
         addr: SocketAddr::new(args.host.parse().unwrap(), args.port),
         folder: args.data,
-        ..Default::default()
+        relationship: Relationship {
+            persistent: args.persistent,
+            ..defaults.relationship
+        },
+        bootstrap: Bootstrap {
+            seeds: args.seed,
+            ..defaults.bootstrap
+        },
+        discovery: args.discovery.then(Discovery::default),
+        index_data: args.index_data,
+        ..defaults
+    };
+    let peer = if let Some(keystore) = &args.keystore {
+        let passphrase = std::env::var("MCCLOUD_PASSPHRASE").expect("MCCLOUD_PASSPHRASE is not set");
+        let keystore = Keystore::open(keystore).unwrap();
+        mccloud::Peer::from_keystore_async(cfg, &keystore, &args.key, passphrase.as_bytes())
+            .await
+            .unwrap()
+    } else {
+        mccloud::Peer::new_async(cfg).await.unwrap()
     };
-    let peer = mccloud::Peer::new(cfg).unwrap();
 
     for conn in args.conn.iter() {
         match conn.as_str().into_target_addr() {
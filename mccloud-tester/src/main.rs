@@ -7,7 +7,7 @@ use axum::{
 };
 use indexmap::IndexMap;
 use mccloud::{
-    config::{Algorithm, Config, Relationship},
+    config::{Config, Relationship},
     Peer, TargetAddr,
 };
 use serde::{Deserialize, Serialize};
@@ -51,40 +51,28 @@ struct App {
 }
 
 impl App {
-    async fn update_peer_list(&mut self) {
-        let mut peers = Vec::new();
-
-        for p in self.peers.values() {
-            let data = PeerData::from(p).await;
-            peers.push(data);
-        }
-
-        self.site.peers = peers;
-    }
-
     async fn spawn_peers(&mut self, count: u32) {
         let mut new_ones = Vec::new();
         for _ in 0..count {
+            let defaults = Config::default();
             let cfg = Config {
+                network: "mccloud-tester".into(),
                 addr: ([127, 0, 0, 1], self.port_pool).into(),
-                proxy: None,
                 folder: PathBuf::from("data").join(self.port_pool.to_string()),
                 data_gather_time: Duration::from_millis(800),
-                thin: false,
                 relationship: Relationship {
                     time: Duration::from_millis(1000),
                     reconnect: Duration::from_millis(2000),
+                    max_reconnect: Duration::from_secs(30),
                     count: 2,
-                    retry: 3,
-                },
-                algorithm: Algorithm::Riddle {
-                    next_candidates: 3,
-                    forced_restart: true,
+                    leave_after: Duration::from_secs(10),
+                    ..defaults.relationship
                 },
+                ..defaults
             };
             self.port_pool += 1;
 
-            let p = Peer::new(cfg);
+            let p = Peer::new_async(cfg).await;
 
             match p {
                 Ok(p) => {
@@ -206,28 +194,6 @@ async fn peer_connect(state: AppPtr, Json(conn): Json<ConnData>) {
     }
 }
 
-async fn peer_blocks(state: AppPtr, Path(pubhex): Path<String>) -> Json<Vec<BlockData>> {
-    let app = state.lock().await;
-    let mut blocks = Vec::new();
-
-    if let Some(p) = app.peers.get(&pubhex) {
-        for blk in p.block_iter().await {
-            let b = blk.unwrap();
-            let data: Vec<String> = b.data.into_iter().map(|d| String::from_utf8(d.data).unwrap()).collect();
-            let next: Vec<String> = b.next_choices.iter().map(hex::encode).collect();
-
-            blocks.push(BlockData {
-                hash: hex::encode(b.hash),
-                data,
-                author: hex::encode(b.author),
-                next_authors: next,
-            });
-        }
-    }
-
-    Json(blocks)
-}
-
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt().init();
@@ -0,0 +1,55 @@
+use hashbrown::HashSet;
+
+use crate::{config::Access, PubKeyBytes};
+
+///
+/// The allow and deny lists of a peer, initialized from the config and changed at runtime.
+///
+pub struct AccessList {
+    permissioned: bool,
+    allow: HashSet<PubKeyBytes>,
+    deny: HashSet<PubKeyBytes>,
+}
+
+impl AccessList {
+    pub fn new(cfg: &Access) -> Self {
+        Self {
+            permissioned: cfg.permissioned,
+            allow: cfg.allow.iter().cloned().collect(),
+            deny: cfg.deny.iter().cloned().collect(),
+        }
+    }
+
+    /// Returns true if the peer may connect and author data and blocks.
+    pub fn is_allowed(&self, pubkey: &PubKeyBytes) -> bool {
+        !self.deny.contains(pubkey) && (!self.permissioned || self.allow.contains(pubkey))
+    }
+
+    /// Returns false if the key was already in the allow list.
+    pub fn allow(&mut self, pubkey: PubKeyBytes) -> bool {
+        self.allow.insert(pubkey)
+    }
+
+    /// Returns false if the key was not in the allow list.
+    pub fn revoke(&mut self, pubkey: &PubKeyBytes) -> bool {
+        self.allow.remove(pubkey)
+    }
+
+    /// Returns false if the key was already in the deny list.
+    pub fn deny(&mut self, pubkey: PubKeyBytes) -> bool {
+        self.deny.insert(pubkey)
+    }
+
+    /// Returns false if the key was not in the deny list.
+    pub fn undeny(&mut self, pubkey: &PubKeyBytes) -> bool {
+        self.deny.remove(pubkey)
+    }
+
+    pub fn allowed(&self) -> Vec<PubKeyBytes> {
+        self.allow.iter().cloned().collect()
+    }
+
+    pub fn denied(&self) -> Vec<PubKeyBytes> {
+        self.deny.iter().cloned().collect()
+    }
+}
@@ -0,0 +1,342 @@
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use hashbrown::{HashMap, HashSet};
+use rand::seq::SliceRandom;
+
+use crate::{
+    config::Addresses,
+    error::{Error, Result},
+    ex,
+    membership::now,
+    PubKeyBytes,
+};
+
+/// The name of the file, inside the data folder, which holds the address book.
+pub const ADDRESS_FILE: &str = "addresses.db";
+/// The name of the file, inside the data folder, which holds the anchor peers.
+pub const ANCHOR_FILE: &str = "anchors.db";
+
+/// How many addresses are kept per peer.
+const MAX_ADDRS: usize = 4;
+
+/// Where a peer was reachable.
+#[derive(BorshSerialize, BorshDeserialize, Clone, PartialEq, Eq, Debug)]
+pub struct AddressRecord {
+    pub pubkey: PubKeyBytes,
+    /// The addresses of the peer, the one which worked last comes first.
+    pub addrs: Vec<String>,
+    /// When we were last connected to the peer, in unix milliseconds. Zero, if we only learned about the peer from
+    /// others.
+    pub last_seen: u64,
+    /// How often connecting to the peer failed in a row.
+    pub failures: u32,
+}
+
+/// The network an address is in: the /16 subnet of an ipv4, the /32 subnet of an ipv6, or else the host.
+pub fn group(addr: &str) -> String {
+    match addr.parse::<SocketAddr>().map(|a| a.ip()) {
+        Ok(IpAddr::V4(ip)) => {
+            let o = ip.octets();
+            format!("{}.{}", o[0], o[1])
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let s = ip.segments();
+            format!("{:x}:{:x}", s[0], s[1])
+        }
+        Err(_) => addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr).to_string(),
+    }
+}
+
+impl AddressRecord {
+    /// The network the peer is in, by the address which worked last.
+    pub fn group(&self) -> String {
+        self.addrs.first().map(|a| group(a)).unwrap_or_default()
+    }
+}
+
+///
+/// The known addresses of other peers, saved in the data folder, so a restarted peer finds back into the network.
+///
+pub struct AddressBook {
+    cfg: Addresses,
+    file: PathBuf,
+    records: HashMap<PubKeyBytes, AddressRecord>,
+    /// Which peer told us about a record. Not saved, as it only matters for the peers of this run.
+    sources: HashMap<PubKeyBytes, PubKeyBytes>,
+    /// The long lived outbound peers, which are connected first after a restart.
+    anchors: Vec<PubKeyBytes>,
+    dirty: bool,
+    closed: bool,
+}
+
+impl AddressBook {
+    /// Loads the address book from the data folder. A missing file is an empty address book.
+    pub fn load(folder: &Path, cfg: Addresses) -> Result<Self> {
+        let file = folder.join(ADDRESS_FILE);
+
+        let records: Vec<AddressRecord> = ex!(read_file(&file), source);
+        let anchors: Vec<PubKeyBytes> = ex!(read_file(&folder.join(ANCHOR_FILE)), source);
+
+        Ok(Self {
+            cfg,
+            file,
+            records: records.into_iter().map(|r| (r.pubkey, r)).collect(),
+            sources: HashMap::new(),
+            anchors,
+            dirty: false,
+            closed: false,
+        })
+    }
+
+    /// Writes the address book to the data folder, if it changed.
+    pub fn save(&mut self) -> Result<()> {
+        if !self.dirty || self.closed {
+            return Ok(());
+        }
+
+        let records: Vec<&AddressRecord> = self.records.values().collect();
+        ex!(write_file(&self.file, &records), source);
+        ex!(
+            write_file(&self.file.with_file_name(ANCHOR_FILE), &self.anchors),
+            source
+        );
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Saves the address book a last time. Later changes are kept in memory only.
+    pub fn close(&mut self) -> Result<()> {
+        let res = self.save();
+        self.closed = true;
+        res
+    }
+
+    /// Records a successful connection to a peer, via the given address.
+    pub fn seen(&mut self, pubkey: PubKeyBytes, addr: String) {
+        let Some(record) = self.entry(pubkey, true) else {
+            return;
+        };
+        record.addrs.retain(|a| *a != addr);
+        record.addrs.insert(0, addr);
+        record.addrs.truncate(MAX_ADDRS);
+        record.last_seen = now();
+        record.failures = 0;
+        self.dirty = true;
+    }
+
+    /// Records a failed connection attempt via the given address. An address others told us about, of a peer we
+    /// were connected to, is just dropped: only the address which worked last counts against the peer. A peer which
+    /// failed too often is removed.
+    pub fn failed(&mut self, pubkey: &PubKeyBytes, addr: &str) {
+        let Some(record) = self.records.get_mut(pubkey) else {
+            return;
+        };
+        if !record.addrs.iter().any(|a| a == addr) {
+            return;
+        }
+
+        if record.last_seen > 0 && record.addrs[0] != addr {
+            record.addrs.retain(|a| a != addr);
+        } else {
+            record.failures += 1;
+            if record.failures >= self.cfg.max_failures {
+                self.records.remove(pubkey);
+                self.sources.remove(pubkey);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Adds the addresses of a record, which the source peer sent us. Its failures and last seen time are not taken
+    /// over, only our own connections count. If the address book is full, it only replaces a record we were never
+    /// connected to. A single source adds at most `per_source` new records.
+    pub fn learn(&mut self, mut other: AddressRecord, source: &PubKeyBytes) {
+        other.addrs.truncate(MAX_ADDRS);
+        if other.addrs.is_empty() {
+            return;
+        }
+        if !self.records.contains_key(&other.pubkey)
+            && self.sources.values().filter(|s| *s == source).count() >= self.cfg.per_source
+        {
+            return;
+        }
+
+        let Some(record) = self.entry(other.pubkey, false) else {
+            return;
+        };
+        for addr in other.addrs {
+            if record.addrs.len() < MAX_ADDRS && !record.addrs.contains(&addr) {
+                record.addrs.push(addr);
+            }
+        }
+        self.sources.entry(other.pubkey).or_insert(*source);
+        self.dirty = true;
+    }
+
+    /// Returns the record of the peer, and makes room for it if it is new. A record we were connected to is only
+    /// evicted for another one we were connected to.
+    fn entry(&mut self, pubkey: PubKeyBytes, seen: bool) -> Option<&mut AddressRecord> {
+        if !self.records.contains_key(&pubkey) && self.records.len() >= self.cfg.capacity && !self.evict(seen) {
+            return None;
+        }
+
+        Some(self.records.entry(pubkey).or_insert_with(|| AddressRecord {
+            pubkey,
+            addrs: Vec::new(),
+            last_seen: 0,
+            failures: 0,
+        }))
+    }
+
+    /// Removes the worst record: the one which failed most, and among those, was seen longest ago. Unless `seen`,
+    /// only records we were never connected to are taken. Returns false if there was none.
+    fn evict(&mut self, seen: bool) -> bool {
+        let worst = self
+            .records
+            .values()
+            .filter(|r| seen || r.last_seen == 0)
+            .max_by_key(|r| (r.failures, u64::MAX - r.last_seen))
+            .map(|r| r.pubkey);
+        match worst {
+            Some(worst) => {
+                self.records.remove(&worst);
+                self.sources.remove(&worst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///
+    /// Returns up to `count` records to connect to, the most reliable and most recently seen first. A record is
+    /// only taken of a network we are not connected to yet, and from a source no other record was taken from,
+    /// so a single peer can not fill all our connections. If there are not enough of those, the rest is filled up
+    /// from other networks, but still only one record per source.
+    ///
+    pub fn candidates(
+        &self,
+        count: usize,
+        exclude: &HashSet<PubKeyBytes>,
+        groups: &HashSet<String>,
+    ) -> Vec<AddressRecord> {
+        let mut candidates: Vec<&AddressRecord> = self
+            .records
+            .values()
+            .filter(|r| !r.addrs.is_empty() && !exclude.contains(&r.pubkey))
+            .collect();
+        candidates.sort_unstable_by_key(|r| (r.failures, u64::MAX - r.last_seen));
+
+        let mut groups = groups.clone();
+        let mut sources: HashSet<PubKeyBytes> = HashSet::new();
+        let (mut picked, rest): (Vec<&AddressRecord>, Vec<&AddressRecord>) = candidates.into_iter().partition(|r| {
+            let source = self.sources.get(&r.pubkey);
+            let diverse = !groups.contains(&r.group()) && source.is_none_or(|s| !sources.contains(s));
+            if diverse {
+                groups.insert(r.group());
+                sources.extend(source);
+            }
+            diverse
+        });
+        picked.extend(rest.into_iter().filter(|r| match self.sources.get(&r.pubkey) {
+            Some(source) => sources.insert(*source),
+            None => true,
+        }));
+
+        picked.into_iter().take(count).cloned().collect()
+    }
+
+    /// Remembers the anchor peers, to connect to them first after a restart.
+    pub fn set_anchors(&mut self, anchors: Vec<PubKeyBytes>) {
+        if self.anchors != anchors {
+            self.anchors = anchors;
+            self.dirty = true;
+        }
+    }
+
+    pub fn is_anchor(&self, pubkey: &PubKeyBytes) -> bool {
+        self.anchors.contains(pubkey)
+    }
+
+    /// Returns the records of the anchor peers, which are not excluded.
+    pub fn anchors(&self, exclude: &HashSet<PubKeyBytes>) -> Vec<AddressRecord> {
+        self.anchors
+            .iter()
+            .filter(|a| !exclude.contains(*a))
+            .filter_map(|a| self.records.get(a))
+            .filter(|r| !r.addrs.is_empty())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns up to `count` of the most recently seen records, to send them to another peer.
+    pub fn recent(&self, count: usize, exclude: &PubKeyBytes) -> Vec<AddressRecord> {
+        let mut recent: Vec<&AddressRecord> = self.records.values().filter(|r| r.pubkey != *exclude).collect();
+        recent.sort_unstable_by_key(|r| u64::MAX - r.last_seen);
+
+        recent.into_iter().take(count).cloned().collect()
+    }
+
+    ///
+    /// Returns up to `count` random records for a peer exchange. Only peers which were seen within `max_age` and
+    /// did not fail since are shared, and a second peer of a network is only taken, once every network has one.
+    ///
+    pub fn sample(&self, count: usize, exclude: &HashSet<PubKeyBytes>, max_age: Duration) -> Vec<AddressRecord> {
+        let oldest = now().saturating_sub(max_age.as_millis() as u64);
+        let mut fresh: Vec<&AddressRecord> = self
+            .records
+            .values()
+            .filter(|r| !r.addrs.is_empty() && r.failures == 0 && r.last_seen >= oldest && !exclude.contains(&r.pubkey))
+            .collect();
+        fresh.shuffle(&mut rand::rng());
+
+        let mut groups = HashSet::new();
+        let (mut sample, rest): (Vec<&AddressRecord>, Vec<&AddressRecord>) =
+            fresh.into_iter().partition(|r| groups.insert(r.group()));
+        sample.extend(rest);
+
+        sample.into_iter().take(count).cloned().collect()
+    }
+
+    pub fn records(&self) -> Vec<AddressRecord> {
+        self.records.values().cloned().collect()
+    }
+}
+
+/// Reads a borsh file. A missing file is empty.
+fn read_file<T: BorshDeserialize>(file: &Path) -> Result<Vec<T>> {
+    if !file.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut buffer = Vec::new();
+    let mut f = ex!(OpenOptions::new().read(true).open(file), io);
+    ex!(f.read_to_end(&mut buffer), io);
+
+    Ok(ex!(borsh::from_slice(&buffer), io))
+}
+
+/// Writes a borsh file via a temporary one, so a crash never leaves a half written file.
+fn write_file<T: BorshSerialize>(file: &Path, items: &[T]) -> Result<()> {
+    let buffer = ex!(borsh::to_vec(items), io);
+
+    let tmp = file.with_extension("tmp");
+    {
+        let mut f = ex!(
+            OpenOptions::new().create(true).truncate(true).write(true).open(&tmp),
+            io
+        );
+        ex!(f.write_all(&buffer), io);
+        ex!(f.sync_all(), io);
+    }
+    ex!(std::fs::rename(&tmp, file), io);
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use tokio_socks::TargetAddr;
+
+use crate::{config::Scoring, error::ErrorKind, PubKeyBytes};
+
+/// The longest possible ban. Longer bans are cut, to not overflow the clock.
+const FOREVER: Duration = Duration::from_secs(60 * 60 * 24 * 365 * 10);
+
+/// What is banned.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Banned {
+    Pubkey(PubKeyBytes),
+    /// An ip address without port.
+    Host(String),
+}
+
+impl Banned {
+    /// Returns the host of an address, if it can be banned. Loopback addresses and names are never banned, as
+    /// they are shared by all peers behind a local proxy or transport.
+    pub fn host(addr: &TargetAddr<'_>) -> Option<Self> {
+        match addr {
+            TargetAddr::Ip(ip) if !ip.ip().is_loopback() => Some(Self::Host(ip.ip().to_string())),
+            _ => None,
+        }
+    }
+}
+
+///
+/// The misbehaviour scores of the peers and the active bans.
+///
+/// Every peer starts with the full score. Each protocol violation of the peer takes away the penalty of its kind,
+/// and one point is regained per `regain` time. A peer which reaches zero is banned by public key and host, until
+/// the ban time is over.
+///
+pub struct Bans {
+    cfg: Scoring,
+    /// The lowered scores, and when they were lowered last.
+    scores: HashMap<PubKeyBytes, (u32, Instant)>,
+    banned: HashMap<Banned, Instant>,
+}
+
+impl Bans {
+    pub fn new(cfg: Scoring) -> Self {
+        Self {
+            cfg,
+            scores: HashMap::new(),
+            banned: HashMap::new(),
+        }
+    }
+
+    fn penalty(&self, kind: ErrorKind) -> u32 {
+        match kind {
+            ErrorKind::Protocol => self.cfg.protocol,
+            ErrorKind::Encryption => self.cfg.encryption,
+            ErrorKind::Blockchain => self.cfg.blockchain,
+            ErrorKind::Limit => self.cfg.limit,
+            // an idle peer, or an honest peer relaying the data of someone we do not allow
+            ErrorKind::Timeout | ErrorKind::Denied => 0,
+            // our own failures, or a normal disconnect
+            ErrorKind::Io | ErrorKind::Disconnect | ErrorKind::Addr | ErrorKind::Sync | ErrorKind::Extern => 0,
+        }
+    }
+
+    /// Lowers the score of a peer, by the penalty of the error kind. Returns true if the peer was banned.
+    pub fn penalize(&mut self, pubkey: &PubKeyBytes, host: Option<Banned>, kind: ErrorKind) -> bool {
+        let penalty = self.penalty(kind);
+        if penalty == 0 {
+            return false;
+        }
+
+        let score = self.score(pubkey).saturating_sub(penalty);
+        self.scores.insert(*pubkey, (score, Instant::now()));
+
+        if score == 0 {
+            self.scores.remove(pubkey);
+            self.ban(Banned::Pubkey(*pubkey), self.cfg.ban_time);
+            if let Some(host) = host {
+                self.ban(host, self.cfg.ban_time);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the current score of a peer, with the points regained since its last penalty.
+    pub fn score(&self, pubkey: &PubKeyBytes) -> u32 {
+        match self.scores.get(pubkey) {
+            Some((score, since)) => {
+                let regained = since.elapsed().as_nanos() / self.cfg.regain.as_nanos().max(1);
+                (*score as u128 + regained).min(self.cfg.score as u128) as u32
+            }
+            None => self.cfg.score,
+        }
+    }
+
+    pub fn ban(&mut self, what: Banned, time: Duration) {
+        self.banned.insert(what, Instant::now() + time.min(FOREVER));
+    }
+
+    pub fn is_banned(&mut self, what: &Banned) -> bool {
+        match self.banned.get(what) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                self.banned.remove(what);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// Returns all active bans, with the time they still last.
+    pub fn list(&mut self) -> Vec<(Banned, Duration)> {
+        let now = Instant::now();
+        self.banned.retain(|_, until| *until > now);
+        self.banned.iter().map(|(b, until)| (b.clone(), *until - now)).collect()
+    }
+
+    /// Lifts a ban. Returns false if there was no such ban.
+    pub fn unban(&mut self, what: &Banned) -> bool {
+        if let Banned::Pubkey(pubkey) = what {
+            self.scores.remove(pubkey);
+        }
+        self.banned.remove(what).is_some()
+    }
+
+    /// Lifts all bans and resets all scores.
+    pub fn clear(&mut self) {
+        self.scores.clear();
+        self.banned.clear();
+    }
+}
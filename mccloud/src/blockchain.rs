@@ -1,7 +1,6 @@
 use std::{
-    fs::{File, OpenOptions},
-    io::{BufReader, Read, Seek, SeekFrom, Write},
-    path::PathBuf,
+    future::Future,
+    sync::{Arc, RwLock},
 };
 
 use borsh::{BorshDeserialize, BorshSerialize};
@@ -13,91 +12,24 @@ use k256::{
     SecretKey,
 };
 
+pub use crate::storage::{BlockIterator, BlockStream, DataLocation, Recovery};
 use crate::{
     error::{Error, Result},
-    ex, HashBytes, PubKeyBytes, SignBytes,
+    ex,
+    storage::{self, SharedStorage, Storage},
+    HashBytes, PubKeyBytes, SignBytes,
 };
 
-#[derive(BorshDeserialize, BorshSerialize, Clone)]
-pub struct IndexEntry {
-    hash: HashBytes,
-    pos: u64,
-    size: u64,
-}
-
-pub struct IndexIterator {
-    file: BufReader<File>,
-}
-
-impl IndexIterator {
-    pub fn new(file: &PathBuf) -> Self {
-        let file = File::open(file).unwrap();
-        let file = BufReader::new(file);
-        Self { file }
-    }
-}
-
-impl Iterator for IndexIterator {
-    type Item = IndexEntry;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        // borsh::from_reader::<_, IndexEntry>(&mut self.file).ok()
-        IndexEntry::deserialize_reader(&mut self.file).ok()
-    }
-}
-
-pub struct BlockIterator {
-    file: BufReader<File>,
-    index_it: IndexIterator,
-}
+const NETWORK_LABEL: &[u8] = b"mccloud network";
 
-impl BlockIterator {
-    pub fn new(index: &PathBuf, db: &PathBuf, start: Option<HashBytes>) -> Self {
-        let file = File::open(db).unwrap();
-        let file = BufReader::new(file);
-        let mut index_it = IndexIterator::new(index);
-
-        if let Some(start) = start {
-            for i in index_it.by_ref() {
-                if i.hash == start {
-                    break;
-                }
-            }
-        }
-
-        Self { file, index_it }
-    }
-}
-
-impl Iterator for BlockIterator {
-    type Item = Result<Block>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(idx) = self.index_it.next() {
-            let mut buffer = vec![0u8; idx.size as _];
-            if let Err(e) = self.file.seek(std::io::SeekFrom::Start(idx.pos)) {
-                return Some(Err(Error::io(line!(), module_path!(), e)));
-            }
-            if let Err(e) = self.file.read_exact(&mut buffer) {
-                return Some(Err(Error::io(line!(), module_path!(), e)));
-            }
-            match zstd::stream::decode_all(buffer.as_slice()) {
-                Ok(buffer) => match borsh::from_slice(&buffer) {
-                    Ok(block) => {
-                        return Some(Ok(block));
-                    }
-                    Err(e) => {
-                        return Some(Err(Error::io(line!(), module_path!(), e)));
-                    }
-                },
-                Err(e) => {
-                    return Some(Err(Error::io(line!(), module_path!(), e)));
-                }
-            }
-        }
-
-        None
-    }
+/// Derives the id of a network from its name.
+pub fn network_id(name: &str) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(NETWORK_LABEL);
+    sha.update(name.as_bytes());
+    let mut id = [0u8; 32];
+    id.copy_from_slice(&sha.finalize());
+    id
 }
 
 #[derive(BorshDeserialize, BorshSerialize, Clone)]
@@ -110,16 +42,10 @@ pub struct Data {
 }
 
 impl Data {
-    pub fn new(data: Vec<u8>, author: &PubKeyBytes, secret: &SecretKey) -> Result<Self> {
+    /// Creates signed data. The signature is only valid in the given network.
+    pub fn new(data: Vec<u8>, author: &PubKeyBytes, secret: &SecretKey, network: &HashBytes) -> Result<Self> {
         let signer = SigningKey::from(secret);
-        let mut sha = Sha256::new();
-
-        sha.update(author);
-        sha.update(&data);
-
-        let mut hshbytes = [0u8; 32];
-        let hash = sha.finalize();
-        hshbytes.copy_from_slice(&hash);
+        let hshbytes = hash_signed(Some(network), author, &data);
 
         let sign = ex!(signer.sign_prehash(&hshbytes), encrypt);
         let signbytes = sign.to_bytes();
@@ -131,12 +57,17 @@ impl Data {
         })
     }
 
-    pub fn verify(&self) -> Result<()> {
-        let mut sha = Sha256::new();
+    pub fn verify(&self, network: &HashBytes) -> Result<()> {
+        self.verify_scheme(Some(network))
+    }
+
+    /// Verifies a signature of the first release, which is not bound to a network.
+    pub fn verify_legacy(&self) -> Result<()> {
+        self.verify_scheme(None)
+    }
 
-        sha.update(self.author);
-        sha.update(&self.data);
-        let hash = sha.finalize();
+    fn verify_scheme(&self, network: Option<&HashBytes>) -> Result<()> {
+        let hash = hash_signed(network, &self.author, &self.data);
 
         let verifier = ex!(VerifyingKey::from_bytes(&self.author[1..]), encrypt);
         let sign = ex!(Signature::try_from(&self.sign[..]), encrypt);
@@ -164,30 +95,126 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn verify(&self) -> Result<bool> {
-        let hash = hash_data(&self.parent, &self.author, &self.next_choices, &self.data);
+    /// Verifies the signature of the block and of all its data, for the given network.
+    pub fn verify(&self, network: &HashBytes) -> Result<bool> {
+        self.verify_scheme(Some(network))
+    }
+
+    /// Verifies the signatures of a block of the first release, which are not bound to a network.
+    pub fn verify_legacy(&self) -> Result<bool> {
+        self.verify_scheme(None)
+    }
+
+    fn verify_scheme(&self, network: Option<&HashBytes>) -> Result<bool> {
+        let hash = hash_data(network, &self.parent, &self.author, &self.next_choices, &self.data);
+        if hash[..] != self.hash[..] {
+            return Err(Error::wrong_block_hash(line!(), module_path!(), &self.hash));
+        }
+
         let verifier = ex!(VerifyingKey::from_bytes(&self.author[1..]), encrypt);
         let sign = ex!(Signature::try_from(&self.sign[..]), encrypt);
         ex!(verifier.verify_prehash(&hash, &sign), encrypt);
 
+        for d in self.data.iter() {
+            ex!(d.verify_scheme(network), source);
+        }
+
         Ok(true)
     }
 }
 
+/// A block whose signatures are checked, so it can be added to the chain. It is already compressed the way it is
+/// stored, so the chain is not locked for that.
+pub struct VerifiedBlock {
+    block: Block,
+    /// Whether the block is only signed without the network id.
+    legacy: bool,
+    encoded: Vec<u8>,
+}
+
+impl VerifiedBlock {
+    /// Verifies the signatures of the block for the network, and compresses it, on the blocking thread pool. With
+    /// `legacy`, a block signed without the network id is accepted too.
+    pub async fn new(blk: Block, network: HashBytes, legacy: bool) -> Result<Self> {
+        let verified = tokio::task::spawn_blocking(move || {
+            let signed_legacy = match blk.verify(&network) {
+                Ok(_) => false,
+                Err(e) if !legacy || blk.verify_legacy().is_err() => return Err(e),
+                Err(_) => true,
+            };
+            let encoded = ex!(storage::encode(&blk), source);
+
+            Ok(Self {
+                block: blk,
+                legacy: signed_legacy,
+                encoded,
+            })
+        });
+
+        Ok(ex!(ex!(verified.await, sync), source))
+    }
+
+    /// A block we created ourselves, so it is signed for our network. It is compressed on the blocking thread pool.
+    pub(crate) async fn created(block: Block) -> Result<Self> {
+        let encoded = tokio::task::spawn_blocking(move || {
+            let encoded = ex!(storage::encode(&block), source);
+
+            Ok(Self {
+                block,
+                legacy: false,
+                encoded,
+            })
+        });
+
+        Ok(ex!(ex!(encoded.await, sync), source))
+    }
+}
+
 pub struct Blockchain {
-    index_file: PathBuf,
-    db_file: PathBuf,
+    /// The id of the network, the blocks belong to.
+    pub network: HashBytes,
+    /// Read and written on the blocking thread pool, so disk access does not stall the runtime.
+    storage: SharedStorage,
     pub cache: HashMap<SignBytes, Data>,
     pub root: Option<HashBytes>,
     pub last: Option<HashBytes>,
     pub next_authors: Vec<PubKeyBytes>,
     pub count: u64,
-    block_pos: u64,
+    /// What was discarded on startup, of blocks which were not written completely.
+    pub recovery: Recovery,
+    /// Whether blocks signed without the network id are still accepted. Only with `Config::legacy_signatures`,
+    /// until the first block which is signed with the network id.
+    pub legacy: bool,
 }
 
-fn hash_data(last: &Option<HashBytes>, pubkey: &PubKeyBytes, next: &[PubKeyBytes], data: &[Data]) -> Vec<u8> {
+/// The hash which is signed for data. Signatures of the first release do not include a network.
+fn hash_signed(network: Option<&HashBytes>, author: &PubKeyBytes, data: &[u8]) -> HashBytes {
+    let mut sha = Sha256::new();
+
+    if let Some(network) = network {
+        sha.update(network);
+    }
+    sha.update(author);
+    sha.update(data);
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sha.finalize());
+    hash
+}
+
+fn hash_data(
+    network: Option<&HashBytes>,
+    last: &Option<HashBytes>,
+    pubkey: &PubKeyBytes,
+    next: &[PubKeyBytes],
+    data: &[Data],
+) -> Vec<u8> {
     let mut hsh = Sha256::new();
 
+    if let Some(network) = network {
+        hsh.update(network);
+    }
+
     if let Some(parent) = last {
         hsh.update(parent);
     }
@@ -207,80 +234,112 @@ fn hash_data(last: &Option<HashBytes>, pubkey: &PubKeyBytes, next: &[PubKeyBytes
 }
 
 impl Blockchain {
-    pub fn new(folder: &PathBuf) -> Result<Self> {
-        if !folder.exists() {
-            ex!(std::fs::create_dir_all(folder), io);
+    ///
+    /// Opens the blockchain in the storage. Fails if the stored blocks belong to another network.
+    ///
+    /// A chain of the first release, whose signatures are not bound to a network, is only opened with `legacy`.
+    ///
+    /// This reads the first and the last block, so it is best called on the blocking thread pool.
+    ///
+    pub fn new(storage: Box<dyn Storage>, network: HashBytes, legacy: bool) -> Result<Self> {
+        let meta = storage.metadata();
+        if !meta.recovery.is_empty() {
+            tracing::warn!(
+                "blockchain recovered, discarded {} blocks, {} index bytes and {} block bytes",
+                meta.recovery.blocks,
+                meta.recovery.index_bytes,
+                meta.recovery.db_bytes
+            );
         }
 
-        let index_file = folder.join("index.db");
-        let db_file = folder.join("blocks.db");
-
-        let (root, last, block_pos, cnt, next) = if index_file.exists() {
-            let mut it = IndexIterator::new(&index_file);
-
-            let mut last = it.next();
-            let root = last.as_ref().map(|n| n.hash);
-            let mut cnt = if root.is_some() { 1 } else { 0 };
-
-            for idx in it {
-                last = Some(idx);
-                cnt += 1;
+        let mut next = Vec::new();
+        let mut still_legacy = legacy;
+        if let Some(root) = ex!(storage.get_by_height(0), source) {
+            if root.verify(&network).is_err() {
+                if root.verify_legacy().is_err() {
+                    return Err(Error::wrong_network(line!(), module_path!()));
+                }
+                if !legacy {
+                    return Err(Error::legacy_chain(line!(), module_path!()));
+                }
             }
-
-            let (last, next, block_pos) = if let Some(last) = last {
-                let mut file = ex!(File::open(&db_file), io);
-                ex!(file.seek(SeekFrom::Start(last.pos)), io);
-                let mut buffer = vec![0u8; last.size as _];
-                ex!(file.read_exact(&mut buffer), io);
-                let buffer = ex!(zstd::stream::decode_all(buffer.as_slice()), io);
-                let blk: Block = borsh::from_slice(&buffer).unwrap();
-
-                (Some(last.hash), blk.next_choices, last.pos + last.size)
-            } else {
-                (None, Vec::new(), 0)
-            };
-            (root, last, block_pos, cnt, next)
-        } else {
-            ex!(
-                OpenOptions::new()
-                    .create(true)
-                    .truncate(false)
-                    .write(true)
-                    .open(&index_file),
-                io
-            );
-            ex!(
-                OpenOptions::new()
-                    .create(true)
-                    .truncate(false)
-                    .write(true)
-                    .open(&db_file),
-                io
-            );
-            (None, None, 0, 0, Vec::new())
-        };
+            if let Some(blk) = ex!(storage.get_by_height(meta.count - 1), source) {
+                still_legacy = legacy && blk.verify(&network).is_err();
+                next = blk.next_choices;
+            }
+        }
 
         Ok(Self {
-            index_file,
-            db_file,
+            network,
+            storage: Arc::new(RwLock::new(storage)),
             cache: HashMap::new(),
-            root,
-            last,
+            root: meta.root,
+            last: meta.last,
             next_authors: next,
-            count: cnt,
-            block_pos,
+            count: meta.count,
+            recovery: meta.recovery,
+            legacy: still_legacy,
         })
     }
 
-    pub fn get_blocks(&self, start: Option<[u8; 32]>) -> BlockIterator {
-        BlockIterator::new(&self.index_file, &self.db_file, start)
+    /// Returns a stream over the blocks after the start block, or over all blocks without start.
+    pub fn get_blocks(&self, start: Option<[u8; 32]>) -> BlockStream {
+        BlockStream::new(self.storage.clone(), start)
+    }
+
+    /// Returns the block at the height, the root block has height 0.
+    pub async fn get_block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        read_storage(&self.storage, move |storage| storage.get_by_height(height)).await
+    }
+
+    /// Returns the block with the hash.
+    pub async fn get_block_by_hash(&self, hash: &HashBytes) -> Result<Option<Block>> {
+        let hash = *hash;
+        read_storage(&self.storage, move |storage| {
+            match ex!(storage.height_of(&hash), source) {
+                Some(height) => storage.get_by_height(height),
+                None => Ok(None),
+            }
+        })
+        .await
+    }
+
+    /// Returns the height of the block with the hash.
+    pub async fn get_height(&self, hash: &HashBytes) -> Result<Option<u64>> {
+        let hash = *hash;
+        read_storage(&self.storage, move |storage| storage.height_of(&hash)).await
+    }
+
+    /// Checks if the block with the hash is part of the chain.
+    pub async fn contains_block(&self, hash: &HashBytes) -> Result<bool> {
+        Ok(ex!(self.get_height(hash).await, source).is_some())
+    }
+
+    /// Returns where the data with the signature is stored in the chain.
+    pub async fn find_data(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        let sign = *sign;
+        read_storage(&self.storage, move |storage| storage.find_data(&sign)).await
+    }
+
+    ///
+    /// Returns where the data of the author is stored in the chain, the newest first. Skips the first `skip` ones
+    /// and returns at most `limit`.
+    ///
+    /// Without a data index, all blocks are read.
+    ///
+    pub async fn data_by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        let author = *author;
+        read_storage(&self.storage, move |storage| {
+            storage.data_by_author(&author, skip, limit)
+        })
+        .await
     }
 
     /// Creates a new block. The block is *not* added to the block chain.
     pub fn create_block(&mut self, next: Vec<PubKeyBytes>, pubkey: PubKeyBytes, secret: &SecretKey) -> Result<Block> {
         let data: Vec<Data> = self.cache.drain().map(|(_k, v)| v).collect();
         let signer = SigningKey::from(secret);
-        let hash = hash_data(&self.last, &pubkey, &next, &data);
+        let hash = hash_data(Some(&self.network), &self.last, &pubkey, &next, &data);
 
         let mut hshbytes = [0u8; 32];
         hshbytes.copy_from_slice(&hash);
@@ -297,8 +356,24 @@ impl Blockchain {
         })
     }
 
-    /// Adds a new block to the block chain.
-    pub fn add_block(&mut self, blk: Block, force: bool) -> Result<()> {
+    /// Verifies a block for this chain. The future holds no reference to the chain, so the signatures are checked
+    /// without keeping it locked.
+    pub fn verify_block(&self, blk: Block) -> impl Future<Output = Result<VerifiedBlock>> {
+        VerifiedBlock::new(blk, self.network, self.legacy)
+    }
+
+    ///
+    /// Adds a verified block to the block chain.
+    ///
+    /// The block is written on the blocking thread pool, as compressing the block takes a while.
+    ///
+    pub async fn add_block(&mut self, blk: VerifiedBlock, force: bool) -> Result<()> {
+        let VerifiedBlock {
+            block: blk,
+            legacy: signed_legacy,
+            encoded,
+        } = blk;
+
         if self.last != blk.parent {
             return Err(Error::non_child_block(line!(), module_path!(), blk.hash));
         }
@@ -313,7 +388,18 @@ impl Blockchain {
             ));
         }
 
-        ex!(blk.verify(), source);
+        // once a block is signed with the network id, the chain no longer takes legacy blocks
+        if signed_legacy && !self.legacy {
+            ex!(blk.verify(&self.network), source);
+        }
+
+        let storage = self.storage.clone();
+        let added = tokio::task::spawn_blocking(move || {
+            ex!(ex!(storage.write(), sync).append_encoded(&blk, &encoded), source);
+
+            Ok(blk)
+        });
+        let blk = ex!(ex!(added.await, sync), source);
 
         if self.root.is_none() {
             self.root = Some(blk.hash);
@@ -324,28 +410,25 @@ impl Blockchain {
         }
 
         self.last = Some(blk.hash);
-        self.next_authors = blk.next_choices.clone();
+        self.next_authors = blk.next_choices;
         self.count += 1;
-
-        let data = ex!(borsh::to_vec(&blk), io);
-        let data = ex!(zstd::stream::encode_all(data.as_slice(), 19), io);
-        let idx = IndexEntry {
-            hash: blk.hash,
-            pos: self.block_pos,
-            size: data.len() as _,
-        };
-        self.block_pos += idx.size;
-
-        {
-            let idx_file = ex!(OpenOptions::new().append(true).open(&self.index_file), io);
-            ex!(borsh::to_writer(idx_file, &idx), io);
-        }
-
-        {
-            let mut db_file = ex!(OpenOptions::new().append(true).open(&self.db_file), io);
-            ex!(db_file.write_all(&data), io);
-        }
+        self.legacy = signed_legacy;
 
         Ok(())
     }
 }
+
+/// Runs a read of the storage on the blocking thread pool, so disk access and decompression do not stall the
+/// runtime.
+async fn read_storage<T, F>(storage: &SharedStorage, f: F) -> Result<T>
+where
+    F: FnOnce(&dyn Storage) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let storage = storage.clone();
+
+    ex!(
+        tokio::task::spawn_blocking(move || f(ex!(storage.read(), sync).as_ref())).await,
+        sync
+    )
+}
@@ -1,110 +1,552 @@
-use aes_gcm_siv::{aead::Aead, Aes256GcmSiv, KeyInit, Nonce};
+use aes_gcm_siv::{
+    aead::{Aead, Payload},
+    Aes256GcmSiv, KeyInit, Nonce,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use hashbrown::HashMap;
 use k256::{
-    ecdh::diffie_hellman,
     elliptic_curve::rand_core::{OsRng, RngCore},
     sha2::{Digest, Sha256},
-    PublicKey, SecretKey,
 };
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
-    net::tcp::{OwnedReadHalf, OwnedWriteHalf},
-    sync::Mutex,
+    select,
+    sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot, watch,
+    },
+    time,
 };
 use tokio_socks::TargetAddr;
 
 use crate::{
-    error::{Error, Result},
+    config::{Limits, Outbound, Rekey, SlowPeer},
+    error::{Error, ErrorKind, Result},
     ex,
     message::Message,
+    transport::{Channel, ReadHalf, WriteHalf},
+    version::{Capabilities, Protocol, PREAMBLE_SIZE},
     HashBytes, PubKeyBytes,
 };
 
+const REKEY_LABEL: &[u8] = b"mccloud rekey";
+
 pub struct ClientWriter {
-    pub sck: OwnedWriteHalf,
+    pub sck: WriteHalf,
     pub aes: Aes256GcmSiv,
     pub nonce: u64,
+    key: HashBytes,
+    epoch: u32,
+    rekey: Rekey,
+    sent: u64,
+    since: Instant,
 }
 
 pub struct ClientReader {
-    pub sck: OwnedReadHalf,
+    pub sck: ReadHalf,
     pub aes: Aes256GcmSiv,
     pub nonce: u64,
+    key: HashBytes,
+    epoch: u32,
+    limits: Limits,
+    established: bool,
+}
+
+/// Derives the key of the next epoch. The old key can not be recovered from the new one.
+fn next_key(key: &HashBytes) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(REKEY_LABEL);
+    sha.update(key);
+    let mut next = [0u8; 32];
+    next.copy_from_slice(&sha.finalize());
+    next
+}
+
+/// What a frame carries. It is sent in plain text in front of the frame, so the size limit can be checked before
+/// the frame is read.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrameKind {
+    Message = 0,
+    Block = 1,
+}
+
+impl FrameKind {
+    fn of(msg: &Message) -> Self {
+        if msg.is_block() {
+            Self::Block
+        } else {
+            Self::Message
+        }
+    }
+
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Message),
+            1 => Some(Self::Block),
+            _ => None,
+        }
+    }
+}
+
+/// The size of the plain text frame header: size, kind, iv and nonce.
+const FRAME_HEADER: usize = 4 + 1 + 12 + 8;
+
+/// The authenticated plain text header of a frame.
+fn associated(kind: u8, nonce: &[u8; 8]) -> [u8; 9] {
+    let mut aad = [0u8; 9];
+    aad[0] = kind;
+    aad[1..].copy_from_slice(nonce);
+    aad
+}
+
+/// An entry of the outbound queue.
+enum Outgoing {
+    Message(Arc<Message>),
+    /// Answered by the writer, once everything queued before is written.
+    Flush(oneshot::Sender<()>),
+}
+
+type Queue = mpsc::Sender<Outgoing>;
+
+/// The liveness of a connection, measured by pings.
+struct Liveness {
+    /// The nonce of the last ping and when it was sent.
+    pending: Option<(u64, Instant)>,
+    /// The smoothed round trip time.
+    rtt: Option<Duration>,
+    /// When the connection was established, or the last pong was received.
+    alive: Instant,
+}
+
+/// Who opened a connection.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    /// The other peer connected to us.
+    Inbound,
+    /// We connected to the other peer.
+    Outbound,
 }
 
 pub struct ClientInfo {
-    // pub addr: SocketAddr,
+    /// The address we dialed, or the connection came from.
+    pub addr: TargetAddr<'static>,
     pub thin: bool,
+    pub direction: Direction,
+    /// If the connection is dialed again forever.
+    pub persistent: bool,
+    /// When the connection was established.
+    pub since: Instant,
+    /// The address the peer claims to listen on.
     pub listen: TargetAddr<'static>,
     pub pubkey: PubKeyBytes,
-    pub writer: Mutex<ClientWriter>,
+    /// The negotiated protocol version.
+    pub protocol: u16,
+    /// The capabilities both sides have.
+    pub capabilities: Capabilities,
+    /// The outbound queue of the control channel.
+    queue: Queue,
+    /// The outbound queues of the additional channels, if the transport multiplexes.
+    channels: HashMap<Channel, Queue>,
+    outbound: Outbound,
+    closed: Arc<watch::Sender<bool>>,
+    liveness: Mutex<Liveness>,
+    /// How many `Addresses` the peer may still send: one after the greeting, and one per `RequestAddresses`.
+    addresses: AtomicU32,
+    /// The block count the peer advertised when we requested its blocks, zero if we did not request them.
+    requested: AtomicU64,
+    /// The configured address of a persistent dial, which lost against this connection.
+    adopted: Mutex<Option<TargetAddr<'static>>>,
 }
 
 impl ClientInfo {
-    pub async fn write(&self, msg: &Message) -> Result<()> {
-        let mut w = self.writer.lock().await;
-        w.write(msg).await
+    /// Creates the client and spawns a writer task for each channel, which sends the queued messages.
+    pub fn new(
+        (thin, direction, persistent): (bool, Direction, bool),
+        (addr, listen): (TargetAddr<'static>, TargetAddr<'static>),
+        pubkey: PubKeyBytes,
+        writer: ClientWriter,
+        channels: Vec<(Channel, ClientWriter)>,
+        outbound: Outbound,
+        (protocol, capabilities): (u16, Capabilities),
+    ) -> Self {
+        let closed = Arc::new(watch::Sender::new(false));
+        let queue = spawn_writer(writer, outbound.capacity, closed.clone());
+        let channels = channels
+            .into_iter()
+            .map(|(channel, w)| (channel, spawn_writer(w, outbound.capacity, closed.clone())))
+            .collect();
+
+        Self {
+            addr,
+            thin,
+            direction,
+            persistent,
+            since: Instant::now(),
+            listen,
+            pubkey,
+            protocol,
+            capabilities,
+            queue,
+            channels,
+            outbound,
+            closed,
+            liveness: Mutex::new(Liveness {
+                pending: None,
+                rtt: None,
+                alive: Instant::now(),
+            }),
+            addresses: AtomicU32::new(1),
+            requested: AtomicU64::new(0),
+            adopted: Mutex::new(None),
+        }
+    }
+
+    fn queue_of(&self, msg: &Message) -> &Queue {
+        self.channels.get(&msg.channel()).unwrap_or(&self.queue)
+    }
+
+    /// Returns true if the peer understands the message.
+    pub fn supports(&self, msg: &Message) -> bool {
+        self.capabilities.contains(msg.capabilities())
+    }
+
+    /// Queues a message and waits until the queue has room for it. Messages the peer does not understand are
+    /// skipped.
+    pub async fn write(&self, msg: Message) -> Result<()> {
+        if !self.supports(&msg) {
+            return Ok(());
+        }
+
+        let request = matches!(msg, Message::RequestAddresses { .. });
+        let queue = self.queue_of(&msg);
+        if queue.send(Outgoing::Message(Arc::new(msg))).await.is_err() {
+            return Err(Error::disconnected(line!(), module_path!()));
+        }
+        if request {
+            self.addresses_requested();
+        }
+
+        Ok(())
+    }
+
+    /// Queues a message without waiting. If the queue is full, the slow peer policy is applied.
+    pub fn try_write(&self, msg: Arc<Message>) -> Result<()> {
+        if !self.supports(&msg) {
+            return Ok(());
+        }
+
+        let request = matches!(*msg, Message::RequestAddresses { .. });
+        match self.queue_of(&msg).try_send(Outgoing::Message(msg)) {
+            Ok(()) => {
+                if request {
+                    self.addresses_requested();
+                }
+                Ok(())
+            }
+            Err(TrySendError::Full(_)) => {
+                if self.outbound.slow_peer == SlowPeer::Disconnect {
+                    self.close();
+                }
+                Err(Error::queue_full(line!(), module_path!(), self.outbound.capacity))
+            }
+            Err(TrySendError::Closed(_)) => Err(Error::disconnected(line!(), module_path!())),
+        }
+    }
+
+    /// Remembers the nonce of a new ping. Returns false, if the last ping is still unanswered.
+    pub fn ping_sent(&self, nonce: u64) -> bool {
+        let mut l = self.liveness.lock().unwrap();
+        if l.pending.is_some() {
+            return false;
+        }
+        l.pending = Some((nonce, Instant::now()));
+        true
+    }
+
+    /// Updates the round trip time, if the pong answers the last ping. Returns false for an unexpected pong.
+    pub fn pong_received(&self, nonce: u64) -> bool {
+        let mut l = self.liveness.lock().unwrap();
+        match l.pending {
+            Some((pending, sent)) if pending == nonce => {
+                let sample = sent.elapsed();
+                // smoothed like the tcp round trip time
+                l.rtt = Some(l.rtt.map(|rtt| (rtt * 7 + sample) / 8).unwrap_or(sample));
+                l.pending = None;
+                l.alive = Instant::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Allows the peer to answer our `RequestAddresses`. Unanswered requests do not add up.
+    fn addresses_requested(&self) {
+        let _ = self
+            .addresses
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some((n + 1).min(2)));
+    }
+
+    /// Returns true if the peer may send `Addresses` now, and counts them. Otherwise they were not asked for.
+    pub fn addresses_received(&self) -> bool {
+        self.addresses
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    /// Remembers that we requested the blocks of the peer, which advertised `count` blocks.
+    pub fn blocks_requested(&self, count: u64) {
+        self.requested.store(count, Ordering::Relaxed);
+    }
+
+    /// Returns the block count the peer advertised, if we requested its blocks. Otherwise they were not asked for.
+    pub fn requested_blocks(&self) -> Option<u64> {
+        Some(self.requested.load(Ordering::Relaxed)).filter(|n| *n > 0)
+    }
+
+    /// Takes over the persistent dial of `addr`, which was closed in favour of this connection.
+    pub fn adopt_persistent(&self, addr: TargetAddr<'static>) {
+        *self.adopted.lock().unwrap() = Some(addr);
+    }
+
+    /// Returns the address which is dialed forever when the connection is lost: the configured address of a
+    /// persistent peer, even if the connection was opened by the other side.
+    pub fn persistent_addr(&self) -> Option<TargetAddr<'static>> {
+        if self.persistent {
+            Some(self.addr.to_owned())
+        } else {
+            self.adopted.lock().unwrap().as_ref().map(|a| a.to_owned())
+        }
+    }
+
+    /// Returns the smoothed round trip time, once a ping was answered.
+    pub fn rtt(&self) -> Option<Duration> {
+        self.liveness.lock().unwrap().rtt
+    }
+
+    /// Returns how long the peer has not answered a ping.
+    pub fn silent_for(&self) -> Duration {
+        self.liveness.lock().unwrap().alive.elapsed()
+    }
+
+    /// Queues a last message and waits until it is written, at most for the given time.
+    pub async fn finish(&self, msg: Message, timeout: Duration) {
+        let queue = self.queue_of(&msg).clone();
+        let (tx, rx) = oneshot::channel();
+
+        let flushed = async {
+            if queue.send(Outgoing::Message(Arc::new(msg))).await.is_ok()
+                && queue.send(Outgoing::Flush(tx)).await.is_ok()
+            {
+                let _ = rx.await;
+            }
+        };
+        let _ = time::timeout(timeout, flushed).await;
+    }
+
+    /// Closes the connection.
+    pub fn close(&self) {
+        self.closed.send_replace(true);
+    }
+
+    /// Waits until the connection is closed.
+    pub async fn closed(&self) {
+        let _ = self.closed.subscribe().wait_for(|c| *c).await;
     }
 }
 
-pub fn shared_secret(pubkey: &PubKeyBytes, private_key: &SecretKey) -> HashBytes {
-    let pubkey = PublicKey::from_sec1_bytes(pubkey).unwrap();
-    let mut shared = Sha256::new();
-    shared.update(diffie_hellman(private_key.to_nonzero_scalar(), pubkey.as_affine()).raw_secret_bytes());
-    let shared = shared.finalize().to_vec();
-    let mut buf: [u8; 32] = [0u8; 32];
-    buf.copy_from_slice(&shared);
-    buf
+/// The writer task stops, if the queue is dropped or the connection closed, even in the middle of a stalled write.
+fn spawn_writer(mut writer: ClientWriter, capacity: usize, closed: Arc<watch::Sender<bool>>) -> Queue {
+    let (tx, mut rx) = mpsc::channel::<Outgoing>(capacity);
+    let mut closed_rx = closed.subscribe();
+
+    tokio::spawn(async move {
+        let sending = async {
+            while let Some(out) = rx.recv().await {
+                let msg = match out {
+                    Outgoing::Message(msg) => msg,
+                    Outgoing::Flush(done) => {
+                        let _ = done.send(());
+                        continue;
+                    }
+                };
+                if let Err(e) = writer.write(&msg).await {
+                    if e.kind != ErrorKind::Disconnect {
+                        tracing::error!("{e}");
+                    }
+                    closed.send_replace(true);
+                    break;
+                }
+            }
+        };
+
+        select! {
+            _ = sending => {}
+            _ = closed_rx.wait_for(|c| *c) => {}
+        }
+    });
+
+    tx
 }
 
 impl ClientWriter {
-    pub fn new(sck: OwnedWriteHalf, shared: &HashBytes) -> Result<Self> {
+    pub fn new(sck: WriteHalf, shared: &HashBytes, rekey: Rekey) -> Result<Self> {
         let aes = ex!(Aes256GcmSiv::new_from_slice(shared), encrypt);
-        Ok(Self { sck, aes, nonce: 1 })
+        Ok(Self {
+            sck,
+            aes,
+            nonce: 1,
+            key: *shared,
+            epoch: 0,
+            rekey,
+            sent: 0,
+            since: Instant::now(),
+        })
+    }
+
+    /// Never renews the session key. For peers without the rekey capability.
+    pub fn disable_rekey(&mut self) {
+        self.rekey = Rekey {
+            messages: u64::MAX,
+            time: Duration::MAX,
+        };
     }
 
-    pub async fn write_greeting(sck: &mut OwnedWriteHalf, msg: &Message) -> Result<()> {
+    /// Writes the preamble with the protocol versions we speak. It is the first thing sent on a connection.
+    pub async fn write_preamble(sck: &mut WriteHalf, protocol: &Protocol) -> Result<()> {
+        ex!(sck.write_all(&protocol.preamble()).await, io);
+
+        Ok(())
+    }
+
+    /// Writes an unencrypted message. Only used for the handshake.
+    pub async fn write_handshake(sck: &mut WriteHalf, msg: &Message) -> Result<()> {
         let data = ex!(borsh::to_vec(msg), io);
         let size = (data.len() as u32).to_le_bytes();
-        ex!(sck.write(&size).await, io);
+        ex!(sck.write_all(&size).await, io);
         ex!(sck.write_all(&data).await, io);
 
         Ok(())
     }
 
     pub async fn write(&mut self, msg: &Message) -> Result<()> {
+        if self.sent >= self.rekey.messages || self.since.elapsed() >= self.rekey.time {
+            ex!(self.rekey().await, source);
+        }
+        self.sent += 1;
+
+        self.write_frame(msg).await
+    }
+
+    /// Announces the next key epoch to the reader on the other side and switches to the new key.
+    async fn rekey(&mut self) -> Result<()> {
+        let epoch = self.epoch + 1;
+        ex!(self.write_frame(&Message::Rekey { epoch }).await, source);
+
+        self.key = next_key(&self.key);
+        self.aes = ex!(Aes256GcmSiv::new_from_slice(&self.key), encrypt);
+        self.epoch = epoch;
+        self.sent = 0;
+        self.since = Instant::now();
+
+        Ok(())
+    }
+
+    async fn write_frame(&mut self, msg: &Message) -> Result<()> {
         let data = ex!(borsh::to_vec(msg), io);
 
         let mut iv = [0u8; 12];
         OsRng.fill_bytes(&mut iv);
         let iv = Nonce::from_slice(&iv);
 
+        let kind = FrameKind::of(msg) as u8;
         let nonce = self.nonce.to_le_bytes();
         self.nonce += 1;
 
-        let encrypted = ex!(self.aes.encrypt(&iv, data.as_ref()), encrypt);
+        // the kind and nonce are sent in plain text, but authenticated
+        let encrypted = ex!(
+            self.aes.encrypt(
+                iv,
+                Payload {
+                    msg: &data,
+                    aad: &associated(kind, &nonce)
+                }
+            ),
+            encrypt
+        );
 
-        let size = (encrypted.len() as u32).to_le_bytes();
-        ex!(self.sck.write(&size).await, io);
-        ex!(self.sck.write(&iv).await, io);
-        ex!(self.sck.write(&nonce).await, io);
-        ex!(self.sck.write_all(&encrypted).await, io);
+        // one write per frame, the socket is not buffered
+        let mut frame = Vec::with_capacity(FRAME_HEADER + encrypted.len());
+        frame.extend((encrypted.len() as u32).to_le_bytes());
+        frame.push(kind);
+        frame.extend(iv);
+        frame.extend(nonce);
+        frame.extend(encrypted);
+        ex!(self.sck.write_all(&frame).await, io);
 
         Ok(())
     }
 }
 
 impl ClientReader {
-    pub fn new(sck: OwnedReadHalf, shared: &HashBytes) -> Result<Self> {
+    pub fn new(sck: ReadHalf, shared: &HashBytes, limits: Limits) -> Result<Self> {
         let aes = ex!(Aes256GcmSiv::new_from_slice(shared), encrypt);
-        Ok(Self { sck, aes, nonce: 0 })
+        Ok(Self {
+            sck,
+            aes,
+            nonce: 0,
+            key: *shared,
+            epoch: 0,
+            limits,
+            established: false,
+        })
+    }
+
+    /// Marks the greeting as done. From now on the data and block limits and the idle timeout apply.
+    pub fn established(&mut self) {
+        self.established = true;
+    }
+
+    fn max_frame(&self, kind: FrameKind) -> u32 {
+        match (self.established, kind) {
+            (false, _) => self.limits.greeting,
+            (true, FrameKind::Message) => self.limits.data,
+            (true, FrameKind::Block) => self.limits.block,
+        }
     }
 
-    pub async fn read_greeting(sck: &mut OwnedReadHalf) -> Result<Message> {
+    /// Reads the preamble with the protocol versions the other side speaks.
+    pub async fn read_preamble(sck: &mut ReadHalf) -> Result<Protocol> {
+        let mut bytes = [0u8; PREAMBLE_SIZE];
+        ex!(sck.read_exact(&mut bytes).await, io);
+
+        match Protocol::from_preamble(&bytes) {
+            Some(protocol) => Ok(protocol),
+            None => Err(Error::protocol(
+                line!(),
+                module_path!(),
+                "no mccloud preamble, the peer is older than protocol version 4",
+            )),
+        }
+    }
+
+    /// Reads an unencrypted message. Only used for the handshake.
+    pub async fn read_handshake(sck: &mut ReadHalf, max: u32) -> Result<Message> {
         let mut size_bytes = [0u8; 4];
 
         ex!(sck.read_exact(&mut size_bytes).await, io);
         let size = u32::from_le_bytes(size_bytes);
 
+        if size > max {
+            return Err(Error::oversized(line!(), module_path!(), size, max));
+        }
+
         let mut data = vec![0u8; size as usize];
         ex!(sck.read_exact(&mut data).await, io);
 
@@ -112,11 +554,52 @@ impl ClientReader {
     }
 
     pub async fn read(&mut self) -> Result<Message> {
+        loop {
+            let frame = if self.established {
+                ex!(
+                    ex!(
+                        time::timeout(self.limits.idle_timeout, self.read_frame()).await,
+                        timeout
+                    ),
+                    source
+                )
+            } else {
+                ex!(self.read_frame().await, source)
+            };
+
+            match frame {
+                Message::Rekey { epoch } => {
+                    if epoch != self.epoch + 1 {
+                        return Err(Error::protocol(line!(), module_path!(), "unexpected rekey epoch"));
+                    }
+
+                    self.key = next_key(&self.key);
+                    self.aes = ex!(Aes256GcmSiv::new_from_slice(&self.key), encrypt);
+                    self.epoch = epoch;
+                }
+                msg => return Ok(msg),
+            }
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<Message> {
         let mut size_bytes = [0u8; 4];
 
         ex!(self.sck.read_exact(&mut size_bytes).await, io);
         let size = u32::from_le_bytes(size_bytes);
 
+        let mut kind = [0u8; 1];
+        ex!(self.sck.read_exact(&mut kind).await, io);
+        let Some(frame_kind) = FrameKind::from_tag(kind[0]) else {
+            return Err(Error::protocol(line!(), module_path!(), "unknown frame kind"));
+        };
+
+        // the limit is checked before anything of the frame is allocated
+        let max = self.max_frame(frame_kind);
+        if size > max {
+            return Err(Error::oversized(line!(), module_path!(), size, max));
+        }
+
         let mut iv = [0u8; 12];
         ex!(self.sck.read_exact(&mut iv).await, io);
 
@@ -124,17 +607,33 @@ impl ClientReader {
         ex!(self.sck.read_exact(&mut nonce_bytes).await, io);
         let nonce = u64::from_le_bytes(nonce_bytes);
 
-        let mut data = vec![0u8; size as usize];
-        ex!(self.sck.read_exact(&mut data).await, io);
-
         if self.nonce >= nonce {
             return Err(Error::protocol(line!(), module_path!(), "nonce to low"));
         }
 
+        let mut data = vec![0u8; size as usize];
+        ex!(self.sck.read_exact(&mut data).await, io);
+
+        let plain = ex!(
+            self.aes.decrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: &data,
+                    aad: &associated(kind[0], &nonce_bytes)
+                }
+            ),
+            encrypt
+        );
         self.nonce = nonce;
 
-        let plain = ex!(self.aes.decrypt(Nonce::from_slice(&iv), data.as_ref()), encrypt);
+        // The frame passed authentication, so the sender encoded it badly
+        let Ok(msg) = borsh::from_slice::<Message>(&plain) else {
+            return Err(Error::protocol(line!(), module_path!(), "message can not be decoded"));
+        };
+        if FrameKind::of(&msg) != frame_kind {
+            return Err(Error::protocol(line!(), module_path!(), "frame kind does not match"));
+        }
 
-        Ok(ex!(borsh::from_slice(&plain), io))
+        Ok(msg)
     }
 }
@@ -1,8 +1,14 @@
-use std::{net::SocketAddr, path::PathBuf, time::Duration};
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    path::PathBuf,
+    time::Duration,
+};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::PubKeyBytes;
+
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Relationship {
@@ -10,10 +16,198 @@ pub struct Relationship {
     pub count: u32,
     /// In which time intervals to look for new connections.
     pub time: Duration,
-    /// The time interval of attempted reconnects.
+    /// The delay before the first reconnect. It is doubled for each failed attempt.
     pub reconnect: Duration,
+    /// The longest delay between two reconnects.
+    pub max_reconnect: Duration,
     /// How often to retry, after an already established connection is lost.
     pub retry: u32,
+    /// How long a lost neighbour may be away, before its leave is sent to the network.
+    pub leave_after: Duration,
+    /// The addresses of peers, which are connected on startup and are retried forever.
+    pub persistent: Vec<String>,
+    /// How many of the longest connected outbound peers are kept as anchors, and connected first after a restart.
+    pub anchors: usize,
+    /// How often one outbound connection, which is neither an anchor nor persistent, is replaced by a new peer.
+    pub rotate: Duration,
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Bootstrap {
+    /// The addresses of well known peers. They are connected on startup, and again while there are no connections.
+    pub seeds: Vec<String>,
+    /// How long to wait, before the seeds are tried again.
+    pub retry: Duration,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Discovery {
+    /// The multicast group and port, the beacons are sent to.
+    pub group: SocketAddrV4,
+    /// The address of the local interface to use. Unspecified lets the system choose.
+    pub interface: Ipv4Addr,
+    /// How often this peer sends its beacon.
+    pub interval: Duration,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 29, 92), 29092),
+            interface: Ipv4Addr::UNSPECIFIED,
+            interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rekey {
+    /// After how many sent messages the session key of a connection is renewed.
+    pub messages: u64,
+    /// After which time the session key of a connection is renewed.
+    pub time: Duration,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Limits {
+    /// The maximum frame size in bytes of the handshake and the greeting.
+    pub greeting: u32,
+    /// The maximum frame size in bytes of shared data and all other non block messages.
+    pub data: u32,
+    /// The maximum frame size in bytes of a shared or requested block.
+    pub block: u32,
+    /// How long the handshake and greeting of a new connection may take.
+    pub handshake_timeout: Duration,
+    /// How long a connection may not receive anything, before it is dropped.
+    pub idle_timeout: Duration,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Connections {
+    /// How many connections other peers may open to us. If full, a new one evicts the inbound connection of the
+    /// peer with the lowest score, then the highest round trip time, then the shortest connection time.
+    pub inbound: usize,
+    /// How many connections we open to other peers. Connections to persistent peers are always opened.
+    pub outbound: usize,
+    /// How many connections there may be in both directions.
+    pub total: usize,
+    /// How many handshakes of inbound connections may run at the same time. Further connections are dropped
+    /// before the handshake.
+    pub handshakes: usize,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Keepalive {
+    /// How often each connection is pinged.
+    pub interval: Duration,
+    /// After which time without a pong a connection is considered dead and dropped.
+    pub timeout: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SlowPeer {
+    /// Broadcasted messages are dropped for this peer, until its queue has room again.
+    Drop,
+    /// The connection to the peer is closed.
+    Disconnect,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Outbound {
+    /// How many messages may wait to be sent to a single peer.
+    pub capacity: usize,
+    /// What to do if a broadcast finds the queue of a peer full.
+    pub slow_peer: SlowPeer,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scoring {
+    /// The score every peer starts with. A peer which reaches zero is disconnected and banned.
+    pub score: u32,
+    /// The penalty for protocol violations.
+    pub protocol: u32,
+    /// The penalty for invalid signatures and messages which can not be decrypted.
+    pub encryption: u32,
+    /// The penalty for invalid blocks.
+    pub blockchain: u32,
+    /// The penalty for exceeded limits.
+    pub limit: u32,
+    /// How long it takes to regain one point of the score.
+    pub regain: Duration,
+    /// How long a ban lasts.
+    pub ban_time: Duration,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Membership {
+    /// How often a peer renews its signed announcement.
+    pub refresh: Duration,
+    /// How old an announcement or leave may get. Peers which did not renew their announcement in this time are
+    /// considered offline.
+    pub max_age: Duration,
+    /// How far the timestamp of a record may be ahead of our clock.
+    pub max_skew: Duration,
+}
+
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Addresses {
+    /// How many peers the address book holds at most.
+    pub capacity: usize,
+    /// After how many failed connection attempts in a row a peer is removed from the address book.
+    pub max_failures: u32,
+    /// How many address records are exchanged with a new connection, or on request.
+    pub share: u32,
+    /// How many new records a single peer may add to the address book.
+    pub per_source: usize,
+    /// How long ago a peer may have been seen, to be passed on to other peers.
+    pub max_age: Duration,
+}
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Access {
+    /// If true, only peers in the allow list may connect and author data and blocks.
+    pub permissioned: bool,
+    /// The peers which are allowed in permissioned mode.
+    #[cfg_attr(feature = "serde", serde(with = "hex_pubkeys"))]
+    pub allow: Vec<PubKeyBytes>,
+    /// The peers which are never accepted, even if they are in the allow list.
+    #[cfg_attr(feature = "serde", serde(with = "hex_pubkeys"))]
+    pub deny: Vec<PubKeyBytes>,
+}
+
+/// Serializes public keys as hex strings, as serde has no support for arrays of 33 bytes.
+#[cfg(feature = "serde")]
+mod hex_pubkeys {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::PubKeyBytes;
+
+    pub fn serialize<S: Serializer>(keys: &[PubKeyBytes], s: S) -> Result<S::Ok, S::Error> {
+        s.collect_seq(keys.iter().map(hex::encode))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<PubKeyBytes>, D::Error> {
+        let keys: Vec<String> = Vec::deserialize(d)?;
+        keys.iter()
+            .map(|k| {
+                let mut key: PubKeyBytes = [0u8; 33];
+                hex::decode_to_slice(k, &mut key).map_err(D::Error::custom)?;
+                Ok(key)
+            })
+            .collect()
+    }
 }
 
 #[derive(Clone)]
@@ -25,6 +219,34 @@ pub struct Proxy {
     pub announce_by: String,
 }
 
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TransportKind {
+    /// TCP on `Config::addr`, via the socks proxy if one is set.
+    Tcp,
+    /// A unix domain socket, for co-located services.
+    #[cfg(unix)]
+    Unix { path: PathBuf },
+    /// An in process transport, mostly used for tests.
+    Memory { name: String },
+    /// QUIC on `Config::addr`, with separate streams for control, gossip and block sync.
+    #[cfg(feature = "quic")]
+    Quic,
+}
+
+/// Where the blockchain is kept.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StorageKind {
+    /// Flat files in `Config::folder`.
+    File,
+    /// Only in memory, for tests and ephemeral thin clients. The blockchain is lost on shutdown.
+    Memory,
+    /// An embedded redb database in `Config::folder`.
+    #[cfg(feature = "redb")]
+    Redb,
+}
+
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Algorithm {
@@ -43,34 +265,134 @@ pub enum Algorithm {
 #[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Config {
+    /// The name of the network. Peers of different networks do not connect to each other, and data and blocks
+    /// of one network are not valid in another one.
+    pub network: String,
+    /// Accepts a blockchain of the first release, whose signatures are not bound to a network. Its blocks are
+    /// valid in any network, so they are only accepted until the first block signed with the network id.
+    pub legacy_signatures: bool,
     /// The address the peer is listening on.
     pub addr: SocketAddr,
     /// A socks proxy. Mostly used via TOR.
     pub proxy: Option<Proxy>,
-    /// The data folder where to save the blockchain.
+    /// How to reach other peers.
+    pub transport: TransportKind,
+    /// The data folder where to save the blockchain and the node key.
     pub folder: PathBuf,
+    /// Where the blockchain is kept.
+    pub storage: StorageKind,
+    /// The secret key of this node. If not set, the key is loaded from the data folder, or created there on the
+    /// first start.
+    pub key: Option<[u8; 32]>,
     /// How long to gather new data until a new block is generated.
     pub data_gather_time: Duration,
     /// A thin node does not participate in generating new blocks.
     pub thin: bool,
+    /// Indexes the data in the blockchain by author and signature, to find it without reading all blocks.
+    pub index_data: bool,
     /// The relationship config to other nodes.
     pub relationship: Relationship,
+    /// The peers to join the network by.
+    pub bootstrap: Bootstrap,
+    /// If set, other peers in the local network are found via udp multicast. Only for the ip based transports.
+    pub discovery: Option<Discovery>,
+    /// When to renew the session keys of long lived connections.
+    pub rekey: Rekey,
+    /// Limits to protect against peers, which try to exhaust our resources.
+    pub limits: Limits,
+    /// How many connections are accepted and opened.
+    pub connections: Connections,
+    /// How dead connections are detected and the round trip times measured.
+    pub keepalive: Keepalive,
+    /// The outbound queue of each connection.
+    pub outbound: Outbound,
+    /// The address book of other peers, saved in the data folder.
+    pub addresses: Addresses,
+    /// How announcements and leaves of peers are checked.
+    pub membership: Membership,
+    /// Which peers may participate.
+    pub access: Access,
+    /// How misbehaving peers are punished.
+    pub scoring: Scoring,
     pub algorithm: Algorithm,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            network: "mccloud".into(),
+            legacy_signatures: false,
             addr: ([0, 0, 0, 0], 29092).into(),
             proxy: None,
+            transport: TransportKind::Tcp,
             folder: "data".into(),
+            storage: StorageKind::File,
+            key: None,
             data_gather_time: Duration::from_millis(750),
             thin: false,
+            index_data: false,
             relationship: Relationship {
                 time: Duration::from_secs(10),
-                reconnect: Duration::from_secs(15),
+                reconnect: Duration::from_secs(1),
+                max_reconnect: Duration::from_secs(5 * 60),
                 count: 3,
                 retry: 3,
+                leave_after: Duration::from_secs(30),
+                persistent: Vec::new(),
+                anchors: 2,
+                rotate: Duration::from_secs(20 * 60),
+            },
+            bootstrap: Bootstrap {
+                seeds: Vec::new(),
+                retry: Duration::from_secs(30),
+            },
+            discovery: None,
+            rekey: Rekey {
+                messages: 1 << 20,
+                time: Duration::from_secs(60 * 60),
+            },
+            limits: Limits {
+                greeting: 1024 * 1024,
+                data: 1024 * 1024,
+                block: 64 * 1024 * 1024,
+                handshake_timeout: Duration::from_secs(10),
+                idle_timeout: Duration::from_secs(10 * 60),
+            },
+            connections: Connections {
+                inbound: 64,
+                outbound: 16,
+                total: 72,
+                handshakes: 16,
+            },
+            keepalive: Keepalive {
+                interval: Duration::from_secs(30),
+                timeout: Duration::from_secs(90),
+            },
+            outbound: Outbound {
+                capacity: 1024,
+                slow_peer: SlowPeer::Disconnect,
+            },
+            addresses: Addresses {
+                capacity: 1024,
+                max_failures: 5,
+                share: 32,
+                per_source: 128,
+                max_age: Duration::from_secs(3 * 60 * 60),
+            },
+            membership: Membership {
+                refresh: Duration::from_secs(10 * 60),
+                max_age: Duration::from_secs(30 * 60),
+                max_skew: Duration::from_secs(60),
+            },
+            access: Access::default(),
+            scoring: Scoring {
+                score: 100,
+                protocol: 20,
+                encryption: 50,
+                blockchain: 2,
+                limit: 50,
+                regain: Duration::from_secs(60),
+                ban_time: Duration::from_secs(60 * 60),
             },
             algorithm: Algorithm::Riddle {
                 next_candidates: 3,
@@ -0,0 +1,96 @@
+use std::net::{Ipv4Addr, SocketAddr};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use k256::{
+    ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier},
+    schnorr::{Signature, SigningKey, VerifyingKey},
+    sha2::{Digest, Sha256},
+    SecretKey,
+};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::{
+    config::Discovery,
+    error::{Error, Result},
+    ex,
+    membership::now,
+    HashBytes, PubKeyBytes, SignBytes,
+};
+
+const BEACON_LABEL: &[u8] = b"mccloud beacon";
+
+/// How far the timestamp of a beacon may be off, in milliseconds.
+const MAX_SKEW: u64 = 60_000;
+
+/// What a peer sends to the multicast group, so others in the local network can connect to it.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Beacon {
+    pub network: HashBytes,
+    pub pubkey: PubKeyBytes,
+    /// The port the peer listens on. The address is the one the beacon came from.
+    pub port: u16,
+    /// When the beacon was signed, in unix milliseconds.
+    pub timestamp: u64,
+    pub sign: SignBytes,
+}
+
+impl Beacon {
+    /// Creates the beacon of a peer, signed by itself.
+    pub fn new(network: &HashBytes, pubkey: &PubKeyBytes, port: u16, secret: &SecretKey) -> Result<Self> {
+        let mut beacon = Self {
+            network: *network,
+            pubkey: *pubkey,
+            port,
+            timestamp: now(),
+            sign: [0u8; 64],
+        };
+
+        let signing = SigningKey::from(secret);
+        let sign = ex!(signing.sign_prehash(&beacon.hash()), encrypt);
+        beacon.sign = sign.to_bytes();
+
+        Ok(beacon)
+    }
+
+    fn hash(&self) -> HashBytes {
+        let mut sha = Sha256::new();
+        sha.update(BEACON_LABEL);
+        sha.update(self.network);
+        sha.update(self.pubkey);
+        sha.update(self.port.to_le_bytes());
+        sha.update(self.timestamp.to_le_bytes());
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&sha.finalize());
+        hash
+    }
+
+    /// Verifies that the beacon is recent and signed by the peer it names.
+    pub fn verify(&self) -> Result<()> {
+        if self.timestamp.abs_diff(now()) > MAX_SKEW {
+            return Err(Error::protocol(line!(), module_path!(), "beacon is outdated"));
+        }
+
+        let verifier = ex!(VerifyingKey::from_bytes(&self.pubkey[1..]), encrypt);
+        let sign = ex!(Signature::try_from(&self.sign[..]), encrypt);
+        ex!(verifier.verify_prehash(&self.hash(), &sign), encrypt);
+
+        Ok(())
+    }
+}
+
+/// Opens a udp socket, which is a member of the multicast group. Several peers on one host share the port.
+pub fn socket(cfg: &Discovery) -> Result<UdpSocket> {
+    let sck = ex!(Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)), io);
+    ex!(sck.set_reuse_address(true), io);
+    ex!(sck.set_nonblocking(true), io);
+
+    let bind = SocketAddr::from((Ipv4Addr::UNSPECIFIED, cfg.group.port()));
+    ex!(sck.bind(&bind.into()), io);
+    ex!(sck.join_multicast_v4(cfg.group.ip(), &cfg.interface), io);
+    ex!(sck.set_multicast_if_v4(&cfg.interface), io);
+    ex!(sck.set_multicast_loop_v4(true), io);
+
+    Ok(ex!(UdpSocket::from_std(sck.into()), io))
+}
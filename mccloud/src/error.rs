@@ -13,6 +13,12 @@ pub enum ErrorKind {
     Encryption,
     Blockchain,
     Protocol,
+    /// The peer sent a frame which is too big.
+    Limit,
+    /// The peer was idle or stalled too long.
+    Timeout,
+    /// The peer is not allowed to participate.
+    Denied,
     Extern,
 }
 
@@ -58,7 +64,11 @@ impl Error {
 
     pub fn io(line: u32, module: &str, e: std::io::Error) -> Self {
         let kind = match e.kind() {
-            std::io::ErrorKind::UnexpectedEof | std::io::ErrorKind::BrokenPipe => ErrorKind::Disconnect,
+            std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::BrokenPipe
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::NotConnected => ErrorKind::Disconnect,
             _ => ErrorKind::Io,
         };
         Self {
@@ -80,6 +90,16 @@ impl Error {
         }
     }
 
+    pub fn storage<E: Display>(line: u32, module: &str, e: E) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Io,
+            line,
+            module: module.into(),
+            msg: Some(e.to_string()),
+        }
+    }
+
     pub fn encrypt<E: Display>(line: u32, module: &str, e: E) -> Self {
         Self {
             source: None,
@@ -100,6 +120,69 @@ impl Error {
         }
     }
 
+    pub fn wrong_block_hash(line: u32, module: &str, hsh: &HashBytes) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Blockchain,
+            line,
+            module: module.into(),
+            msg: Some(format!("block ({}) does not match its hash", hex::encode(hsh))),
+        }
+    }
+
+    pub fn corrupted_block(line: u32, module: &str, hsh: &HashBytes) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Io,
+            line,
+            module: module.into(),
+            msg: Some(format!("stored block ({}) is corrupted", hex::encode(hsh))),
+        }
+    }
+
+    pub fn corrupted_index(line: u32, module: &str, file: &str) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Io,
+            line,
+            module: module.into(),
+            msg: Some(format!("index {} is corrupted", file)),
+        }
+    }
+
+    pub fn unknown_index_version(line: u32, module: &str, file: &str, version: u64) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Io,
+            line,
+            module: module.into(),
+            msg: Some(format!(
+                "index {} has the format version {}, which this version can not read",
+                file, version
+            )),
+        }
+    }
+
+    pub fn legacy_chain(line: u32, module: &str) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Blockchain,
+            line,
+            module: module.into(),
+            msg: Some("the blockchain is signed without network id, it is only opened with legacy signatures".into()),
+        }
+    }
+
+    pub fn wrong_network(line: u32, module: &str) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Blockchain,
+            line,
+            module: module.into(),
+            msg: Some("the blockchain belongs to another network".into()),
+        }
+    }
+
     pub fn unexpected_block_author(
         line: u32,
         module: &str,
@@ -123,6 +206,76 @@ impl Error {
         }
     }
 
+    pub fn oversized(line: u32, module: &str, size: u32, max: u32) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Limit,
+            line,
+            module: module.into(),
+            msg: Some(format!("frame of {size} bytes exceeds the limit of {max} bytes")),
+        }
+    }
+
+    pub fn denied(line: u32, module: &str, pubkey: &PubKeyBytes) -> Self {
+        Self {
+            kind: ErrorKind::Denied,
+            source: None,
+            line,
+            module: module.into(),
+            msg: Some(format!("peer {} is not allowed", hex::encode(pubkey))),
+        }
+    }
+
+    pub fn banned(line: u32, module: &str, what: String) -> Self {
+        Self {
+            kind: ErrorKind::Denied,
+            source: None,
+            line,
+            module: module.into(),
+            msg: Some(format!("{what} is banned")),
+        }
+    }
+
+    pub fn queue_full(line: u32, module: &str, capacity: usize) -> Self {
+        Self {
+            kind: ErrorKind::Limit,
+            source: None,
+            line,
+            module: module.into(),
+            msg: Some(format!("outbound queue of {capacity} messages is full")),
+        }
+    }
+
+    pub fn too_many_connections(line: u32, module: &str, what: &str) -> Self {
+        Self {
+            kind: ErrorKind::Limit,
+            source: None,
+            line,
+            module: module.into(),
+            msg: Some(format!("too many {what} connections")),
+        }
+    }
+
+    pub fn disconnected(line: u32, module: &str) -> Self {
+        Self {
+            kind: ErrorKind::Disconnect,
+            source: None,
+            line,
+            module: module.into(),
+            msg: Some("connection is closed".into()),
+        }
+    }
+
+    pub fn timeout<E: Display>(line: u32, module: &str, e: E) -> Self {
+        Self {
+            source: None,
+            kind: ErrorKind::Timeout,
+            line,
+            module: module.into(),
+            msg: Some(e.to_string()),
+        }
+    }
+
     pub fn external(line: u32, module: &str, msg: String) -> Self {
         Self {
             source: None,
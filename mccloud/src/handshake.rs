@@ -0,0 +1,112 @@
+use k256::{
+    ecdh::EphemeralSecret,
+    ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier},
+    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
+    schnorr::{Signature, SigningKey, VerifyingKey},
+    sha2::{Digest, Sha256},
+    PublicKey, SecretKey,
+};
+
+use crate::{
+    error::{Error, Result},
+    ex,
+    transport::Channel,
+    HashBytes, PubKeyBytes, SignBytes,
+};
+
+const SESSION_LABEL: &[u8] = b"mccloud session key";
+const TRANSCRIPT_LABEL: &[u8] = b"mccloud handshake";
+const CHANNEL_LABEL: &[u8] = b"mccloud channel";
+
+///
+/// The ephemeral side of a connection handshake.
+///
+/// Both peers send a fresh ephemeral public key in plain text. The session keys are derived from the ephemeral
+/// ECDH secret, so a leaked node key does not reveal past traffic. Afterwards each side proves the possession of
+/// its node key, by signing the handshake transcript inside the encrypted greeting.
+///
+pub struct Handshake {
+    secret: EphemeralSecret,
+    pub ephemeral: PubKeyBytes,
+}
+
+impl Handshake {
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::random(&mut OsRng);
+        let mut ephemeral: PubKeyBytes = [0u8; 33];
+        ephemeral.copy_from_slice(secret.public_key().to_encoded_point(true).as_bytes());
+
+        Self { secret, ephemeral }
+    }
+
+    /// Returns the keys for sending and receiving.
+    pub fn session_keys(&self, remote: &PubKeyBytes) -> Result<(HashBytes, HashBytes)> {
+        let remote_key = ex!(PublicKey::from_sec1_bytes(remote), encrypt);
+        let shared = self.secret.diffie_hellman(&remote_key);
+        let shared = shared.raw_secret_bytes();
+
+        let derive = |from: &PubKeyBytes, to: &PubKeyBytes| {
+            let mut sha = Sha256::new();
+            sha.update(SESSION_LABEL);
+            sha.update(shared);
+            sha.update(from);
+            sha.update(to);
+            let mut key = [0u8; 32];
+            key.copy_from_slice(&sha.finalize());
+            key
+        };
+
+        Ok((derive(&self.ephemeral, remote), derive(remote, &self.ephemeral)))
+    }
+
+    /// Signs the transcript with the node key.
+    pub fn sign(&self, remote: &PubKeyBytes, pubkey: &PubKeyBytes, prikey: &SecretKey) -> Result<SignBytes> {
+        let hash = transcript(&self.ephemeral, remote, pubkey);
+        let signer = SigningKey::from(prikey);
+        let sign = ex!(signer.sign_prehash(&hash), encrypt);
+
+        Ok(sign.to_bytes())
+    }
+
+    /// Verifies that the remote peer signed the transcript with the node key it claims.
+    pub fn verify(&self, remote: &PubKeyBytes, pubkey: &PubKeyBytes, sign: &SignBytes) -> Result<()> {
+        let hash = transcript(remote, &self.ephemeral, pubkey);
+        let verifier = ex!(VerifyingKey::from_bytes(&pubkey[1..]), encrypt);
+        let sign = ex!(Signature::try_from(&sign[..]), encrypt);
+        ex!(verifier.verify_prehash(&hash, &sign), encrypt);
+
+        Ok(())
+    }
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Derives the key of a channel from a session key. The control channel uses the session key itself.
+pub fn channel_key(key: &HashBytes, channel: Channel) -> HashBytes {
+    if channel == Channel::Control {
+        return *key;
+    }
+
+    let mut sha = Sha256::new();
+    sha.update(CHANNEL_LABEL);
+    sha.update([channel.tag()]);
+    sha.update(key);
+    let mut derived = [0u8; 32];
+    derived.copy_from_slice(&sha.finalize());
+    derived
+}
+
+fn transcript(signer_ephemeral: &PubKeyBytes, other_ephemeral: &PubKeyBytes, pubkey: &PubKeyBytes) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(TRANSCRIPT_LABEL);
+    sha.update(signer_ephemeral);
+    sha.update(other_ephemeral);
+    sha.update(pubkey);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sha.finalize());
+    hash
+}
@@ -127,6 +127,12 @@ pub struct Highlander {
     rounds: usize,
 }
 
+impl Default for Highlander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Highlander {
     pub fn new() -> Self {
         Self {
@@ -146,7 +152,7 @@ impl Highlander {
     }
 
     pub fn populate_roster<'a, T: Iterator<Item = &'a PubKeyBytes>>(&mut self, iter: T) {
-        self.roster.extend(iter.map(|id| (id.clone(), None)));
+        self.roster.extend(iter.map(|id| (*id, None)));
         self.rounds = game_rounds(self.roster.len());
     }
 
@@ -166,7 +172,7 @@ impl Highlander {
                     if let Err(e) = game.validate() {
                         tracing::error!(
                             "game could not be validated\nauthor: {}\n{}",
-                            hex::encode(&game.author),
+                            hex::encode(game.author),
                             e
                         );
                         false
@@ -175,13 +181,13 @@ impl Highlander {
                         true
                     }
                 } else {
-                    tracing::warn!("got already a game for\nauthor: {}", hex::encode(&game.author));
+                    tracing::warn!("got already a game for\nauthor: {}", hex::encode(game.author));
                     false
                 }
             } else {
                 tracing::error!(
                     "game author is not part of the game\nauthor: {}",
-                    hex::encode(&game.author)
+                    hex::encode(game.author)
                 );
                 false
             }
@@ -190,7 +196,7 @@ impl Highlander {
                 "round length do not match:\nexpected: {}\ngame: {}\nauthor: {}",
                 self.rounds,
                 game.rounds.len(),
-                hex::encode(&game.author)
+                hex::encode(game.author)
             );
             false
         }
@@ -204,8 +210,8 @@ impl Highlander {
 
         OsRng.fill_bytes(&mut buf);
 
-        for i in 0..count {
-            buf[i] %= 3;
+        for b in buf.iter_mut() {
+            *b %= 3;
         }
 
         let mut hash = Sha256::new();
@@ -215,14 +221,14 @@ impl Highlander {
         let sign = ex!(signer.sign_prehash(&hash), encrypt);
 
         Ok(Game {
-            author: pubkey.clone(),
+            author: *pubkey,
             sign: sign.to_bytes(),
             rounds: buf,
         })
     }
 
     pub fn is_filled(&self) -> bool {
-        if self.roster.len() == 0 {
+        if self.roster.is_empty() {
             // tracing::error!("is filled should never be checked if roster is not populated!");
             return false;
         }
@@ -247,20 +253,20 @@ impl Highlander {
 
         let start = SystemTime::now();
         // if roster.len() < 4096 {
-        roster.sort_by_cached_key(|x| x.0.clone());
+        roster.sort_by_cached_key(|x| x.0);
         // } else {
         //     roster.par_sort_by_cached_key(|x| x.0.clone());
         // }
         let dur = start.elapsed().unwrap();
         tracing::debug!("roster stort -> {:?}", dur);
 
-        for i in 0..roster.len() {
-            tree[i] = Some(i);
+        for (i, t) in tree.iter_mut().enumerate().take(roster.len()) {
+            *t = Some(i);
         }
 
         let mut lvl = 0;
         let mut offset = 0;
-        let mut count = (count + 1) / 2;
+        let mut count = count.div_ceil(2);
 
         let start = SystemTime::now();
         while count > 1 {
@@ -293,9 +299,9 @@ impl Highlander {
         tracing::debug!("tree evaluation -> {:?}", dur);
 
         // tracing::debug!("tree {:?}", tree);
-        let winner = tree.last().unwrap().clone().unwrap();
+        let winner = tree.last().unwrap().unwrap();
         let winner_pubkey = roster[winner].0;
-        tracing::debug!("winner {}", hex::encode(&winner_pubkey));
+        tracing::debug!("winner {}", hex::encode(winner_pubkey));
 
         winner_pubkey
     }
@@ -0,0 +1,65 @@
+use std::{
+    fs::OpenOptions,
+    io::{Read, Write},
+    path::Path,
+};
+
+use k256::{elliptic_curve::rand_core::OsRng, SecretKey};
+
+use crate::{
+    error::{Error, Result},
+    ex,
+};
+
+/// The name of the file, inside the data folder, which holds the node key.
+pub const KEY_FILE: &str = "node.key";
+
+/// Loads the node key from the data folder. If there is none, a new one is created and saved.
+pub fn load_or_create(folder: &Path) -> Result<SecretKey> {
+    let file = folder.join(KEY_FILE);
+
+    if file.exists() {
+        let mut buffer = Vec::new();
+        let mut f = ex!(OpenOptions::new().read(true).open(&file), io);
+        ex!(f.read_to_end(&mut buffer), io);
+
+        Ok(ex!(SecretKey::from_slice(&buffer), encrypt))
+    } else {
+        if !folder.exists() {
+            ex!(std::fs::create_dir_all(folder), io);
+        }
+
+        let key = SecretKey::random(&mut OsRng);
+        save(&file, &key)?;
+
+        Ok(key)
+    }
+}
+
+/// Writes the raw secret key bytes to a file, which is only readable by the owner.
+pub fn save(file: &Path, key: &SecretKey) -> Result<()> {
+    write_private(file, &key.to_bytes())
+}
+
+/// Writes a file only the owner may read. It is written to a temporary file first, which replaces `file` once it
+/// is complete, so a crash never leaves a partial key behind.
+pub(crate) fn write_private(file: &Path, data: &[u8]) -> Result<()> {
+    let tmp = file.with_extension("tmp");
+    let mut opts = OpenOptions::new();
+    opts.create(true).truncate(true).write(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+
+    {
+        let mut f = ex!(opts.open(&tmp), io);
+        ex!(f.write_all(data), io);
+        ex!(f.sync_all(), io);
+    }
+    ex!(std::fs::rename(&tmp, file), io);
+
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use std::{
+    fs::OpenOptions,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use aes_gcm_siv::{
+    aead::{Aead, Payload},
+    Aes256GcmSiv, KeyInit, Nonce,
+};
+use argon2::{Argon2, Params};
+use borsh::{BorshDeserialize, BorshSerialize};
+use hashbrown::HashMap;
+use k256::{
+    elliptic_curve::{
+        rand_core::{OsRng, RngCore},
+        sec1::ToEncodedPoint,
+    },
+    SecretKey,
+};
+
+use crate::{
+    error::{Error, Result},
+    ex, identity, PubKeyBytes,
+};
+
+const FORMAT_VERSION: u8 = 1;
+
+/// The argon2id cost parameters used to derive the encryption key from a passphrase.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory size in KiB.
+    pub m_cost: u32,
+    /// Number of iterations.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// A single passphrase encrypted secp256k1 key.
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct KeystoreEntry {
+    /// The public key of the encrypted secret key.
+    pub pubkey: PubKeyBytes,
+    kdf: KdfParams,
+    salt: [u8; 16],
+    iv: [u8; 12],
+    cipher: Vec<u8>,
+}
+
+impl KeystoreEntry {
+    /// Encrypts a secret key with the passphrase. Fails on an empty passphrase, which would not protect the key.
+    pub fn seal(key: &SecretKey, passphrase: &[u8], kdf: KdfParams) -> Result<Self> {
+        ex!(check_passphrase(passphrase), source);
+        let pubkey = pubkey_of(key);
+
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let mut iv = [0u8; 12];
+        OsRng.fill_bytes(&mut iv);
+
+        let aes = ex!(derive_cipher(passphrase, &salt, &kdf), source);
+        let plain = key.to_bytes();
+        let cipher = ex!(
+            aes.encrypt(
+                Nonce::from_slice(&iv),
+                Payload {
+                    msg: plain.as_ref(),
+                    aad: &pubkey,
+                }
+            ),
+            encrypt
+        );
+
+        Ok(Self {
+            pubkey,
+            kdf,
+            salt,
+            iv,
+            cipher,
+        })
+    }
+
+    /// Decrypts the secret key. Fails if the passphrase is wrong or the entry was tampered with.
+    pub fn unlock(&self, passphrase: &[u8]) -> Result<SecretKey> {
+        ex!(check_passphrase(passphrase), source);
+        let aes = ex!(derive_cipher(passphrase, &self.salt, &self.kdf), source);
+        let plain = ex!(
+            aes.decrypt(
+                Nonce::from_slice(&self.iv),
+                Payload {
+                    msg: self.cipher.as_ref(),
+                    aad: &self.pubkey,
+                }
+            ),
+            encrypt
+        );
+        let key = ex!(SecretKey::from_slice(&plain), encrypt);
+
+        if pubkey_of(&key) != self.pubkey {
+            return Err(Error::encrypt(
+                line!(),
+                module_path!(),
+                "keystore entry does not match its public key",
+            ));
+        }
+
+        Ok(key)
+    }
+}
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct KeystoreFile {
+    version: u8,
+    entries: Vec<(String, KeystoreEntry)>,
+}
+
+/// A file of named, passphrase encrypted keys. Used for node and data author keys.
+pub struct Keystore {
+    file: PathBuf,
+    kdf: KdfParams,
+    entries: HashMap<String, KeystoreEntry>,
+}
+
+fn pubkey_of(key: &SecretKey) -> PubKeyBytes {
+    let mut pubkey: PubKeyBytes = [0u8; 33];
+    pubkey.copy_from_slice(key.public_key().to_encoded_point(true).as_bytes());
+    pubkey
+}
+
+fn check_passphrase(passphrase: &[u8]) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(Error::encrypt(line!(), module_path!(), "the passphrase is empty"));
+    }
+
+    Ok(())
+}
+
+fn derive_cipher(passphrase: &[u8], salt: &[u8], kdf: &KdfParams) -> Result<Aes256GcmSiv> {
+    let params = ex!(Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(32)), encrypt);
+    let argon = Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params);
+    let mut key = [0u8; 32];
+    ex!(argon.hash_password_into(passphrase, salt, &mut key), encrypt);
+
+    Ok(ex!(Aes256GcmSiv::new_from_slice(&key), encrypt))
+}
+
+impl Keystore {
+    /// Opens a keystore file. If the file does not exist, an empty keystore is returned, which is created on
+    /// the first save.
+    pub fn open(file: &Path) -> Result<Self> {
+        let mut entries = HashMap::new();
+
+        if file.exists() {
+            let mut buffer = Vec::new();
+            let mut f = ex!(OpenOptions::new().read(true).open(file), io);
+            ex!(f.read_to_end(&mut buffer), io);
+            let content: KeystoreFile = ex!(borsh::from_slice(&buffer), io);
+
+            if content.version != FORMAT_VERSION {
+                return Err(Error::encrypt(
+                    line!(),
+                    module_path!(),
+                    format!("unsupported keystore version {}", content.version),
+                ));
+            }
+
+            entries.extend(content.entries);
+        }
+
+        Ok(Self {
+            file: file.to_path_buf(),
+            kdf: KdfParams::default(),
+            entries,
+        })
+    }
+
+    /// Sets the KDF parameters used for newly added entries.
+    pub fn set_kdf_params(&mut self, kdf: KdfParams) {
+        self.kdf = kdf;
+    }
+
+    /// Returns the names of all entries.
+    pub fn names(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Returns the entry with the given name.
+    pub fn entry(&self, name: &str) -> Option<&KeystoreEntry> {
+        self.entries.get(name)
+    }
+
+    /// Generates a new random key under the given name and returns its public key.
+    pub fn generate(&mut self, name: &str, passphrase: &[u8]) -> Result<PubKeyBytes> {
+        let key = SecretKey::random(&mut OsRng);
+        self.insert(name, &key, passphrase)
+    }
+
+    /// Imports a raw 32 byte secp256k1 secret key under the given name and returns its public key.
+    pub fn import(&mut self, name: &str, raw: &[u8], passphrase: &[u8]) -> Result<PubKeyBytes> {
+        let key = ex!(SecretKey::from_slice(raw), encrypt);
+        self.insert(name, &key, passphrase)
+    }
+
+    /// Exports the raw 32 byte secp256k1 secret key of the given entry.
+    pub fn export(&self, name: &str, passphrase: &[u8]) -> Result<[u8; 32]> {
+        let key = ex!(self.unlock(name, passphrase), source);
+        Ok(key.to_bytes().into())
+    }
+
+    /// Decrypts the secret key of the given entry.
+    pub fn unlock(&self, name: &str, passphrase: &[u8]) -> Result<SecretKey> {
+        match self.entries.get(name) {
+            Some(entry) => entry.unlock(passphrase),
+            None => Err(Error::encrypt(
+                line!(),
+                module_path!(),
+                format!("no keystore entry named {name}"),
+            )),
+        }
+    }
+
+    /// Removes an entry. Returns false if there was no such entry.
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    /// Writes the keystore to its file.
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.file.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                ex!(std::fs::create_dir_all(parent), io);
+            }
+        }
+
+        let mut entries: Vec<(String, KeystoreEntry)> =
+            self.entries.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let data = ex!(
+            borsh::to_vec(&KeystoreFile {
+                version: FORMAT_VERSION,
+                entries,
+            }),
+            io
+        );
+
+        identity::write_private(&self.file, &data)
+    }
+
+    fn insert(&mut self, name: &str, key: &SecretKey, passphrase: &[u8]) -> Result<PubKeyBytes> {
+        let entry = ex!(KeystoreEntry::seal(key, passphrase, self.kdf), source);
+        let pubkey = entry.pubkey;
+        self.entries.insert(name.to_string(), entry);
+
+        Ok(pubkey)
+    }
+}
@@ -1,45 +1,58 @@
 #![doc = include_str!("../../README.md")]
 
 use std::{
-    fmt::Display,
+    cmp::Reverse,
     future::Future,
-    net::{SocketAddr, ToSocketAddrs},
     pin::Pin,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc, Weak,
     },
-    time::SystemTime,
+    time::Duration,
 };
 
-use blockchain::{Block, BlockIterator, Blockchain, Data};
-use client::{ClientInfo, ClientReader, ClientWriter};
-use config::{Algorithm, Config};
+use access::AccessList;
+use addressbook::{AddressBook, AddressRecord};
+use ban::{Banned, Bans};
+use blockchain::{network_id, Block, BlockStream, Blockchain, Data, DataLocation, Recovery, VerifiedBlock};
+use client::{ClientInfo, ClientReader, ClientWriter, Direction};
+use config::{Algorithm, Config, Discovery};
 use error::ErrorKind;
 pub use error::{Error, Result};
+use handshake::Handshake;
 use hashbrown::{hash_map::Entry, HashMap, HashSet};
-use k256::{
-    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
-    SecretKey,
-};
+use k256::{elliptic_curve::sec1::ToEncodedPoint, SecretKey};
+use keystore::Keystore;
+use membership::{MemberRecord, Members};
 use message::Message;
+use reconnect::{PendingReconnect, Reconnects, Redial};
+use storage::Storage;
 use tokio::{
-    io::AsyncWriteExt,
-    net::{TcpListener, TcpStream},
     select,
-    sync::{broadcast, mpsc, Mutex, RwLock},
+    sync::{broadcast, mpsc, Mutex, Notify, RwLock, Semaphore},
     time,
 };
-use tokio_socks::tcp::Socks5Stream;
 pub use tokio_socks::{IntoTargetAddr, TargetAddr};
-pub use version::Version;
+use transport::{Stream, Transport};
+pub use version::{Capabilities, Protocol, Version, PREAMBLE_SIZE};
 
+mod access;
+pub mod addressbook;
+pub mod ban;
 pub mod blockchain;
 mod client;
 pub mod config;
+mod discovery;
 pub mod error;
+pub mod handshake;
 pub mod highlander;
+pub mod identity;
+pub mod keystore;
+mod membership;
 mod message;
+pub mod reconnect;
+pub mod storage;
+pub mod transport;
 mod version;
 
 #[macro_export]
@@ -53,7 +66,20 @@ pub type PubKeyBytes = [u8; 33];
 pub type HashBytes = [u8; 32];
 pub type SignBytes = [u8; 64];
 
+/// How long the reconnect task sleeps, if nothing is scheduled.
+const FAR_FUTURE: Duration = Duration::from_secs(60 * 60);
+
+/// How long a shutdown waits for the leave to be sent to each client.
+const LEAVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How many messages read from the additional channels of a connection may wait to be handled.
+const CHANNEL_QUEUE: usize = 16;
+
+/// How often learned addresses may trigger new connections.
+const HEAL_INTERVAL: Duration = Duration::from_secs(1);
+
 type Clients = HashMap<PubKeyBytes, Arc<ClientInfo>>;
+type Dial = (TargetAddr<'static>, Redial, Option<PubKeyBytes>);
 type OnCreateCb = dyn Fn(HashMap<SignBytes, Data>) -> Pin<Box<dyn Future<Output = Result<HashMap<SignBytes, Data>>> + Send>>
     + Send
     + 'static;
@@ -68,33 +94,196 @@ fn target_addr_to_string<'a>(b: TargetAddr<'a>) -> String {
 pub struct Peer {
     me: Weak<Peer>,
     pub cfg: Config,
+    network: HashBytes,
+    transport: Arc<dyn Transport>,
     version: Version,
+    protocol: Protocol,
     prikey: SecretKey,
     pubkey: PubKeyBytes,
     pubhex: String,
-    to_accept: mpsc::Sender<(TargetAddr<'static>, u32, Option<PubKeyBytes>)>,
+    to_accept: mpsc::Sender<Dial>,
     last_block_tx: broadcast::Sender<Block>,
     to_shutdown: broadcast::Sender<bool>,
     clients: RwLock<Clients>,
-    known: RwLock<HashSet<PubKeyBytes>>,
+    known: RwLock<Members>,
+    /// Our own signed announcement, renewed periodically. Thin peers do not announce themselves.
+    announcement: RwLock<Option<MemberRecord>>,
+    access: RwLock<AccessList>,
+    addresses: Mutex<AddressBook>,
+    reconnects: Mutex<Reconnects>,
+    /// Wakes up the reconnect task, when a reconnect is scheduled.
+    reconnect_changed: Notify,
+    /// When learned addresses last triggered new connections.
+    healed: Mutex<Option<time::Instant>>,
+    bans: Mutex<Bans>,
     blockchain: RwLock<Blockchain>,
     on_block_creation: Mutex<Option<Box<OnCreateCb>>>,
     is_block_gathering: AtomicBool,
 }
 
+/// What a peer reads from its data folder, before it starts.
+struct Stored {
+    blockchain: Blockchain,
+    addresses: AddressBook,
+}
+
+impl Stored {
+    /// Opens the blockchain in the storage, and loads the address book. This blocks on disk access.
+    fn load(cfg: &Config, storage: Box<dyn Storage>) -> Result<Self> {
+        let network = network_id(&cfg.network);
+        let blockchain = ex!(Blockchain::new(storage, network, cfg.legacy_signatures), source);
+        let addresses = ex!(AddressBook::load(&cfg.folder, cfg.addresses), source);
+
+        Ok(Self { blockchain, addresses })
+    }
+}
+
+/// Returns the node key of the config, or loads it from the data folder.
+fn node_key(cfg: &Config) -> Result<SecretKey> {
+    match &cfg.key {
+        Some(key) => Ok(ex!(SecretKey::from_slice(key), encrypt)),
+        None => Ok(ex!(identity::load_or_create(&cfg.folder), source)),
+    }
+}
+
 impl Peer {
+    ///
+    /// Creates a new peer. The node key is taken from the config, or loaded from the data folder.
+    ///
+    /// The node key, the blockchain and the address book are read from disk in place. Within a running runtime,
+    /// `new_async` does not stall it, like the `_async` counterparts of the other constructors.
+    ///
     pub fn new(cfg: Config) -> Result<Arc<Self>> {
-        let prikey = k256::SecretKey::random(&mut OsRng);
+        let prikey = ex!(node_key(&cfg), source);
+
+        Self::with_key(cfg, prikey)
+    }
+
+    /// Creates a new peer like `new`, but reads from disk on the blocking thread pool.
+    pub async fn new_async(cfg: Config) -> Result<Arc<Self>> {
+        let transport = ex!(transport::from_config(&cfg), source);
+
+        // opening a file storage may recover or index the whole chain
+        let load_cfg = cfg.clone();
+        let loaded = tokio::task::spawn_blocking(move || {
+            let prikey = ex!(node_key(&load_cfg), source);
+            let storage = ex!(storage::from_config(&load_cfg), source);
+            let stored = ex!(Stored::load(&load_cfg, storage), source);
+
+            Ok((prikey, stored))
+        });
+        let (prikey, stored) = ex!(ex!(loaded.await, sync), source);
+
+        Self::start(cfg, prikey, transport, stored)
+    }
+
+    /// Creates a new peer, with the node key taken from an entry of a keystore.
+    pub fn from_keystore(cfg: Config, keystore: &Keystore, name: &str, passphrase: &[u8]) -> Result<Arc<Self>> {
+        let prikey = ex!(keystore.unlock(name, passphrase), source);
+
+        Self::with_key(cfg, prikey)
+    }
+
+    /// Creates a new peer like `from_keystore`, but unlocks the key and reads from disk on the blocking thread pool.
+    pub async fn from_keystore_async(
+        cfg: Config,
+        keystore: &Keystore,
+        name: &str,
+        passphrase: &[u8],
+    ) -> Result<Arc<Self>> {
+        let Some(entry) = keystore.entry(name).cloned() else {
+            return Err(Error::encrypt(
+                line!(),
+                module_path!(),
+                format!("no keystore entry named {name}"),
+            ));
+        };
+        // the key derivation is expensive on purpose
+        let passphrase = passphrase.to_vec();
+        let unlocked = tokio::task::spawn_blocking(move || entry.unlock(&passphrase));
+        let prikey = ex!(ex!(unlocked.await, sync), source);
+
+        Self::with_key_async(cfg, prikey).await
+    }
+
+    /// Creates a new peer with the given node key. The key in the config is ignored.
+    pub fn with_key(cfg: Config, prikey: SecretKey) -> Result<Arc<Self>> {
+        let transport = ex!(transport::from_config(&cfg), source);
+
+        Self::with_transport(cfg, prikey, transport)
+    }
+
+    /// Creates a new peer like `with_key`, but reads from disk on the blocking thread pool.
+    pub async fn with_key_async(cfg: Config, prikey: SecretKey) -> Result<Arc<Self>> {
+        let transport = ex!(transport::from_config(&cfg), source);
+
+        Self::with_transport_async(cfg, prikey, transport).await
+    }
+
+    /// Creates a new peer with the given node key, which uses a custom transport. The key and transport in the
+    /// config are ignored.
+    pub fn with_transport(cfg: Config, prikey: SecretKey, transport: Arc<dyn Transport>) -> Result<Arc<Self>> {
+        let storage = ex!(storage::from_config(&cfg), source);
+
+        Self::with_storage(cfg, prikey, transport, storage)
+    }
+
+    /// Creates a new peer like `with_transport`, but reads from disk on the blocking thread pool.
+    pub async fn with_transport_async(
+        cfg: Config,
+        prikey: SecretKey,
+        transport: Arc<dyn Transport>,
+    ) -> Result<Arc<Self>> {
+        let load_cfg = cfg.clone();
+        let loaded = tokio::task::spawn_blocking(move || {
+            let storage = ex!(storage::from_config(&load_cfg), source);
+            Stored::load(&load_cfg, storage)
+        });
+        let stored = ex!(ex!(loaded.await, sync), source);
+
+        Self::start(cfg, prikey, transport, stored)
+    }
+
+    /// Creates a new peer with the given node key, which uses a custom transport and keeps the blockchain in a
+    /// custom storage. The key, transport and storage in the config are ignored.
+    pub fn with_storage(
+        cfg: Config,
+        prikey: SecretKey,
+        transport: Arc<dyn Transport>,
+        storage: Box<dyn Storage>,
+    ) -> Result<Arc<Self>> {
+        let stored = ex!(Stored::load(&cfg, storage), source);
+
+        Self::start(cfg, prikey, transport, stored)
+    }
+
+    /// Creates a new peer like `with_storage`, but opens the blockchain on the blocking thread pool.
+    pub async fn with_storage_async(
+        cfg: Config,
+        prikey: SecretKey,
+        transport: Arc<dyn Transport>,
+        storage: Box<dyn Storage>,
+    ) -> Result<Arc<Self>> {
+        let load_cfg = cfg.clone();
+        let loaded = tokio::task::spawn_blocking(move || Stored::load(&load_cfg, storage));
+        let stored = ex!(ex!(loaded.await, sync), source);
+
+        Self::start(cfg, prikey, transport, stored)
+    }
+
+    /// Starts the peer on what was read from its data folder.
+    fn start(cfg: Config, prikey: SecretKey, transport: Arc<dyn Transport>, stored: Stored) -> Result<Arc<Self>> {
+        let Stored { blockchain, addresses } = stored;
         let mut pubkey: PubKeyBytes = [0u8; 33];
         pubkey.copy_from_slice(prikey.public_key().to_encoded_point(true).as_bytes());
         let (to_shutdown, _) = broadcast::channel(1);
-
-        let blockchain = ex!(Blockchain::new(&cfg.folder), source);
+        let network = blockchain.network;
 
         let pubhex: String = hex::encode(pubkey);
         let version = Version::default();
+        let protocol = Protocol::default();
 
-        tracing::info!("{version}");
+        tracing::info!("{version} {protocol}");
         tracing::info!(
             "root block {}",
             blockchain.root.as_ref().map(hex::encode).unwrap_or_default()
@@ -104,13 +293,25 @@ impl Peer {
             blockchain.count,
             blockchain.last.as_ref().map(hex::encode).unwrap_or_default()
         );
+        let announcement = if cfg.thin {
+            None
+        } else {
+            Some(ex!(MemberRecord::announce(&network, &pubkey, &prikey), source))
+        };
+        let known = Members::new(cfg.membership, pubkey);
+        let access = AccessList::new(&cfg.access);
+        let reconnects = Reconnects::new(&cfg.relationship);
+        let bans = Bans::new(cfg.scoring);
         let (last_block_tx, _) = broadcast::channel(10);
         let (to_accept_tx, to_accept_rx) = mpsc::channel(10);
 
         let peer = Arc::new_cyclic(|me| Self {
             // let peer = Arc::new(Self {
             me: me.clone(),
+            network,
+            transport,
             version,
+            protocol,
             prikey,
             pubkey,
             pubhex,
@@ -119,7 +320,14 @@ impl Peer {
             last_block_tx,
             to_shutdown,
             clients: RwLock::new(HashMap::new()),
-            known: RwLock::new(HashSet::new()),
+            known: RwLock::new(known),
+            announcement: RwLock::new(announcement),
+            access: RwLock::new(access),
+            addresses: Mutex::new(addresses),
+            reconnects: Mutex::new(reconnects),
+            reconnect_changed: Notify::new(),
+            healed: Mutex::new(None),
+            bans: Mutex::new(bans),
             blockchain: RwLock::new(blockchain),
             on_block_creation: Mutex::new(None),
             is_block_gathering: AtomicBool::new(false),
@@ -139,6 +347,22 @@ impl Peer {
             }
         });
 
+        let p5 = peer.clone();
+        tokio::spawn(async move {
+            if let Err(e) = p5.reconnect().await {
+                tracing::error!("{} reconnect: {}", p5.pubhex, e);
+            }
+        });
+
+        if let Some(discovery) = peer.cfg.discovery {
+            let p4 = peer.clone();
+            tokio::spawn(async move {
+                if let Err(e) = p4.discover(discovery).await {
+                    tracing::error!("{} discovery: {}", p4.pubhex, e);
+                }
+            });
+        }
+
         if !peer.cfg.thin {
             let p3 = peer.clone();
             tokio::spawn(async move {
@@ -168,24 +392,79 @@ impl Peer {
 
     async fn establish_relationship(&self) -> Result<()> {
         let mut rx_shutdown = self.to_shutdown.subscribe();
+        // a failed attempt is logged, the relationships are still checked periodically
+        if let Err(e) = self.connect_persistent_peers().await {
+            tracing::error!("{} connect persistent peers: {}", self.pubhex, e);
+        }
+        if let Err(e) = self.connect_known().await {
+            tracing::error!("{} connect known peers: {}", self.pubhex, e);
+        }
+        if let Err(e) = self.connect_seeds().await {
+            tracing::error!("{} connect seeds: {}", self.pubhex, e);
+        }
+
         let mut interval = time::interval(self.cfg.relationship.time);
+        let mut bootstrap = time::interval_at(
+            time::Instant::now() + self.cfg.bootstrap.retry,
+            self.cfg.bootstrap.retry,
+        );
+        let mut keepalive = time::interval(self.cfg.keepalive.interval);
+        let mut refresh = time::interval_at(
+            time::Instant::now() + self.cfg.membership.refresh,
+            self.cfg.membership.refresh,
+        );
+        let mut rotate = time::interval_at(
+            time::Instant::now() + self.cfg.relationship.rotate,
+            self.cfg.relationship.rotate,
+        );
 
         loop {
             select! {
                 _ = rx_shutdown.recv() => { break; }
+                _ = bootstrap.tick() => {
+                    if self.clients.read().await.is_empty() {
+                        if let Err(e) = self.connect_seeds().await {
+                            tracing::error!("{} connect seeds: {}", self.pubhex, e);
+                        }
+                    }
+                }
+                _ = keepalive.tick() => {
+                    if let Err(e) = self.keepalive().await {
+                        tracing::error!("{} keepalive: {}", self.pubhex, e);
+                    }
+                }
+                _ = refresh.tick() => {
+                    self.known.write().await.expire();
+                    if let Err(e) = self.reannounce().await {
+                        tracing::error!("{} reannounce: {}", self.pubhex, e);
+                    }
+                }
+                _ = rotate.tick() => {
+                    if let Err(e) = self.rotate_outbound().await {
+                        tracing::error!("{} rotate outbound: {}", self.pubhex, e);
+                    }
+                }
                 _ = interval.tick() => {
+                    self.update_anchors().await;
+                    if let Err(e) = self.addresses.lock().await.save() {
+                        tracing::error!("{} save addresses: {}", self.pubhex, e);
+                    }
+
                     let current = self.clients.read().await.len();
 
                     if current < self.cfg.relationship.count as _ {
                         let keys: Vec<PubKeyBytes> = self.clients.read().await.keys().cloned().collect();
-                        ex!(
-                            self.broadcast(Message::RequestNeighbours {
-                                count: self.cfg.relationship.count,
-                                exclude: keys,
-                            })
-                            .await,
-                            source
-                        );
+                        self.broadcast(Message::RequestNeighbours {
+                            count: self.cfg.relationship.count,
+                            exclude: keys.clone(),
+                        })
+                        .await;
+                        // the neighbours of our neighbours may not be enough, so we also ask for their address books
+                        self.broadcast(Message::RequestAddresses {
+                            count: self.cfg.addresses.share,
+                            exclude: keys,
+                        })
+                        .await;
                     }
                 }
             }
@@ -196,56 +475,348 @@ impl Peer {
         Ok(())
     }
 
-    async fn broadcast_except(&self, msg: Message, except: &Arc<ClientInfo>) -> Result<()> {
-        let except = except.pubkey;
+    /// Keeps the outbound peers, which are connected the longest, as anchors.
+    async fn update_anchors(&self) {
+        let mut outbound: Vec<_> = self
+            .clients
+            .read()
+            .await
+            .values()
+            .filter(|c| c.direction == Direction::Outbound && !c.thin)
+            .map(|c| (c.since, c.pubkey))
+            .collect();
+        outbound.sort_unstable();
+
+        let anchors = outbound
+            .into_iter()
+            .take(self.cfg.relationship.anchors)
+            .map(|(_, k)| k)
+            .collect();
+        self.addresses.lock().await.set_anchors(anchors);
+    }
+
+    /// Replaces a random outbound connection, which is neither an anchor nor persistent, by a new peer of the
+    /// address book. So an attacker can not keep the connections it once got.
+    async fn rotate_outbound(&self) -> Result<()> {
+        let outbound: Vec<Arc<ClientInfo>> = {
+            let clients = self.clients.read().await;
+            if clients.len() < self.cfg.relationship.count as _ {
+                return Ok(());
+            }
+            clients
+                .values()
+                .filter(|c| c.direction == Direction::Outbound && !c.persistent)
+                .cloned()
+                .collect()
+        };
+
+        let (mut exclude, mut groups) = self.connected_groups().await;
+        exclude.insert(self.pubkey);
+
+        let (victim, candidate) = {
+            let addresses = self.addresses.lock().await;
+            let rotatable: Vec<&Arc<ClientInfo>> =
+                outbound.iter().filter(|c| !addresses.is_anchor(&c.pubkey)).collect();
+            if rotatable.is_empty() {
+                return Ok(());
+            }
+
+            let victim = rotatable[rand::random::<u32>() as usize % rotatable.len()].clone();
+            groups.remove(&addressbook::group(&target_addr_to_string(victim.addr.to_owned())));
+            match addresses.candidates(1, &exclude, &groups).pop() {
+                Some(candidate) => (victim, candidate),
+                None => return Ok(()),
+            }
+        };
+
+        let addr = match candidate.addrs[0].clone().into_target_addr() {
+            Ok(addr) => addr,
+            Err(e) => {
+                tracing::warn!("{} address of {}: {}", self.pubhex, hex::encode(candidate.pubkey), e);
+                self.addresses
+                    .lock()
+                    .await
+                    .failed(&candidate.pubkey, &candidate.addrs[0]);
+                return Ok(());
+            }
+        };
+
+        tracing::debug!(
+            "{} rotate {} to {}",
+            self.pubhex,
+            hex::encode(victim.pubkey),
+            hex::encode(candidate.pubkey)
+        );
+        {
+            let mut clients = self.clients.write().await;
+            if clients.get(&victim.pubkey).is_some_and(|c| Arc::ptr_eq(c, &victim)) {
+                clients.remove(&victim.pubkey);
+            }
+        }
+        victim.close();
+        ex!(
+            self.to_accept.send((addr, Redial::Never, Some(candidate.pubkey))).await,
+            sync
+        );
+
+        Ok(())
+    }
+
+    /// Connects to the configured persistent peers.
+    async fn connect_persistent_peers(&self) -> Result<()> {
+        for persistent in self.cfg.relationship.persistent.iter() {
+            match persistent.clone().into_target_addr() {
+                Ok(addr) => {
+                    tracing::debug!("{} connect to persistent {}", self.pubhex, persistent);
+                    ex!(self.to_accept.send((addr, Redial::Forever, None)).await, sync);
+                }
+                Err(e) => tracing::error!("{} persistent {}: {}", self.pubhex, persistent, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Connects to all bootstrap seeds.
+    async fn connect_seeds(&self) -> Result<()> {
+        for seed in self.cfg.bootstrap.seeds.iter() {
+            match seed.clone().into_target_addr() {
+                Ok(addr) => {
+                    tracing::debug!("{} connect to seed {}", self.pubhex, seed);
+                    ex!(self.to_accept.send((addr, Redial::Never, None)).await, sync);
+                }
+                Err(e) => tracing::error!("{} seed {}: {}", self.pubhex, seed, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends our beacon to the multicast group, and connects to the peers whose beacons we receive. Of two peers
+    /// which find each other, the one with the lower public key connects. Beacons are signed, so nobody in the
+    /// local network can make us dial an address in the name of another peer.
+    async fn discover(&self, cfg: Discovery) -> Result<()> {
+        let sck = ex!(discovery::socket(&cfg), source);
+
+        let mut rx_shutdown = self.to_shutdown.subscribe();
+        let mut interval = time::interval(cfg.interval);
+        let mut buffer = [0u8; 256];
+
+        loop {
+            select! {
+                _ = rx_shutdown.recv() => { break; }
+                _ = interval.tick() => {
+                    let beacon = ex!(
+                        discovery::Beacon::new(&self.network, &self.pubkey, self.cfg.addr.port(), &self.prikey),
+                        source
+                    );
+                    let beacon = ex!(borsh::to_vec(&beacon), io);
+                    if let Err(e) = sck.send_to(&beacon, cfg.group).await {
+                        tracing::warn!("{} send beacon: {}", self.pubhex, e);
+                    }
+                }
+                res = sck.recv_from(&mut buffer) => {
+                    let (size, from) = match res {
+                        Ok(r) => r,
+                        Err(e) => {
+                            tracing::warn!("{} receive beacon: {}", self.pubhex, e);
+                            continue;
+                        }
+                    };
+                    let Ok(other) = borsh::from_slice::<discovery::Beacon>(&buffer[..size]) else {
+                        continue;
+                    };
+
+                    if other.network != self.network
+                        || other.pubkey <= self.pubkey
+                        || self.clients.read().await.contains_key(&other.pubkey)
+                        || !self.access.read().await.is_allowed(&other.pubkey)
+                        || self.bans.lock().await.is_banned(&Banned::Pubkey(other.pubkey))
+                    {
+                        continue;
+                    }
+                    if let Err(e) = other.verify() {
+                        tracing::warn!("{} beacon from {}: {}", self.pubhex, from, e);
+                        continue;
+                    }
+
+                    let addr = TargetAddr::Ip((from.ip(), other.port).into());
+                    tracing::debug!("{} discovered {} {:?}", self.pubhex, hex::encode(other.pubkey), addr);
+                    ex!(self.to_accept.send((addr, Redial::Never, Some(other.pubkey))).await, sync);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the connected peers, and the networks of our outbound connections. The networks are taken from
+    /// the addresses we dialed, not the ones the peers claim.
+    async fn connected_groups(&self) -> (HashSet<PubKeyBytes>, HashSet<String>) {
+        let clients = self.clients.read().await;
+        let groups = clients
+            .values()
+            .filter(|c| c.direction == Direction::Outbound)
+            .map(|c| addressbook::group(&target_addr_to_string(c.addr.to_owned())))
+            .collect();
+
+        (clients.keys().cloned().collect(), groups)
+    }
+
+    /// Connects to the anchors, and then to the most reliable peers of the address book, until there are enough
+    /// connections.
+    async fn connect_known(&self) -> Result<()> {
+        let (mut exclude, mut groups) = self.connected_groups().await;
+        let missing = (self.cfg.relationship.count as usize).saturating_sub(exclude.len());
+        if missing == 0 {
+            return Ok(());
+        }
+        exclude.insert(self.pubkey);
+
+        let candidates = {
+            let addresses = self.addresses.lock().await;
+            let mut candidates = addresses.anchors(&exclude);
+            candidates.truncate(missing);
+            exclude.extend(candidates.iter().map(|r| r.pubkey));
+            groups.extend(candidates.iter().map(|r| r.group()));
+            candidates.extend(addresses.candidates(missing - candidates.len(), &exclude, &groups));
+            candidates
+        };
+        for record in candidates {
+            if !self.access.read().await.is_allowed(&record.pubkey)
+                || self.bans.lock().await.is_banned(&Banned::Pubkey(record.pubkey))
+            {
+                continue;
+            }
+
+            match record.addrs[0].clone().into_target_addr() {
+                Ok(addr) => {
+                    tracing::debug!("{} connect to known {}", self.pubhex, hex::encode(record.pubkey));
+                    ex!(
+                        self.to_accept.send((addr, Redial::Never, Some(record.pubkey))).await,
+                        sync
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!("{} address of {}: {}", self.pubhex, hex::encode(record.pubkey), e);
+                    self.addresses.lock().await.failed(&record.pubkey, &record.addrs[0]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pings all clients which understand it, and drops the connections which did not answer in time.
+    async fn keepalive(&self) -> Result<()> {
         let cls = self.clients.read().await;
-        for to in cls.values() {
-            if to.pubkey != except {
-                ex!(to.write(&msg).await, source);
+        for cl in cls.values() {
+            if !cl.capabilities.contains(Capabilities::PING) {
+                continue;
             }
+
+            if cl.silent_for() > self.cfg.keepalive.timeout {
+                tracing::warn!("{} dead connection {}", self.pubhex, hex::encode(cl.pubkey));
+                cl.close();
+                continue;
+            }
+
+            let nonce = rand::random();
+            if !cl.ping_sent(nonce) {
+                continue;
+            }
+            let ping = Arc::new(ex!(Message::ping(&self.network, &self.prikey, nonce), source));
+            if let Err(e) = cl.try_write(ping) {
+                tracing::warn!("{} ping {}: {}", self.pubhex, hex::encode(cl.pubkey), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Renews our own announcement and sends it to all clients.
+    async fn reannounce(&self) -> Result<()> {
+        if self.cfg.thin {
+            return Ok(());
         }
 
+        let record = ex!(
+            MemberRecord::announce(&self.network, &self.pubkey, &self.prikey),
+            source
+        );
+        *self.announcement.write().await = Some(record.clone());
+        self.broadcast(Message::Announce { record }).await;
+
         Ok(())
     }
 
-    async fn broadcast(&self, msg: Message) -> Result<()> {
+    /// Queues the message for all clients, except one. A full or closed queue of a client does not affect the
+    /// others.
+    async fn broadcast_except(&self, msg: Message, except: &Arc<ClientInfo>) {
+        let except = except.pubkey;
+        let msg = Arc::new(msg);
         let cls = self.clients.read().await;
         for to in cls.values() {
-            ex!(to.write(&msg).await, source);
+            if to.pubkey != except {
+                if let Err(e) = to.try_write(msg.clone()) {
+                    tracing::warn!("{} broadcast to {}: {}", self.pubhex, hex::encode(to.pubkey), e);
+                }
+            }
         }
+    }
 
-        Ok(())
+    /// Queues the message for all clients. A full or closed queue of a client does not affect the others.
+    async fn broadcast(&self, msg: Message) {
+        let msg = Arc::new(msg);
+        let cls = self.clients.read().await;
+        for to in cls.values() {
+            if let Err(e) = to.try_write(msg.clone()) {
+                tracing::warn!("{} broadcast to {}: {}", self.pubhex, hex::encode(to.pubkey), e);
+            }
+        }
     }
 
-    async fn listen(
-        &self,
-        mut to_accept: mpsc::Receiver<(TargetAddr<'static>, u32, Option<PubKeyBytes>)>,
-    ) -> Result<()> {
-        tracing::info!("{} listen on {}", self.pubhex, self.cfg.addr);
+    async fn listen(&self, mut to_accept: mpsc::Receiver<Dial>) -> Result<()> {
+        tracing::info!("{} listen on {}", self.pubhex, self.transport.announce());
 
-        let listener = ex!(TcpListener::bind(self.cfg.addr).await, io);
+        let mut listener = ex!(self.transport.listen().await, source);
         let mut rx_shutdown = self.to_shutdown.subscribe();
+        // unauthenticated connections cost a key exchange each, so only a few may be in the handshake at once
+        let handshakes = Arc::new(Semaphore::new(self.cfg.connections.handshakes));
 
-        async fn accepting<E: Display>(
-            peer: &Peer,
+        async fn accepting(
+            peer: Arc<Peer>,
             addr: TargetAddr<'static>,
-            sck: std::result::Result<TcpStream, E>,
-            reconn_cnt: u32,
+            sck: Result<Stream>,
+            direction: Direction,
+            redial: Redial,
             pubkey: Option<PubKeyBytes>,
         ) {
-            match sck {
-                Ok(sck) => {
-                    if let Err(e) = peer.accept(addr, sck, reconn_cnt).await {
-                        tracing::error!("{} while accepting: {}", peer.pubhex, e);
-                    }
-                }
+            let res = match sck {
+                Ok(sck) => peer
+                    .accept(addr.to_owned(), sck, (direction, redial), pubkey)
+                    .await
+                    .inspect_err(|e| tracing::error!("{} while accepting: {}", peer.pubhex, e)),
                 Err(e) => {
                     tracing::error!("{} while connecting: {}", peer.pubhex, e);
 
-                    if let Some(pubkey) = pubkey {
-                        peer.trigger_leave(pubkey, addr, reconn_cnt);
+                    if let Some(pubkey) = &pubkey {
+                        peer.addresses
+                            .lock()
+                            .await
+                            .failed(pubkey, &target_addr_to_string(addr.to_owned()));
                     }
+                    Err(e)
+                }
+            };
+
+            match res {
+                Ok(()) => {}
+                // a peer which refused us, or which we refused, is not dialed again
+                Err(e) if matches!(e.kind, ErrorKind::Denied | ErrorKind::Protocol) => {
+                    peer.reconnects.lock().await.forget(&addr);
                 }
+                Err(_) => peer.schedule_reconnect(addr, pubkey, redial).await,
             }
         }
 
@@ -253,30 +824,35 @@ impl Peer {
             select! {
                 _ = rx_shutdown.recv() => { break 'main; }
                 addr = to_accept.recv() => {
-                    if let Some((addr, reconn_cnt, pubkey)) = addr {
-                        if let Some(proxy) = &self.cfg.proxy {
-                            let res = Socks5Stream::connect(proxy.proxy, addr.to_owned()).await;
-                            accepting(self, addr, res.map(|e| e.into_inner()), reconn_cnt, pubkey).await;
-                        }
-                        else {
-                            let raddr = addr.to_socket_addrs()
-                                .map_err(|e| Error::io(line!(), module_path!(), e))
-                                .and_then(|mut x| x.next().ok_or( Error::external(line!(),module_path!() ,"no socket address".into())));
-                            match raddr {
-                                Ok(raddr) => {
-                                    accepting(self, addr, TcpStream::connect(raddr).await, reconn_cnt, None).await;
-                                }
-                                Err(e) => {
-                                    tracing::error!("{} {}", self.pubhex, e);
-                                }
-                            }
+                    if let Some((addr, redial, pubkey)) = addr {
+                        if let Some(reason) = self.refuse_dial(pubkey.as_ref(), redial).await {
+                            tracing::debug!("{} do not connect to {:?}: {}", self.pubhex, addr, reason);
+                            self.reconnects.lock().await.forget(&addr);
+                        } else {
+                            // two peers may dial each other at the same time, so no handshake waits for another
+                            let peer = self.me.upgrade().unwrap();
+                            tokio::spawn(async move {
+                                let res = time::timeout(peer.cfg.limits.handshake_timeout, peer.transport.connect(addr.to_owned()))
+                                    .await
+                                    .map_err(|e| Error::timeout(line!(), module_path!(), e))
+                                    .and_then(|r| r);
+                                accepting(peer, addr, res, Direction::Outbound, redial, pubkey).await;
+                            });
                         }
                     }
                 }
                 res = listener.accept() => {
                     match res {
                         Ok((sck, addr)) => {
-                            accepting(self, TargetAddr::Ip(addr).to_owned(), Ok::<_, std::io::Error>(sck), 0, None).await;
+                            let Ok(permit) = handshakes.clone().try_acquire_owned() else {
+                                tracing::warn!("{} too many handshakes, drop {:?}", self.pubhex, addr);
+                                continue;
+                            };
+                            let peer = self.me.upgrade().unwrap();
+                            tokio::spawn(async move {
+                                accepting(peer, addr, Ok(sck), Direction::Inbound, Redial::Never, None).await;
+                                drop(permit);
+                            });
                         }
                         Err(e) => {
                             tracing::error!("{} {}", self.pubhex, e);
@@ -292,54 +868,246 @@ impl Peer {
         Ok(())
     }
 
-    async fn accept(&self, addr: TargetAddr<'static>, sck: TcpStream, reconn_cnt: u32) -> Result<()> {
-        tracing::info!("{} accept {:?}", self.pubhex, addr);
+    /// Returns why no connection to another peer may be opened now, if so.
+    async fn refuse_dial(&self, pubkey: Option<&PubKeyBytes>, redial: Redial) -> Option<&'static str> {
+        let clients = self.clients.read().await;
+        if pubkey.is_some_and(|k| clients.contains_key(k)) {
+            return Some("already connected");
+        }
+        if redial == Redial::Forever {
+            return None;
+        }
 
-        let (mut reader, mut writer) = sck.into_split();
+        let outbound = clients.values().filter(|c| c.direction == Direction::Outbound).count();
+        if outbound >= self.cfg.connections.outbound {
+            Some("too many outbound connections")
+        } else if clients.len() >= self.cfg.connections.total {
+            Some("too many connections")
+        } else {
+            None
+        }
+    }
 
-        let (myroot, mylast, mycount, greeting) = {
-            let blkch = self.blockchain.read().await;
-            (
-                blkch.root,
-                blkch.last,
-                blkch.count,
-                Message::Greeting {
-                    version: self.version.clone(),
-                    pubkey: self.pubkey,
-                    listen: if let Some(proxy) = &self.cfg.proxy {
-                        proxy.announce_by.clone()
-                    } else {
-                        self.cfg.addr.to_string()
-                    },
-                    root: blkch.root,
-                    last: blkch.last,
-                    count: blkch.count,
-                    thin: self.cfg.thin,
-                    known: self.known.read().await.iter().cloned().collect(),
-                },
-            )
+    /// Evicts an inbound connection, if the inbound or total limit is reached. The evicted peer has the lowest
+    /// score, then the highest round trip time, then the shortest connection time.
+    async fn make_room(&self, clients: &mut Clients) -> Result<()> {
+        let victim = {
+            let inbound: Vec<&Arc<ClientInfo>> =
+                clients.values().filter(|c| c.direction == Direction::Inbound).collect();
+            if inbound.len() < self.cfg.connections.inbound && clients.len() < self.cfg.connections.total {
+                return Ok(());
+            }
+
+            let bans = self.bans.lock().await;
+            let victim = inbound.into_iter().min_by_key(|c| {
+                (
+                    bans.score(&c.pubkey),
+                    Reverse(c.rtt().unwrap_or(Duration::MAX)),
+                    Reverse(c.since),
+                )
+            });
+            match victim {
+                Some(victim) => victim.clone(),
+                None => return Err(Error::too_many_connections(line!(), module_path!(), "inbound")),
+            }
         };
 
-        ex!(ClientWriter::write_greeting(&mut writer, &greeting).await, source);
+        tracing::debug!("{} evict {}", self.pubhex, hex::encode(victim.pubkey));
+        clients.remove(&victim.pubkey);
+        victim.close();
+
+        Ok(())
+    }
+
+    /// Runs the handshake of a new connection. If we dialed a known peer, `expected` is its public key.
+    async fn accept(
+        &self,
+        addr: TargetAddr<'static>,
+        sck: Stream,
+        (direction, redial): (Direction, Redial),
+        expected: Option<PubKeyBytes>,
+    ) -> Result<()> {
+        tracing::info!("{} accept {:?}", self.pubhex, addr);
+
+        let host = Banned::host(&addr);
+        if let Some(host) = &host {
+            if self.bans.lock().await.is_banned(host) {
+                return Err(Error::banned(line!(), module_path!(), format!("{host:?}")));
+            }
+        }
+
+        let Stream {
+            mut reader,
+            mut writer,
+            channels,
+        } = sck;
+
+        let exchange = async {
+            // the preamble has a fixed layout, so peers of different versions fail with a clear error. The
+            // ephemeral key follows without waiting for the other side, so the preamble costs no round trip.
+            let handshake = Handshake::new();
+            ex!(ClientWriter::write_preamble(&mut writer, &self.protocol).await, source);
+            ex!(
+                ClientWriter::write_handshake(
+                    &mut writer,
+                    &Message::Handshake {
+                        ephemeral: handshake.ephemeral
+                    }
+                )
+                .await,
+                source
+            );
+
+            let preamble = ex!(ClientReader::read_preamble(&mut reader).await, source);
+            let Some(negotiated) = self.protocol.negotiate(&preamble) else {
+                return Err(Error::protocol(
+                    line!(),
+                    module_path!(),
+                    &format!(
+                        "no common mccloud protocol version, we speak {} and the peer {}",
+                        self.protocol, preamble
+                    ),
+                ));
+            };
+
+            let Message::Handshake { ephemeral } = ex!(
+                ClientReader::read_handshake(&mut reader, self.cfg.limits.greeting).await,
+                source
+            ) else {
+                return Err(Error::protocol(
+                    line!(),
+                    module_path!(),
+                    "first message was not handshake",
+                ));
+            };
+
+            let (send_key, recv_key) = ex!(handshake.session_keys(&ephemeral), source);
+            let mut reader = ex!(ClientReader::new(reader, &recv_key, self.cfg.limits), source);
+            let mut writer = ex!(ClientWriter::new(writer, &send_key, self.cfg.rekey), source);
+
+            let known = self.known.read().await.announcements();
+            // the announcements the peer learns by the greeting
+            let greeted: HashSet<(PubKeyBytes, u64)> = known.iter().map(|r| (r.subject, r.timestamp)).collect();
+            let (myroot, mylast, mycount, greeting) = {
+                let blkch = self.blockchain.read().await;
+                (
+                    blkch.root,
+                    blkch.last,
+                    blkch.count,
+                    Message::Greeting {
+                        version: self.version.clone(),
+                        protocol: self.protocol,
+                        network: self.network,
+                        pubkey: self.pubkey,
+                        sign: ex!(handshake.sign(&ephemeral, &self.pubkey, &self.prikey), source),
+                        listen: self.transport.announce(),
+                        root: blkch.root,
+                        last: blkch.last,
+                        count: blkch.count,
+                        thin: self.cfg.thin,
+                        announce: self.announcement.read().await.clone().map(Box::new),
+                        known,
+                    },
+                )
+            };
 
-        let greeting = ex!(ClientReader::read_greeting(&mut reader).await, source);
+            ex!(writer.write(&greeting).await, source);
+
+            let greeting = ex!(reader.read().await, source);
+
+            Ok((
+                (preamble, negotiated),
+                handshake,
+                ephemeral,
+                send_key,
+                recv_key,
+                reader,
+                writer,
+                myroot,
+                mylast,
+                mycount,
+                greeting,
+                greeted,
+            ))
+        };
+        let (
+            (preamble, (negotiated, capabilities)),
+            handshake,
+            ephemeral,
+            send_key,
+            recv_key,
+            mut reader,
+            mut writer,
+            myroot,
+            mylast,
+            mycount,
+            greeting,
+            greeted,
+        ) = ex!(
+            ex!(
+                time::timeout(self.cfg.limits.handshake_timeout, exchange).await,
+                timeout
+            ),
+            source
+        );
 
         if let Message::Greeting {
             version,
+            protocol,
+            network,
             pubkey,
+            sign,
             listen,
             root,
             last,
             count,
             thin,
+            announce,
             known,
         } = greeting
         {
-            if self.version != version {
+            ex!(handshake.verify(&ephemeral, &pubkey, &sign), source);
+            reader.established();
+
+            if pubkey == self.pubkey {
+                return Err(Error::protocol(line!(), module_path!(), "connected to ourself"));
+            }
+
+            // a persistent peer is configured by its address, and may change its key
+            if let Some(expected) = expected.filter(|e| *e != pubkey && redial != Redial::Forever) {
+                self.addresses
+                    .lock()
+                    .await
+                    .failed(&expected, &target_addr_to_string(addr.to_owned()));
+                return Err(Error::protocol(
+                    line!(),
+                    module_path!(),
+                    "peer is not the one we dialed",
+                ));
+            }
+
+            if !self.access.read().await.is_allowed(&pubkey) {
+                return Err(Error::denied(line!(), module_path!(), &pubkey));
+            }
+
+            if self.bans.lock().await.is_banned(&Banned::Pubkey(pubkey)) {
+                return Err(Error::banned(line!(), module_path!(), hex::encode(pubkey)));
+            }
+
+            // the greeting is authenticated, the preamble is not
+            if protocol != preamble {
+                return Err(Error::protocol(
+                    line!(),
+                    module_path!(),
+                    "greeting does not match the preamble",
+                ));
+            }
+
+            if network != self.network {
                 return Err(Error::protocol(
                     line!(),
                     module_path!(),
-                    "mccloud versions do not match",
+                    "peer belongs to another network",
                 ));
             }
 
@@ -352,43 +1120,103 @@ impl Peer {
             }
 
             tracing::info!(
-                "{} greeting\n{}\n{}\n{} {}",
+                "{} greeting\n{}\n{} {}\n{} {}",
                 self.pubhex,
                 hex::encode(pubkey),
                 version,
+                protocol,
                 root.as_ref().map(hex::encode).unwrap_or("".into()),
                 count,
             );
-            let shared = client::shared_secret(&pubkey, &self.prikey);
-            let mut reader = ex!(ClientReader::new(reader, &shared), source);
-            let cl = ClientInfo {
-                // addr,
-                thin,
-                listen: ex!(listen.into_target_addr(), sync),
-                pubkey,
-                writer: Mutex::new(ex!(ClientWriter::new(writer, &shared), source)),
-            };
+            let mut channel_readers = Vec::new();
+            let mut channel_writers = Vec::new();
+            for (channel, r, w) in channels {
+                // the control channel already watches if the connection is idle
+                let mut limits = self.cfg.limits;
+                limits.idle_timeout = Duration::MAX;
+
+                let mut r = ex!(
+                    ClientReader::new(r, &handshake::channel_key(&recv_key, channel), limits),
+                    source
+                );
+                r.established();
+                let mut w = ex!(
+                    ClientWriter::new(w, &handshake::channel_key(&send_key, channel), self.cfg.rekey),
+                    source
+                );
+                if !capabilities.contains(Capabilities::REKEY) {
+                    w.disable_rekey();
+                }
+
+                channel_readers.push(r);
+                channel_writers.push((channel, w));
+            }
+
+            if !capabilities.contains(Capabilities::REKEY) {
+                writer.disable_rekey();
+            }
 
             if !thin {
-                let new = {
-                    let mut k = self.known.write().await;
-                    let new = k.insert(pubkey);
+                self.addresses.lock().await.seen(pubkey, listen.clone());
+            }
 
-                    k.extend(&known);
-                    k.remove(&self.pubkey);
+            let cl = ClientInfo::new(
+                (thin, direction, redial == Redial::Forever),
+                (addr.to_owned(), ex!(listen.into_target_addr(), sync)),
+                pubkey,
+                writer,
+                channel_writers,
+                self.cfg.outbound,
+                (negotiated, capabilities),
+            );
 
-                    new
+            if !thin {
+                let Some(announce) = announce.filter(|a| a.subject == pubkey) else {
+                    return Err(Error::protocol(
+                        line!(),
+                        module_path!(),
+                        "greeting without announcement of the peer",
+                    ));
                 };
 
-                if new {
-                    ex!(self.broadcast(Message::Announce { pubkey }).await, source);
+                let mut changed = Vec::new();
+                {
+                    let access = self.access.read().await;
+                    let mut k = self.known.write().await;
+                    if ex!(k.apply(&announce, &self.network), source) {
+                        changed.push(*announce);
+                    }
+                    for record in known.into_iter().filter(|r| access.is_allowed(&r.subject)) {
+                        match k.apply(&record, &self.network) {
+                            Ok(true) => changed.push(record),
+                            Ok(false) => {}
+                            Err(e) => tracing::warn!("{} known of {}: {}", self.pubhex, hex::encode(pubkey), e),
+                        }
+                    }
+                }
+
+                for record in changed {
+                    self.broadcast(Message::Announce { record }).await;
+                }
+
+                let records = self
+                    .addresses
+                    .lock()
+                    .await
+                    .recent(self.cfg.addresses.share as _, &pubkey);
+                if !records.is_empty() {
+                    ex!(cl.write(Message::Addresses { records }).await, source);
                 }
-                for new in known {
-                    ex!(self.broadcast(Message::Announce { pubkey: new }).await, source);
+
+                // the peer is connected, but we hold a leave newer than its announcement
+                let left = self.known.read().await.record(&pubkey).filter(|r| !r.online).cloned();
+                if let Some(record) = left {
+                    ex!(cl.write(Message::Leave { record }).await, source);
                 }
 
                 if (myroot.is_none() || count > mycount) && last.is_some() {
-                    ex!(cl.write(&Message::RequestBlocks { start: mylast }).await, source);
+                    cl.blocks_requested(count);
+                    ex!(cl.write(Message::RequestBlocks { start: mylast }).await, source);
                 }
             }
 
@@ -397,29 +1225,127 @@ impl Peer {
             let cl = Arc::new(cl);
 
             let cl0 = cl.clone();
-            self.clients.write().await.insert(pubkey, cl0);
+            {
+                let mut clients = self.clients.write().await;
+                // of two connections between the same peers, both sides keep the one opened by the lower public key
+                if let Some(existing) = clients.get(&pubkey) {
+                    let preferred = if self.pubkey < pubkey {
+                        Direction::Outbound
+                    } else {
+                        Direction::Inbound
+                    };
+                    if existing.direction != direction && existing.direction == preferred {
+                        if redial == Redial::Forever {
+                            existing.adopt_persistent(addr.to_owned());
+                        }
+                        drop(clients);
+                        tracing::debug!("{} keep existing connection to {}", self.pubhex, hex::encode(pubkey));
+                        cl.close();
+                        self.reconnects.lock().await.forget(&addr);
+                        return Ok(());
+                    }
+
+                    tracing::debug!("{} replace connection to {}", self.pubhex, hex::encode(pubkey));
+                    if let Some(persistent) = existing.persistent_addr().filter(|_| !cl.persistent) {
+                        cl.adopt_persistent(persistent);
+                    }
+                    existing.close();
+                } else if direction == Direction::Inbound {
+                    ex!(self.make_room(&mut clients).await, source);
+                }
+                clients.insert(pubkey, cl0);
+            }
+            self.reconnects.lock().await.forget(&addr);
+
+            // announcements which arrived during the handshake, were broadcasted before the peer was a client
+            let missed: Vec<MemberRecord> = self
+                .known
+                .read()
+                .await
+                .announcements()
+                .into_iter()
+                .filter(|r| r.subject != pubkey && !greeted.contains(&(r.subject, r.timestamp)))
+                .collect();
+            for record in missed {
+                if let Err(e) = cl.try_write(Arc::new(Message::Announce { record })) {
+                    tracing::warn!("{} announce to {}: {}", self.pubhex, hex::encode(pubkey), e);
+                }
+            }
 
             let peer = self.me.upgrade().unwrap();
             let mut rx_shutdown = self.to_shutdown.subscribe();
 
+            // the channels only read, all messages are handled one after the other by the control loop below,
+            // so a block can not overtake its parent
+            let (tx_channels, mut rx_channels) = mpsc::channel(CHANNEL_QUEUE);
+            let mut channel_tasks = Vec::new();
+            for mut r in channel_readers {
+                let peer = peer.clone();
+                let cl = cl.clone();
+                let host = host.clone();
+                let tx_channels = tx_channels.clone();
+                channel_tasks.push(tokio::spawn(async move {
+                    loop {
+                        match r.read().await {
+                            Ok(msg) => {
+                                if tx_channels.send(msg).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => {
+                                if e.kind != ErrorKind::Disconnect {
+                                    tracing::error!("{} {}", peer.pubhex, e);
+                                }
+                                peer.misbehaved(&cl, &host, &e).await;
+                                // a broken channel closes the whole connection
+                                cl.close();
+                                break;
+                            }
+                        }
+                    }
+                }));
+            }
+            drop(tx_channels);
+
             tokio::spawn(async move {
                 loop {
                     select! {
                         _ = rx_shutdown.recv() => {
                             //peer.clients.write().await.remove(&pubkey);
+                            for t in channel_tasks.iter() {
+                                t.abort();
+                            }
+                            if !peer.cfg.thin {
+                                match MemberRecord::leave(&peer.network, &peer.pubkey, &peer.prikey) {
+                                    Ok(record) => cl.finish(Message::Leave { record }, LEAVE_TIMEOUT).await,
+                                    Err(e) => tracing::error!("{} {}", pubhex, e),
+                                }
+                            }
+                            cl.close();
                             return;
                         }
+                        _ = cl.closed() => {
+                            break;
+                        }
+                        Some(msg) = rx_channels.recv() => {
+                            if let Err(e) = peer.on_message(msg, cl.clone()).await {
+                                tracing::error!("{} {}", pubhex, e);
+                                peer.misbehaved(&cl, &host, &e).await;
+                            }
+                        }
                         msg = reader.read() => {
                             match msg {
                                 Ok(msg) => {
                                     if let Err(e) = peer.on_message(msg, cl.clone()).await {
                                         tracing::error!("{} {}", pubhex, e);
+                                        peer.misbehaved(&cl, &host, &e).await;
                                     }
                                 }
                                 Err(e) => {
                                     if e.kind != ErrorKind::Disconnect {
                                         tracing::error!("{} {}", pubhex, e);
                                     }
+                                    peer.misbehaved(&cl, &host, &e).await;
                                     break;
                                 }
                             }
@@ -427,15 +1353,36 @@ impl Peer {
                     }
                 }
 
+                for t in channel_tasks.iter() {
+                    t.abort();
+                }
+                cl.close();
+
                 tracing::debug!(
                     "{} disconnect\n{} {}",
                     peer.pubhex,
-                    hex::encode(&pubkey),
+                    hex::encode(pubkey),
                     target_addr_to_string(addr.to_owned())
                 );
-                peer.clients.write().await.remove(&pubkey);
+                // a replaced or evicted connection is already removed
+                let removed = {
+                    let mut clients = peer.clients.write().await;
+                    if clients.get(&pubkey).is_some_and(|c| Arc::ptr_eq(c, &cl)) {
+                        clients.remove(&pubkey);
+                        true
+                    } else {
+                        false
+                    }
+                };
                 // peer.known.lock().await.swap_remove(&pubkey);
-                peer.trigger_leave(pubkey, addr, reconn_cnt);
+                if removed {
+                    // a persistent peer is dialed again at its configured address, whichever side opened the connection
+                    let (addr, redial) = match cl.persistent_addr() {
+                        Some(persistent) => (persistent, Redial::Forever),
+                        None => (addr, redial),
+                    };
+                    peer.connection_lost(pubkey, addr, redial).await;
+                }
             });
         } else {
             return Err(Error::protocol(
@@ -448,33 +1395,102 @@ impl Peer {
         Ok(())
     }
 
-    fn trigger_leave(&self, pubkey: PubKeyBytes, addr: TargetAddr<'static>, reconn_cnt: u32) {
+    /// Lowers the score of the peer for the error. A peer which is banned by this, is disconnected.
+    async fn misbehaved(&self, cl: &ClientInfo, host: &Option<Banned>, e: &Error) {
+        if self.bans.lock().await.penalize(&cl.pubkey, host.clone(), e.kind) {
+            tracing::warn!("{} banned {} {:?}", self.pubhex, hex::encode(cl.pubkey), host);
+            cl.close();
+        }
+    }
+
+    /// Schedules a reconnect, and the leave of the peer, if it does not come back in time. A short outage does
+    /// not reach the rest of the network.
+    async fn connection_lost(&self, pubkey: PubKeyBytes, addr: TargetAddr<'static>, redial: Redial) {
+        self.schedule_reconnect(addr, Some(pubkey), redial).await;
+
         let peer = self.me.upgrade().unwrap();
         tokio::spawn(async move {
-            if reconn_cnt > 0 {
-                tokio::time::sleep(peer.cfg.relationship.reconnect).await;
-                tracing::debug!(
-                    "{} attempt reconnect\n{} {}",
-                    peer.pubhex,
-                    hex::encode(&pubkey),
-                    target_addr_to_string(addr.to_owned())
-                );
-                if !peer.to_accept.is_closed() {
-                    if let Err(e) = peer.to_accept.send((addr, reconn_cnt - 1, Some(pubkey.clone()))).await {
-                        tracing::error!("{} {}", peer.pubhex, e);
-                    }
-                }
-            } else {
-                tracing::debug!("{} sent leave: {}", peer.pubhex, hex::encode(&pubkey));
-                peer.known.write().await.remove(&pubkey);
-                if let Err(e) = peer.broadcast(Message::Leave { pubkey }).await {
-                    tracing::error!("{} sent leave: {}", peer.pubhex, e);
+            time::sleep(peer.cfg.relationship.leave_after).await;
+            if !peer.clients.read().await.contains_key(&pubkey) {
+                if let Err(e) = peer.observed_leave(pubkey).await {
+                    tracing::error!("{} {}", peer.pubhex, e);
                 }
             }
         });
     }
 
-    async fn create_next_block(&self, blkch: &mut Blockchain) -> Result<Block> {
+    async fn schedule_reconnect(&self, addr: TargetAddr<'static>, pubkey: Option<PubKeyBytes>, redial: Redial) {
+        let target = target_addr_to_string(addr.to_owned());
+        if self.reconnects.lock().await.schedule(addr, pubkey, redial) {
+            tracing::debug!("{} schedule reconnect {}", self.pubhex, target);
+            self.reconnect_changed.notify_one();
+        } else if redial != Redial::Never {
+            tracing::debug!("{} give up reconnect {}", self.pubhex, target);
+        }
+    }
+
+    /// Dials the scheduled reconnects, when they are due.
+    async fn reconnect(&self) -> Result<()> {
+        let mut rx_shutdown = self.to_shutdown.subscribe();
+
+        loop {
+            let next = self.reconnects.lock().await.next_due();
+            let wait = next
+                .map(time::Instant::from_std)
+                .unwrap_or_else(|| time::Instant::now() + FAR_FUTURE);
+
+            select! {
+                _ = rx_shutdown.recv() => { break; }
+                _ = self.reconnect_changed.notified() => {}
+                _ = time::sleep_until(wait) => {
+                    let due = self.reconnects.lock().await.due();
+                    for dial in due {
+                        tracing::debug!("{} attempt reconnect {:?}", self.pubhex, dial.0);
+                        ex!(self.to_accept.send(dial).await, sync);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Signs and sends the leave of a neighbour, which we lost, if it is still online for us.
+    async fn observed_leave(&self, pubkey: PubKeyBytes) -> Result<()> {
+        let record = {
+            let mut known = self.known.write().await;
+            let Some(announcement) = known.announcement(&pubkey) else {
+                return Ok(());
+            };
+            let record = ex!(
+                MemberRecord::observed_leave(
+                    &self.network,
+                    pubkey,
+                    announcement.timestamp,
+                    &self.pubkey,
+                    &self.prikey
+                ),
+                source
+            );
+            if !ex!(known.apply(&record, &self.network), source) {
+                return Ok(());
+            }
+            record
+        };
+
+        tracing::debug!("{} sent leave: {}", self.pubhex, hex::encode(pubkey));
+        self.broadcast(Message::Leave { record }).await;
+
+        Ok(())
+    }
+
+    /// Creates the next block of the gathered data, if there is any. The block is compressed while the chain is not
+    /// locked, it is only added if no other block came in the meantime.
+    async fn create_next_block(&self) -> Result<Option<Block>> {
+        let mut blkch = self.blockchain.write().await;
+        if blkch.cache.is_empty() {
+            return Ok(None);
+        }
         tracing::info!("{} create next block", self.pubhex);
 
         if let Some(oncb) = &mut *self.on_block_creation.lock().await {
@@ -482,6 +1498,12 @@ impl Peer {
             blkch.cache = ex!(oncb(cache).await, source);
         }
 
+        {
+            // data of peers denied in the meantime, would make the block invalid for the others
+            let access = self.access.read().await;
+            blkch.cache.retain(|_, d| access.is_allowed(&d.author));
+        }
+
         // blkch.next_authors
         let Algorithm::Riddle {
             next_candidates,
@@ -490,7 +1512,8 @@ impl Peer {
         let (next_author, all_offline) = {
             let mut nexts = Vec::new();
             let known = self.known.read().await;
-            let mut k: Vec<PubKeyBytes> = known.iter().cloned().collect();
+            let access = self.access.read().await;
+            let mut k: Vec<PubKeyBytes> = known.iter().filter(|k| access.is_allowed(k)).cloned().collect();
 
             let mut all_offline = true;
             for a in blkch.next_authors.iter() {
@@ -508,10 +1531,13 @@ impl Peer {
         };
         let blk = ex!(blkch.create_block(next_author, self.pubkey, &self.prikey), source);
 
+        drop(blkch);
+
+        let created = ex!(VerifiedBlock::created(blk.clone()).await, source);
         let force = *forced_restart && all_offline;
-        ex!(blkch.add_block(blk.clone(), force), source);
+        ex!(self.blockchain.write().await.add_block(created, force).await, source);
 
-        Ok(blk)
+        Ok(Some(blk))
     }
 
     async fn check_is_me_next(&self) -> bool {
@@ -551,13 +1577,8 @@ impl Peer {
                     loop {
                         select! {
                             _ = time::sleep(peer.cfg.data_gather_time) => {
-                                let mut blkch = peer.blockchain.write().await;
-                                if !blkch.cache.is_empty() {
-                                    let block = ex!(peer.create_next_block(&mut blkch).await, source);
-                                    ex!(
-                                        peer.broadcast(Message::ShareBlock { block: block.clone() }).await,
-                                        source
-                                    );
+                                if let Some(block) = ex!(peer.create_next_block().await, source) {
+                                    peer.broadcast(Message::ShareBlock { block: block.clone() }).await;
                                     if peer.last_block_tx.receiver_count() > 0 {
                                         ex!(peer.last_block_tx.send(block), sync);
                                     }
@@ -568,11 +1589,11 @@ impl Peer {
                         }
                     }
 
-                    peer.is_block_gathering.store(false, Ordering::SeqCst);
-
                     Ok(())
                 }
                 .await;
+                // a failed block does not stop the gathering for good
+                peer.is_block_gathering.store(false, Ordering::SeqCst);
 
                 if let Err(e) = res {
                     tracing::error!("{} {}", peer.pubhex, e);
@@ -583,9 +1604,36 @@ impl Peer {
 
     async fn on_message(&self, msg: Message, cl: Arc<ClientInfo>) -> Result<()> {
         match msg {
-            Message::Greeting { .. } => {
+            Message::Handshake { .. } | Message::Greeting { .. } => {
                 tracing::error!("{} we should never get a second greeting", self.pubhex);
             }
+            Message::Rekey { .. } => {
+                tracing::error!("{} rekey is handled by the client reader", self.pubhex);
+            }
+            Message::Ping { nonce, .. } => {
+                ex!(msg.verify_keepalive(&self.network, &cl.pubkey), source);
+                ex!(
+                    cl.write(ex!(Message::pong(&self.network, &self.prikey, nonce), source))
+                        .await,
+                    source
+                );
+            }
+            Message::Addresses { records } => {
+                if cl.addresses_received() {
+                    ex!(self.on_addresses(records, cl).await, source);
+                } else {
+                    tracing::debug!("{} unexpected addresses {}", self.pubhex, hex::encode(cl.pubkey));
+                }
+            }
+            Message::RequestAddresses { count, exclude } => {
+                ex!(self.on_request_addresses(count, exclude, cl).await, source);
+            }
+            Message::Pong { nonce, .. } => {
+                ex!(msg.verify_keepalive(&self.network, &cl.pubkey), source);
+                if !cl.pong_received(nonce) {
+                    tracing::debug!("{} unexpected pong {}", self.pubhex, hex::encode(cl.pubkey));
+                }
+            }
             Message::ShareData { data } => {
                 ex!(self.on_share_data(data, cl).await, source);
             }
@@ -593,7 +1641,7 @@ impl Peer {
                 ex!(self.on_request_blocks(start, cl).await, source);
             }
             Message::RequestedBlock { block } => {
-                ex!(self.on_requested_block(block).await, source);
+                ex!(self.on_requested_block(block, cl).await, source);
             }
             Message::ShareBlock { block } => {
                 ex!(self.on_share_block(block, cl).await, source);
@@ -602,13 +1650,13 @@ impl Peer {
                 ex!(self.on_request_neighbours(count, exclude, cl).await, source);
             }
             Message::IntroduceNeighbours { neighbours } => {
-                ex!(self.on_introduce_neighbours(neighbours).await, source);
+                ex!(self.on_introduce_neighbours(neighbours, cl).await, source);
             }
-            Message::Announce { pubkey } => {
-                ex!(self.on_announce(pubkey, cl).await, source);
+            Message::Announce { record } => {
+                ex!(self.on_announce(record, cl).await, source);
             }
-            Message::Leave { pubkey } => {
-                ex!(self.on_leave(pubkey, cl).await, source);
+            Message::Leave { record } => {
+                ex!(self.on_leave(record, cl).await, source);
             }
         }
 
@@ -623,9 +1671,9 @@ impl Peer {
             let msg = Message::ShareData { data };
 
             if let Some(cl) = cl {
-                ex!(self.broadcast_except(msg, &cl).await, source);
+                self.broadcast_except(msg, &cl).await;
             } else {
-                ex!(self.broadcast(msg).await, source);
+                self.broadcast(msg).await;
             }
         }
 
@@ -634,6 +1682,10 @@ impl Peer {
 
     async fn on_share_data(&self, data: Data, cl: Arc<ClientInfo>) -> Result<()> {
         tracing::info!("{} got data", self.pubhex);
+        if !self.access.read().await.is_allowed(&data.author) {
+            return Err(Error::denied(line!(), module_path!(), &data.author));
+        }
+        ex!(data.verify(&self.network), source);
 
         self.perform_share(data, Some(cl)).await
     }
@@ -644,23 +1696,60 @@ impl Peer {
             self.pubhex,
             start.map(hex::encode).unwrap_or_default()
         );
-        let blk_it = self.blockchain.read().await.get_blocks(start);
-        for block in blk_it {
+        let mut blocks = self.blockchain.read().await.get_blocks(start);
+        while let Some(block) = blocks.next().await {
             let block = ex!(block, source);
-            ex!(cl.write(&Message::RequestedBlock { block }).await, source);
+            ex!(cl.write(Message::RequestedBlock { block }).await, source);
+        }
+
+        Ok(())
+    }
+
+    /// Checks that the author of the block and all authors of its data are allowed. Only for newly shared blocks:
+    /// the history is synced and replayed as it is, even if its authors were denied later.
+    async fn check_block_access(&self, block: &Block) -> Result<()> {
+        let access = self.access.read().await;
+
+        if !access.is_allowed(&block.author) {
+            return Err(Error::denied(line!(), module_path!(), &block.author));
+        }
+        for d in block.data.iter() {
+            if !access.is_allowed(&d.author) {
+                return Err(Error::denied(line!(), module_path!(), &d.author));
+            }
         }
 
         Ok(())
     }
 
-    async fn on_requested_block(&self, block: Block) -> Result<()> {
+    /// Returns true if none of the next authors is known to be online.
+    async fn next_authors_offline(&self, blkch: &Blockchain) -> bool {
+        let known = self.known.read().await;
+        !blkch.next_authors.iter().any(|a| known.contains(a))
+    }
+
+    async fn on_requested_block(&self, block: Block, cl: Arc<ClientInfo>) -> Result<()> {
+        let Some(advertised) = cl.requested_blocks() else {
+            return Err(Error::protocol(line!(), module_path!(), "blocks were not requested"));
+        };
         tracing::info!("{} got block {}", self.pubhex, hex::encode(block.hash));
 
-        let Algorithm::Riddle { forced_restart, .. } = &self.cfg.algorithm;
-        ex!(
-            self.blockchain.write().await.add_block(block.clone(), *forced_restart),
-            source
-        );
+        // the signatures are checked before the chain is locked for writing
+        let verify = self.blockchain.read().await.verify_block(block.clone());
+        let verified = ex!(verify.await, source);
+
+        {
+            let mut blkch = self.blockchain.write().await;
+            let Algorithm::Riddle { forced_restart, .. } = &self.cfg.algorithm;
+            // the history up to the advertised height is replayed as it is, newer blocks are checked like shared ones
+            let force = if blkch.count < advertised {
+                *forced_restart
+            } else {
+                ex!(self.check_block_access(&block).await, source);
+                *forced_restart && self.next_authors_offline(&blkch).await
+            };
+            ex!(blkch.add_block(verified, force).await, source);
+        }
         if self.last_block_tx.receiver_count() > 0 {
             ex!(self.last_block_tx.send(block), sync);
         }
@@ -669,35 +1758,35 @@ impl Peer {
     }
 
     async fn on_share_block(&self, block: Block, cl: Arc<ClientInfo>) -> Result<()> {
+        ex!(self.check_block_access(&block).await, source);
+
+        let verify = {
+            let blkch = self.blockchain.read().await;
+            if blkch.last == Some(block.hash) {
+                // we get the same block again, just ignore it
+                return Ok(());
+            }
+            blkch.verify_block(block.clone())
+        };
+        // the signatures are checked before the chain is locked for writing
+        let verified = ex!(verify.await, source);
+
         {
             let mut blkch = self.blockchain.write().await;
-            let last = blkch.last;
-            if last.map(|n| n == block.hash).unwrap_or(false) {
-                // we get the same block again, just ignore it
+            if blkch.last == Some(block.hash) {
+                // another connection brought the same block in the meantime
                 return Ok(());
             }
 
             tracing::info!("{} share block {}", self.pubhex, hex::encode(block.hash));
-            let mut all_offline = true;
-            {
-                let known = self.known.read().await;
-                for a in blkch.next_authors.iter() {
-                    if known.contains(a) {
-                        all_offline = false;
-                        break;
-                    }
-                }
-            }
+            let all_offline = self.next_authors_offline(&blkch).await;
 
             let Algorithm::Riddle { forced_restart, .. } = &self.cfg.algorithm;
-            ex!(blkch.add_block(block.clone(), *forced_restart && all_offline), source);
+            ex!(blkch.add_block(verified, *forced_restart && all_offline).await, source);
         }
 
-        ex!(
-            self.broadcast_except(Message::ShareBlock { block: block.clone() }, &cl)
-                .await,
-            source
-        );
+        self.broadcast_except(Message::ShareBlock { block: block.clone() }, &cl)
+            .await;
 
         if self.last_block_tx.receiver_count() > 0 {
             ex!(self.last_block_tx.send(block), sync);
@@ -749,7 +1838,7 @@ impl Peer {
 
         if !to_share.is_empty() {
             ex!(
-                cl.write(&Message::IntroduceNeighbours { neighbours: to_share }).await,
+                cl.write(Message::IntroduceNeighbours { neighbours: to_share }).await,
                 source
             );
         }
@@ -757,51 +1846,106 @@ impl Peer {
         Ok(())
     }
 
-    async fn on_introduce_neighbours(&self, neighbours: Vec<(PubKeyBytes, String)>) -> Result<()> {
-        // let to_connect: Vec<String> = neighbours.iter().map(|x| hex::encode(x.0)).collect();
-        // let to_connect = to_connect.join("\n");
-        // tracing::info!(
-        //     "{} introduce new neighbours {}\n{}",
-        //     self.pubhex,
-        //     neighbours.len(),
-        //     to_connect
-        // );
-        let cnt = self.clients.read().await.len();
-        if cnt < self.cfg.relationship.count as _ {
-            let to_add = self.cfg.relationship.count as usize - cnt;
-            for (k, n) in neighbours.into_iter().take(to_add) {
-                if !self.clients.read().await.contains_key(&k) {
-                    ex!(
-                        self.to_accept
-                            .send((ex!(n.into_target_addr(), sync), self.cfg.relationship.retry, None))
-                            .await,
-                        sync
-                    );
+    /// The introduced neighbours are only added to the address book, so the connections are chosen from all
+    /// sources and not just by the neighbour which answered first.
+    async fn on_introduce_neighbours(&self, neighbours: Vec<(PubKeyBytes, String)>, cl: Arc<ClientInfo>) -> Result<()> {
+        let records = neighbours
+            .into_iter()
+            .map(|(pubkey, addr)| AddressRecord {
+                pubkey,
+                addrs: vec![addr],
+                // only our own connections count as seen
+                last_seen: 0,
+                failures: 0,
+            })
+            .collect();
+
+        self.on_addresses(records, cl).await
+    }
+
+    async fn on_addresses(&self, records: Vec<AddressRecord>, cl: Arc<ClientInfo>) -> Result<()> {
+        {
+            let access = self.access.read().await;
+            let mut addresses = self.addresses.lock().await;
+            // a peer can not flood the address book with a single message
+            for record in records.into_iter().take(self.cfg.addresses.share as _) {
+                if record.pubkey != self.pubkey && access.is_allowed(&record.pubkey) {
+                    addresses.learn(record, &cl.pubkey);
                 }
             }
         }
 
+        // the overlay heals itself, by connecting to the peers we just learned about
+        let heal = {
+            let mut healed = self.healed.lock().await;
+            let heal = healed.is_none_or(|t| t.elapsed() >= HEAL_INTERVAL);
+            if heal {
+                *healed = Some(time::Instant::now());
+            }
+            heal
+        };
+        if heal {
+            ex!(self.connect_known().await, source);
+        }
+
+        Ok(())
+    }
+
+    async fn on_request_addresses(&self, count: u32, exclude: Vec<PubKeyBytes>, cl: Arc<ClientInfo>) -> Result<()> {
+        let mut exclude: HashSet<PubKeyBytes> = exclude.into_iter().collect();
+        exclude.insert(cl.pubkey);
+
+        let records = self.addresses.lock().await.sample(
+            count.min(self.cfg.addresses.share) as _,
+            &exclude,
+            self.cfg.addresses.max_age,
+        );
+        if !records.is_empty() {
+            ex!(cl.write(Message::Addresses { records }).await, source);
+        }
+
         Ok(())
     }
 
-    async fn on_announce(&self, pubkey: PubKeyBytes, cl: Arc<ClientInfo>) -> Result<()> {
-        if self.pubkey != pubkey {
-            let new = self.known.write().await.insert(pubkey);
+    async fn on_announce(&self, record: MemberRecord, cl: Arc<ClientInfo>) -> Result<()> {
+        if self.access.read().await.is_allowed(&record.subject) {
+            let new = ex!(self.known.write().await.apply(&record, &self.network), source);
 
             if new {
-                ex!(self.broadcast_except(Message::Announce { pubkey }, &cl).await, source);
+                self.broadcast_except(Message::Announce { record }, &cl).await;
             }
         }
 
         Ok(())
     }
 
-    async fn on_leave(&self, pubkey: PubKeyBytes, cl: Arc<ClientInfo>) -> Result<()> {
-        if self.pubkey != pubkey {
-            let known = self.known.write().await.remove(&pubkey);
+    async fn on_leave(&self, record: MemberRecord, cl: Arc<ClientInfo>) -> Result<()> {
+        if record.subject == self.pubkey {
+            // someone considers us gone, tell everyone we are still here
+            ex!(record.verify(&self.network), source);
+            let current = self.announcement.read().await.as_ref().map(|a| a.timestamp);
+            if current.map(|t| record.version().0 >= t).unwrap_or(false) {
+                ex!(self.reannounce().await, source);
+            }
+            return Ok(());
+        }
+
+        let left = ex!(self.known.write().await.apply(&record, &self.network), source);
 
-            if known {
-                ex!(self.broadcast_except(Message::Leave { pubkey }, &cl).await, source);
+        if left {
+            let subject = record.subject;
+            let observed = record.signer != subject;
+            self.broadcast_except(Message::Leave { record }, &cl).await;
+
+            // the leave of a neighbour is only taken over, if we do not hear from the peer either
+            if observed {
+                let peer = self.me.upgrade().unwrap();
+                tokio::spawn(async move {
+                    time::sleep(peer.cfg.relationship.leave_after).await;
+                    if !peer.clients.read().await.contains_key(&subject) && peer.known.write().await.confirm(&subject) {
+                        tracing::debug!("{} confirmed leave: {}", peer.pubhex, hex::encode(subject));
+                    }
+                });
             }
         }
 
@@ -810,7 +1954,12 @@ impl Peer {
 
     /// Returns the public key.
     pub fn pubkey(&self) -> PubKeyBytes {
-        self.pubkey.clone()
+        self.pubkey
+    }
+
+    /// Returns the id of the network, derived from the network name in the config.
+    pub fn network(&self) -> HashBytes {
+        self.network
     }
 
     /// Returns the hex representation of the public key.
@@ -824,9 +1973,121 @@ impl Peer {
         cl.keys().cloned().collect()
     }
 
+    /// Returns the negotiated protocol version and the common capabilities of a directly connected peer.
+    pub async fn client_protocol(&self, pubkey: &PubKeyBytes) -> Option<(u16, Capabilities)> {
+        self.clients
+            .read()
+            .await
+            .get(pubkey)
+            .map(|cl| (cl.protocol, cl.capabilities))
+    }
+
+    /// Returns the smoothed round trip time to a directly connected peer, once it answered a ping.
+    pub async fn client_rtt(&self, pubkey: &PubKeyBytes) -> Option<Duration> {
+        self.clients.read().await.get(pubkey).and_then(|cl| cl.rtt())
+    }
+
+    /// Returns the smoothed round trip times of all directly connected peers, which answered a ping.
+    pub async fn client_rtts(&self) -> HashMap<PubKeyBytes, Duration> {
+        let cls = self.clients.read().await;
+        cls.iter().filter_map(|(k, cl)| cl.rtt().map(|rtt| (*k, rtt))).collect()
+    }
+
+    /// Closes the connection to a peer and forgets it, if it is no longer allowed.
+    async fn enforce_access(&self, pubkey: &PubKeyBytes) {
+        if !self.access.read().await.is_allowed(pubkey) {
+            if let Some(cl) = self.clients.read().await.get(pubkey) {
+                cl.close();
+            }
+            self.known.write().await.remove(pubkey);
+        }
+    }
+
+    /// Adds a peer to the allow list. Returns false if it was already in the list.
+    pub async fn allow_peer(&self, pubkey: PubKeyBytes) -> bool {
+        self.access.write().await.allow(pubkey)
+    }
+
+    /// Removes a peer from the allow list. In permissioned mode an existing connection to the peer is closed.
+    /// Returns false if it was not in the list.
+    pub async fn revoke_peer(&self, pubkey: &PubKeyBytes) -> bool {
+        let removed = self.access.write().await.revoke(pubkey);
+        self.enforce_access(pubkey).await;
+        removed
+    }
+
+    /// Adds a peer to the deny list and closes an existing connection to it. Returns false if it was already in
+    /// the list.
+    pub async fn deny_peer(&self, pubkey: PubKeyBytes) -> bool {
+        let added = self.access.write().await.deny(pubkey);
+        self.enforce_access(&pubkey).await;
+        added
+    }
+
+    /// Removes a peer from the deny list. Returns false if it was not in the list.
+    pub async fn undeny_peer(&self, pubkey: &PubKeyBytes) -> bool {
+        self.access.write().await.undeny(pubkey)
+    }
+
+    /// Returns true if the peer may connect and author data and blocks.
+    pub async fn is_allowed(&self, pubkey: &PubKeyBytes) -> bool {
+        self.access.read().await.is_allowed(pubkey)
+    }
+
+    /// Returns the public keys in the allow list.
+    pub async fn allowed_peers(&self) -> Vec<PubKeyBytes> {
+        self.access.read().await.allowed()
+    }
+
+    /// Returns the public keys in the deny list.
+    pub async fn denied_peers(&self) -> Vec<PubKeyBytes> {
+        self.access.read().await.denied()
+    }
+
+    /// Returns the misbehaviour score of a peer.
+    pub async fn score(&self, pubkey: &PubKeyBytes) -> u32 {
+        self.bans.lock().await.score(pubkey)
+    }
+
+    /// Returns all active bans, with the time they still last.
+    pub async fn bans(&self) -> Vec<(Banned, Duration)> {
+        self.bans.lock().await.list()
+    }
+
+    /// Bans a public key or host for the given time, and closes the connections affected by it.
+    pub async fn ban(&self, what: Banned, time: Duration) {
+        self.bans.lock().await.ban(what.clone(), time);
+
+        if let Banned::Pubkey(pubkey) = &what {
+            if let Some(cl) = self.clients.read().await.get(pubkey) {
+                cl.close();
+            }
+        }
+    }
+
+    /// Lifts a ban. Returns false if there was no such ban.
+    pub async fn unban(&self, what: &Banned) -> bool {
+        self.bans.lock().await.unban(what)
+    }
+
+    /// Lifts all bans and resets all scores.
+    pub async fn clear_bans(&self) {
+        self.bans.lock().await.clear();
+    }
+
+    /// Returns the records of the address book.
+    pub async fn address_book(&self) -> Vec<AddressRecord> {
+        self.addresses.lock().await.records()
+    }
+
+    /// Returns the connections, which wait to be dialed again.
+    pub async fn pending_reconnects(&self) -> Vec<PendingReconnect> {
+        self.reconnects.lock().await.list()
+    }
+
     /// Returns all known public keys.
     pub async fn known_pubkeys(&self) -> HashSet<PubKeyBytes> {
-        self.known.read().await.clone()
+        self.known.read().await.pubkeys()
     }
 
     /// Sets a callback that is only called on the peer which creates block.
@@ -844,21 +2105,25 @@ impl Peer {
     /// Try to connect to another peer.
     pub async fn connect(&self, addr: TargetAddr<'static>) -> Result<()> {
         tracing::info!("{} connect to {:?}", self.pubhex, addr);
-        ex!(
-            self.to_accept.send((addr, self.cfg.relationship.retry, None)).await,
-            sync
-        );
+        ex!(self.to_accept.send((addr, Redial::Limited, None)).await, sync);
+        Ok(())
+    }
+
+    /// Connects to another peer, and keeps reconnecting to it forever, whenever the connection is lost.
+    pub async fn connect_persistent(&self, addr: TargetAddr<'static>) -> Result<()> {
+        tracing::info!("{} connect persistent to {:?}", self.pubhex, addr);
+        ex!(self.to_accept.send((addr, Redial::Forever, None)).await, sync);
         Ok(())
     }
 
     pub fn create_data(&self, data: Vec<u8>) -> Result<Data> {
-        let data = ex!(Data::new(data, &self.pubkey, &self.prikey), source);
+        let data = ex!(Data::new(data, &self.pubkey, &self.prikey, &self.network), source);
         Ok(data)
     }
 
     /// Share data in the network.
     pub async fn share(&self, data: Vec<u8>) -> Result<()> {
-        let data = ex!(Data::new(data, &self.pubkey, &self.prikey), source);
+        let data = ex!(Data::new(data, &self.pubkey, &self.prikey, &self.network), source);
         self.perform_share(data, None).await
     }
 
@@ -867,12 +2132,68 @@ impl Peer {
         self.last_block_tx.subscribe()
     }
 
-    /// Returns an iterator over all blocks, from start to last.
-    pub async fn block_iter(&self) -> BlockIterator {
+    /// Returns a stream over all blocks, from start to last.
+    pub async fn block_iter(&self) -> BlockStream {
         self.blockchain.read().await.get_blocks(None)
     }
 
+    /// Returns the block at the height, the root block has height 0.
+    pub async fn get_block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        self.blockchain.read().await.get_block_by_height(height).await
+    }
+
+    /// Returns the block with the hash.
+    pub async fn get_block_by_hash(&self, hash: &HashBytes) -> Result<Option<Block>> {
+        self.blockchain.read().await.get_block_by_hash(hash).await
+    }
+
+    /// Checks if the block with the hash is part of the chain.
+    pub async fn contains_block(&self, hash: &HashBytes) -> Result<bool> {
+        self.blockchain.read().await.contains_block(hash).await
+    }
+
+    /// Returns where the data with the signature is stored in the chain.
+    pub async fn find_data(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        self.blockchain.read().await.find_data(sign).await
+    }
+
+    /// Returns where the data of the author is stored in the chain, the newest first. Skips the first `skip` ones
+    /// and returns at most `limit`.
+    pub async fn data_by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        self.blockchain.read().await.data_by_author(author, skip, limit).await
+    }
+
+    /// Returns what was discarded of the blockchain on startup, because it was not written completely.
+    pub async fn chain_recovery(&self) -> Recovery {
+        self.blockchain.read().await.recovery
+    }
+
+    /// Stops the peer. The address book is saved right away, unless it is busy; `shutdown_async` waits for it.
     pub fn shutdown(&self) -> Result<()> {
+        // saved right away, so the data folder is not written anymore once we return
+        match self.addresses.try_lock() {
+            Ok(mut addresses) => {
+                if let Err(e) = addresses.close() {
+                    tracing::error!("{} save addresses: {}", self.pubhex, e);
+                }
+            }
+            Err(_) => tracing::warn!("{} address book is busy, not saved", self.pubhex),
+        }
+
+        self.stop()
+    }
+
+    /// Stops the peer like `shutdown`, but waits until the address book is saved.
+    pub async fn shutdown_async(&self) -> Result<()> {
+        if let Err(e) = self.addresses.lock().await.close() {
+            tracing::error!("{} save addresses: {}", self.pubhex, e);
+        }
+
+        self.stop()
+    }
+
+    /// Tells all tasks of the peer to stop.
+    fn stop(&self) -> Result<()> {
         if self.to_shutdown.receiver_count() > 0 {
             ex!(self.to_shutdown.send(true), sync);
         } else {
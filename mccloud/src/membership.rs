@@ -0,0 +1,283 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use hashbrown::{HashMap, HashSet};
+use k256::{
+    ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier},
+    schnorr::{Signature, SigningKey, VerifyingKey},
+    sha2::{Digest, Sha256},
+    SecretKey,
+};
+
+use crate::{
+    config::Membership,
+    error::{Error, Result},
+    ex, HashBytes, PubKeyBytes, SignBytes,
+};
+
+const MEMBER_LABEL: &[u8] = b"mccloud member";
+
+/// Returns the current unix time in milliseconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// A signed statement, that a peer joined or left the network.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct MemberRecord {
+    /// The peer the record is about.
+    pub subject: PubKeyBytes,
+    /// True for an announcement, false for a leave.
+    pub online: bool,
+    /// When the record was signed, in unix milliseconds.
+    pub timestamp: u64,
+    /// The evidence of a leave, which a neighbour observed: the timestamp of the announcement it revokes.
+    pub revokes: Option<u64>,
+    /// Either the subject itself, or the neighbour which observed the leave.
+    pub signer: PubKeyBytes,
+    pub sign: SignBytes,
+}
+
+impl MemberRecord {
+    /// Creates the announcement of a peer, signed by itself.
+    pub fn announce(network: &HashBytes, pubkey: &PubKeyBytes, secret: &SecretKey) -> Result<Self> {
+        Self::new(network, *pubkey, true, None, pubkey, secret)
+    }
+
+    /// Creates the leave of a peer, signed by itself.
+    pub fn leave(network: &HashBytes, pubkey: &PubKeyBytes, secret: &SecretKey) -> Result<Self> {
+        Self::new(network, *pubkey, false, None, pubkey, secret)
+    }
+
+    /// Creates the leave of a neighbour, which is no longer reachable. It revokes the announcement with the given
+    /// timestamp.
+    pub fn observed_leave(
+        network: &HashBytes,
+        subject: PubKeyBytes,
+        revokes: u64,
+        pubkey: &PubKeyBytes,
+        secret: &SecretKey,
+    ) -> Result<Self> {
+        Self::new(network, subject, false, Some(revokes), pubkey, secret)
+    }
+
+    fn new(
+        network: &HashBytes,
+        subject: PubKeyBytes,
+        online: bool,
+        revokes: Option<u64>,
+        signer: &PubKeyBytes,
+        secret: &SecretKey,
+    ) -> Result<Self> {
+        let mut record = Self {
+            subject,
+            online,
+            timestamp: now(),
+            revokes,
+            signer: *signer,
+            sign: [0u8; 64],
+        };
+
+        let signing = SigningKey::from(secret);
+        let sign = ex!(signing.sign_prehash(&record.hash(network)), encrypt);
+        record.sign = sign.to_bytes();
+
+        Ok(record)
+    }
+
+    fn hash(&self, network: &HashBytes) -> HashBytes {
+        let mut sha = Sha256::new();
+        sha.update(MEMBER_LABEL);
+        sha.update(network);
+        sha.update(self.subject);
+        sha.update([self.online as u8]);
+        sha.update(self.timestamp.to_le_bytes());
+        if let Some(revokes) = self.revokes {
+            sha.update(revokes.to_le_bytes());
+        }
+        sha.update(self.signer);
+
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&sha.finalize());
+        hash
+    }
+
+    /// Verifies the signature for the given network.
+    pub fn verify(&self, network: &HashBytes) -> Result<()> {
+        let verifier = ex!(VerifyingKey::from_bytes(&self.signer[1..]), encrypt);
+        let sign = ex!(Signature::try_from(&self.sign[..]), encrypt);
+        ex!(verifier.verify_prehash(&self.hash(network), &sign), encrypt);
+
+        Ok(())
+    }
+
+    /// The order of the records about the same peer. A leave observed by a neighbour is ordered right after the
+    /// announcement it revokes, so the next announcement of the peer always wins, regardless of the clock of the
+    /// neighbour.
+    pub fn version(&self) -> (u64, bool) {
+        (self.revokes.unwrap_or(self.timestamp), !self.online)
+    }
+}
+
+///
+/// The peers of the network which are online, built from the latest membership record of each peer.
+///
+/// Leaves are kept as tombstones, so an older announcement can not bring a peer back. A leave observed by another
+/// neighbour is only pending, until we confirmed it ourselves.
+///
+pub struct Members {
+    cfg: Membership,
+    /// Our own public key. Records about ourselves are not kept.
+    pubkey: PubKeyBytes,
+    records: HashMap<PubKeyBytes, MemberRecord>,
+    /// The leaves other neighbours observed, which wait for our confirmation.
+    pending: HashMap<PubKeyBytes, MemberRecord>,
+}
+
+impl Members {
+    pub fn new(cfg: Membership, pubkey: PubKeyBytes) -> Self {
+        Self {
+            cfg,
+            pubkey,
+            records: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the peer is online.
+    pub fn contains(&self, pubkey: &PubKeyBytes) -> bool {
+        self.records.get(pubkey).map(|r| r.online).unwrap_or(false)
+    }
+
+    /// Returns the public keys of all peers which are online.
+    pub fn iter(&self) -> impl Iterator<Item = &PubKeyBytes> {
+        self.records.values().filter(|r| r.online).map(|r| &r.subject)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn pubkeys(&self) -> HashSet<PubKeyBytes> {
+        self.iter().cloned().collect()
+    }
+
+    /// Returns the current announcement of a peer, if it is online.
+    pub fn announcement(&self, pubkey: &PubKeyBytes) -> Option<&MemberRecord> {
+        self.records.get(pubkey).filter(|r| r.online)
+    }
+
+    /// Returns the latest record of a peer, which might be a leave.
+    pub fn record(&self, pubkey: &PubKeyBytes) -> Option<&MemberRecord> {
+        self.records.get(pubkey)
+    }
+
+    /// Returns the announcements of all peers which are online.
+    pub fn announcements(&self) -> Vec<MemberRecord> {
+        self.records.values().filter(|r| r.online).cloned().collect()
+    }
+
+    ///
+    /// Verifies a record and applies it, if it is newer than the latest record of the peer. Returns true if the
+    /// record was applied and should be passed on.
+    ///
+    /// A forged record is an error. Records about ourselves, and records which are stale, ahead of our clock,
+    /// already known or revoke an announcement we do not have, are ignored. The signature is only checked for
+    /// records which would be applied, because every neighbour relays the same records again.
+    ///
+    /// A leave which another neighbour observed is kept pending, and only applied by `confirm`. Any member could
+    /// sign one, so the peer gets the time to answer it with a new announcement.
+    ///
+    pub fn apply(&mut self, record: &MemberRecord, network: &HashBytes) -> Result<bool> {
+        let self_signed = record.signer == record.subject;
+        if record.online && !self_signed {
+            return Err(Error::protocol(
+                line!(),
+                module_path!(),
+                "announcement is not signed by its subject",
+            ));
+        }
+        if self_signed == record.revokes.is_some() {
+            return Err(Error::protocol(
+                line!(),
+                module_path!(),
+                "only a leave of a neighbour carries evidence",
+            ));
+        }
+
+        if record.subject == self.pubkey {
+            return Ok(false);
+        }
+
+        let now = now();
+        if record.timestamp > now + self.cfg.max_skew.as_millis() as u64
+            || record.timestamp + (self.cfg.max_age.as_millis() as u64) < now
+        {
+            return Ok(false);
+        }
+
+        let current = self.records.get(&record.subject);
+        if current.map(|c| c.version() >= record.version()).unwrap_or(false) {
+            return Ok(false);
+        }
+        let pending = self.pending.get(&record.subject);
+        if pending.map(|p| p.version() >= record.version()).unwrap_or(false) {
+            return Ok(false);
+        }
+
+        if let Some(revokes) = record.revokes {
+            // the neighbour must have seen the announcement it revokes, and be a member itself
+            let revoked = self
+                .announcement(&record.subject)
+                .map(|a| a.timestamp == revokes)
+                .unwrap_or(false);
+            if !revoked || (record.signer != self.pubkey && !self.contains(&record.signer)) {
+                return Ok(false);
+            }
+        }
+
+        ex!(record.verify(network), source);
+        if record.revokes.is_some() && record.signer != self.pubkey {
+            self.pending.insert(record.subject, record.clone());
+        } else {
+            self.records.insert(record.subject, record.clone());
+        }
+
+        Ok(true)
+    }
+
+    /// Applies the pending leave of a peer, which we could not reach ourselves either. It is dropped, if the peer
+    /// announced itself again in the meantime. Returns true if the peer left.
+    pub fn confirm(&mut self, pubkey: &PubKeyBytes) -> bool {
+        let Some(record) = self.pending.remove(pubkey) else {
+            return false;
+        };
+
+        let revoked = self
+            .announcement(pubkey)
+            .map(|a| Some(a.timestamp) == record.revokes)
+            .unwrap_or(false);
+        if revoked {
+            self.records.insert(record.subject, record);
+        }
+
+        revoked
+    }
+
+    /// Forgets a peer locally, without a record.
+    pub fn remove(&mut self, pubkey: &PubKeyBytes) {
+        self.records.remove(pubkey);
+        self.pending.remove(pubkey);
+    }
+
+    /// Removes the records which are older than the maximum age. Peers which did not refresh their announcement
+    /// in time are no longer online.
+    pub fn expire(&mut self) {
+        let oldest = now().saturating_sub(self.cfg.max_age.as_millis() as u64);
+        self.records.retain(|_, r| r.version().0 >= oldest);
+        self.pending.retain(|_, r| r.version().0 >= oldest);
+    }
+}
@@ -1,29 +1,44 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use k256::{
-    schnorr::{
-        signature::{Signer, Verifier},
-        Signature, SigningKey, VerifyingKey,
-    },
+    ecdsa::signature::hazmat::{PrehashSigner, PrehashVerifier},
+    schnorr::{Signature, SigningKey, VerifyingKey},
+    sha2::{Digest, Sha256},
     SecretKey,
 };
 
 use crate::{
+    addressbook::AddressRecord,
     blockchain::{Block, Data},
     error::{Error, Result},
-    ex, HashBytes, PubKeyBytes, SignBytes, Version,
+    ex,
+    membership::MemberRecord,
+    transport::Channel,
+    version::{Capabilities, Protocol},
+    HashBytes, PubKeyBytes, SignBytes, Version,
 };
 
 #[derive(BorshSerialize, BorshDeserialize, Clone)]
 pub enum Message {
+    Handshake {
+        ephemeral: PubKeyBytes,
+    },
     Greeting {
+        /// The id of the network the peer belongs to.
+        network: HashBytes,
         pubkey: PubKeyBytes,
+        /// The signature over the handshake transcript, made with the node key.
+        sign: SignBytes,
         listen: String,
         root: Option<HashBytes>,
         last: Option<HashBytes>,
         count: u64,
         thin: bool,
         version: Version,
-        known: Vec<PubKeyBytes>,
+        protocol: Protocol,
+        /// The signed announcement of the peer itself, missing for thin peers.
+        announce: Option<Box<MemberRecord>>,
+        /// The announcements of the peers we know.
+        known: Vec<MemberRecord>,
     },
     ShareData {
         data: Data,
@@ -45,36 +60,121 @@ pub enum Message {
         neighbours: Vec<(PubKeyBytes, String)>,
     },
     Announce {
-        pubkey: PubKeyBytes,
+        record: MemberRecord,
     },
     Leave {
-        pubkey: PubKeyBytes,
+        record: MemberRecord,
+    },
+    /// Switches the sending direction of a connection to the key of the next epoch.
+    Rekey {
+        epoch: u32,
+    },
+    /// Checks that the peer is alive, and measures the round trip time.
+    Ping {
+        nonce: u64,
+        sign: SignBytes,
+    },
+    /// The answer to a `Ping`, with the same nonce.
+    Pong {
+        nonce: u64,
+        sign: SignBytes,
+    },
+    /// Records of the address book of the sender.
+    Addresses {
+        records: Vec<AddressRecord>,
+    },
+    /// Asks for a sample of the address book, to find peers beyond the direct neighbours.
+    RequestAddresses {
+        count: u32,
+        exclude: Vec<PubKeyBytes>,
     },
 }
 
+const PING_LABEL: &[u8] = b"mccloud ping";
+const PONG_LABEL: &[u8] = b"mccloud pong";
+
+fn keepalive_hash(label: &[u8], network: &HashBytes, nonce: u64) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(label);
+    sha.update(network);
+    sha.update(nonce.to_le_bytes());
+
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sha.finalize());
+    hash
+}
+
 impl Message {
-    // pub fn keepalive(pubkey: &PubKeyBytes, prikey: &SecretKey, count: u64) -> Self {
-    //     let signer = SigningKey::from(prikey);
-    //     let sign: Signature = signer.sign(&count.to_le_bytes());
-    //     let sign_bytes = sign.to_bytes();
+    /// Returns true for messages which carry a whole block.
+    pub fn is_block(&self) -> bool {
+        matches!(self, Self::RequestedBlock { .. } | Self::ShareBlock { .. })
+    }
+
+    /// Returns the capabilities a peer needs, to understand the message.
+    pub fn capabilities(&self) -> Capabilities {
+        match self {
+            Self::Rekey { .. } => Capabilities::REKEY,
+            Self::Ping { .. } | Self::Pong { .. } => Capabilities::PING,
+            Self::Addresses { .. } => Capabilities::ADDRESSES,
+            Self::RequestAddresses { .. } => Capabilities::EXCHANGE,
+            _ => Capabilities::NONE,
+        }
+    }
+
+    /// Returns the channel the message is sent on.
+    pub fn channel(&self) -> Channel {
+        match self {
+            Self::ShareData { .. } | Self::ShareBlock { .. } | Self::Announce { .. } | Self::Leave { .. } => {
+                Channel::Gossip
+            }
+            Self::RequestBlocks { .. } | Self::RequestedBlock { .. } => Channel::Bulk,
+            _ => Channel::Control,
+        }
+    }
+
+    /// Creates a ping, signed with the node key.
+    pub fn ping(network: &HashBytes, secret: &SecretKey, nonce: u64) -> Result<Self> {
+        let signer = SigningKey::from(secret);
+        let sign = ex!(
+            signer.sign_prehash(&keepalive_hash(PING_LABEL, network, nonce)),
+            encrypt
+        );
+
+        Ok(Self::Ping {
+            nonce,
+            sign: sign.to_bytes(),
+        })
+    }
+
+    /// Creates the answer to a ping, signed with the node key.
+    pub fn pong(network: &HashBytes, secret: &SecretKey, nonce: u64) -> Result<Self> {
+        let signer = SigningKey::from(secret);
+        let sign = ex!(
+            signer.sign_prehash(&keepalive_hash(PONG_LABEL, network, nonce)),
+            encrypt
+        );
+
+        Ok(Self::Pong {
+            nonce,
+            sign: sign.to_bytes(),
+        })
+    }
 
-    //     Self::KeepAlive {
-    //         pubkey: *pubkey,
-    //         count,
-    //         sign: sign_bytes,
-    //     }
-    // }
+    /// Verifies that a ping or pong was signed by the given peer. Other messages are not checked.
+    pub fn verify_keepalive(&self, network: &HashBytes, pubkey: &PubKeyBytes) -> Result<()> {
+        let (label, nonce, sign) = match self {
+            Self::Ping { nonce, sign } => (PING_LABEL, nonce, sign),
+            Self::Pong { nonce, sign } => (PONG_LABEL, nonce, sign),
+            _ => return Ok(()),
+        };
 
-    // pub fn verify(&self) -> Result<bool> {
-    //     match self {
-    //         Self::KeepAlive { pubkey, count, sign } => {
-    //             let verifier = ex!(VerifyingKey::from_bytes(&pubkey[1..]), encrypt);
-    //             let signature = ex!(Signature::try_from(&sign[..]), encrypt);
-    //             ex!(verifier.verify(&count.to_le_bytes(), &signature), encrypt);
+        let verifier = ex!(VerifyingKey::from_bytes(&pubkey[1..]), encrypt);
+        let sign = ex!(Signature::try_from(&sign[..]), encrypt);
+        ex!(
+            verifier.verify_prehash(&keepalive_hash(label, network, *nonce), &sign),
+            encrypt
+        );
 
-    //             Ok(true)
-    //         }
-    //         _ => Ok(false),
-    //     }
-    // }
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use hashbrown::HashMap;
+use tokio_socks::TargetAddr;
+
+use crate::{config::Relationship, target_addr_to_string, PubKeyBytes};
+
+/// If a connection is dialed again, after it was lost or could not be established.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Redial {
+    Never,
+    /// Up to `Relationship::retry` times.
+    Limited,
+    /// Forever, for persistent peers.
+    Forever,
+}
+
+/// A connection which waits to be dialed again.
+#[derive(Clone, Debug)]
+pub struct PendingReconnect {
+    pub addr: String,
+    /// The peer, if we were connected before.
+    pub pubkey: Option<PubKeyBytes>,
+    /// How many attempts already failed.
+    pub attempt: u32,
+    pub persistent: bool,
+    /// The time until the next attempt. None while an attempt is running.
+    pub next: Option<Duration>,
+}
+
+struct Pending {
+    addr: TargetAddr<'static>,
+    pubkey: Option<PubKeyBytes>,
+    attempt: u32,
+    redial: Redial,
+    next: Option<Instant>,
+}
+
+///
+/// The connections to dial again, with exponential backoff and jitter.
+///
+pub struct Reconnects {
+    initial: Duration,
+    max: Duration,
+    retry: u32,
+    pending: HashMap<String, Pending>,
+}
+
+impl Reconnects {
+    pub fn new(cfg: &Relationship) -> Self {
+        Self {
+            initial: cfg.reconnect,
+            max: cfg.max_reconnect,
+            retry: cfg.retry,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// The delay before an attempt: doubled for each failed one up to the maximum, and then randomly shortened by
+    /// up to half, so peers which lost each other at the same time do not redial in lockstep.
+    fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.initial.saturating_mul(1 << attempt.min(31)).min(self.max);
+        delay / 2 + delay.mul_f64(rand::random::<f64>() / 2.0)
+    }
+
+    ///
+    /// Schedules the next attempt for a lost connection or a failed attempt. Returns false if the connection is
+    /// given up.
+    ///
+    pub fn schedule(&mut self, addr: TargetAddr<'static>, pubkey: Option<PubKeyBytes>, redial: Redial) -> bool {
+        let key = target_addr_to_string(addr.to_owned());
+        let (attempt, pubkey, redial) = match self.pending.remove(&key) {
+            // an attempt failed
+            Some(p) if p.next.is_none() => (p.attempt + 1, p.pubkey.or(pubkey), p.redial),
+            _ => (0, pubkey, redial),
+        };
+
+        if redial == Redial::Never || (redial == Redial::Limited && attempt >= self.retry) {
+            return false;
+        }
+
+        let next = Instant::now() + self.delay(attempt);
+        self.pending.insert(
+            key,
+            Pending {
+                addr,
+                pubkey,
+                attempt,
+                redial,
+                next: Some(next),
+            },
+        );
+
+        true
+    }
+
+    /// Forgets the pending reconnect of an address, which is connected again or refused the connection.
+    pub fn forget(&mut self, addr: &TargetAddr<'static>) {
+        self.pending.remove(&target_addr_to_string(addr.to_owned()));
+    }
+
+    /// Returns the time of the earliest next attempt.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.pending.values().filter_map(|p| p.next).min()
+    }
+
+    /// Returns the connections which are due to be dialed, and marks their attempt as running.
+    pub fn due(&mut self) -> Vec<(TargetAddr<'static>, Redial, Option<PubKeyBytes>)> {
+        let now = Instant::now();
+        self.pending
+            .values_mut()
+            .filter(|p| p.next.map(|n| n <= now).unwrap_or(false))
+            .map(|p| {
+                p.next = None;
+                (p.addr.to_owned(), p.redial, p.pubkey)
+            })
+            .collect()
+    }
+
+    pub fn list(&self) -> Vec<PendingReconnect> {
+        let now = Instant::now();
+        self.pending
+            .iter()
+            .map(|(addr, p)| PendingReconnect {
+                addr: addr.clone(),
+                pubkey: p.pubkey,
+                attempt: p.attempt,
+                persistent: p.redial == Redial::Forever,
+                next: p.next.map(|n| n.saturating_duration_since(now)),
+            })
+            .collect()
+    }
+}
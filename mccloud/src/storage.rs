@@ -0,0 +1,279 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::task::JoinHandle;
+
+use crate::{
+    blockchain::Block,
+    config::{Config, StorageKind},
+    error::{Error, Result},
+    ex, HashBytes, PubKeyBytes, SignBytes,
+};
+
+mod dataindex;
+mod file;
+mod hashindex;
+mod memory;
+#[cfg(feature = "redb")]
+mod redb;
+
+pub use file::FileStorage;
+pub use memory::MemoryStorage;
+#[cfg(feature = "redb")]
+pub use redb::RedbStorage;
+
+/// Where data is stored in the blockchain.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DataLocation {
+    /// The hash of the block, which holds the data.
+    pub block: HashBytes,
+    /// The height of the block.
+    pub height: u64,
+    /// The position of the data in the block.
+    pub index: u32,
+}
+
+/// What the recovery on startup discarded, after the blockchain was not written completely.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Recovery {
+    /// How many indexed blocks had no complete block data.
+    pub blocks: u64,
+    /// How many bytes were cut off the end of the index file.
+    pub index_bytes: u64,
+    /// How many bytes were cut off the end of the block file.
+    pub db_bytes: u64,
+}
+
+impl Recovery {
+    pub fn is_empty(&self) -> bool {
+        self.blocks == 0 && self.index_bytes == 0 && self.db_bytes == 0
+    }
+}
+
+/// What a storage knows about the stored blocks, without reading them.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    /// How many blocks are stored.
+    pub count: u64,
+    /// The hash of the first block.
+    pub root: Option<HashBytes>,
+    /// The hash of the last block.
+    pub last: Option<HashBytes>,
+    /// What was discarded when the storage was opened.
+    pub recovery: Recovery,
+}
+
+/// An iterator over stored blocks, in the order of their height.
+pub struct BlockIterator(Box<dyn Iterator<Item = Result<Block>> + Send>);
+
+impl BlockIterator {
+    pub fn new<I: Iterator<Item = Result<Block>> + Send + 'static>(it: I) -> Self {
+        Self(Box::new(it))
+    }
+}
+
+impl Iterator for BlockIterator {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
+
+/// A storage which is shared with the blocking threads, which read and write it.
+pub(crate) type SharedStorage = Arc<RwLock<Box<dyn Storage>>>;
+
+/// The blocks of a stream, before and after the storage is asked for them.
+enum Blocks {
+    /// The blocks after the start block, or all blocks without start.
+    Unopened(SharedStorage, Option<HashBytes>),
+    Open(BlockIterator),
+}
+
+impl Blocks {
+    /// Reads the next block. This blocks, it is only called on the blocking thread pool.
+    fn read_next(self) -> (BlockIterator, Option<Result<Block>>) {
+        let mut it = match self {
+            Blocks::Unopened(storage, start) => match storage.read() {
+                Ok(storage) => {
+                    let from = match start {
+                        Some(start) => match storage.height_of(&start) {
+                            Ok(Some(height)) => height + 1,
+                            _ => storage.metadata().count,
+                        },
+                        None => 0,
+                    };
+                    storage.iter(from)
+                }
+                Err(e) => {
+                    let e = Error::sync(line!(), module_path!(), e);
+                    return (BlockIterator::new(std::iter::empty()), Some(Err(e)));
+                }
+            },
+            Blocks::Open(it) => it,
+        };
+        let blk = it.next();
+
+        (it, blk)
+    }
+}
+
+///
+/// An async stream over stored blocks, in the order of their height.
+///
+/// The blocks are read and decompressed one at a time on the blocking thread pool, so a large chain does not
+/// stall the runtime. Nothing is read until the stream is polled.
+///
+pub struct BlockStream {
+    blocks: Option<Blocks>,
+    reading: Option<JoinHandle<(BlockIterator, Option<Result<Block>>)>>,
+}
+
+impl BlockStream {
+    /// Streams the blocks after the start block, or all blocks without start.
+    pub(crate) fn new(storage: SharedStorage, start: Option<HashBytes>) -> Self {
+        Self {
+            blocks: Some(Blocks::Unopened(storage, start)),
+            reading: None,
+        }
+    }
+
+    /// Returns the next block, or `None` after the last one.
+    pub async fn next(&mut self) -> Option<Result<Block>> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for BlockStream {
+    type Item = Result<Block>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let reading = match &mut this.reading {
+            Some(reading) => reading,
+            None => {
+                let Some(blocks) = this.blocks.take() else {
+                    return Poll::Ready(None);
+                };
+                this.reading
+                    .insert(tokio::task::spawn_blocking(move || blocks.read_next()))
+            }
+        };
+
+        let res = match Pin::new(reading).poll(cx) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.reading = None;
+
+        Poll::Ready(match res {
+            Ok((it, Some(blk))) => {
+                this.blocks = Some(Blocks::Open(it));
+                Some(blk)
+            }
+            Ok((_, None)) => None,
+            Err(e) => Some(Err(Error::sync(line!(), module_path!(), e))),
+        })
+    }
+}
+
+///
+/// Where the blocks of the chain are kept. Blocks are only appended, the root block has height 0.
+///
+/// The blocks are verified by the `Blockchain`, before they are appended.
+///
+pub trait Storage: Send + Sync {
+    /// The number of blocks, the first and the last one.
+    fn metadata(&self) -> Metadata;
+
+    /// Appends the block as the child of the last one.
+    fn append(&mut self, blk: &Block) -> Result<()>;
+
+    /// Appends the block like `append`, which `encode` already serialized and compressed before the chain was locked.
+    /// Storages which do not keep the blocks compressed, ignore the encoded form.
+    fn append_encoded(&mut self, blk: &Block, _encoded: &[u8]) -> Result<()> {
+        self.append(blk)
+    }
+
+    /// Returns the block at the height.
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>>;
+
+    /// Returns the height of the block with the hash.
+    fn height_of(&self, hash: &HashBytes) -> Result<Option<u64>>;
+
+    /// Iterates the blocks from the height on.
+    fn iter(&self, from: u64) -> BlockIterator;
+
+    /// Returns where the data with the signature is stored. By default all blocks are read.
+    fn find_data(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        scan_data(self.iter(0), sign)
+    }
+
+    /// Returns where the data of the author is stored, the newest first. By default all blocks are read.
+    fn data_by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        scan_author(self.iter(0), author, skip, limit)
+    }
+}
+
+/// Finds data by its signature, for storages without a data index.
+fn scan_data(blocks: BlockIterator, sign: &SignBytes) -> Result<Option<DataLocation>> {
+    for (blk, height) in blocks.zip(0..) {
+        let blk = ex!(blk, source);
+        if let Some(index) = blk.data.iter().position(|d| d.sign == *sign) {
+            return Ok(Some(DataLocation {
+                block: blk.hash,
+                height,
+                index: index as _,
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Finds the data of an author, for storages without a data index.
+fn scan_author(blocks: BlockIterator, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+    let mut locations = Vec::new();
+    for (blk, height) in blocks.zip(0..) {
+        let blk = ex!(blk, source);
+        for (d, index) in blk.data.iter().zip(0..) {
+            if d.author == *author {
+                locations.push(DataLocation {
+                    block: blk.hash,
+                    height,
+                    index,
+                });
+            }
+        }
+    }
+
+    Ok(locations.into_iter().rev().skip(skip).take(limit).collect())
+}
+
+/// Opens the storage selected in the config. This blocks, while a file storage is recovered or indexed.
+pub fn from_config(cfg: &Config) -> Result<Box<dyn Storage>> {
+    Ok(match &cfg.storage {
+        StorageKind::File => Box::new(ex!(FileStorage::open(&cfg.folder, cfg.index_data), source)),
+        StorageKind::Memory => Box::new(MemoryStorage::new()),
+        #[cfg(feature = "redb")]
+        StorageKind::Redb => Box::new(ex!(RedbStorage::open(&cfg.folder, cfg.index_data), source)),
+    })
+}
+
+/// Serializes and compresses a block, the way the persistent storages keep it.
+pub(crate) fn encode(blk: &Block) -> Result<Vec<u8>> {
+    let data = ex!(borsh::to_vec(blk), io);
+
+    Ok(ex!(zstd::stream::encode_all(data.as_slice(), 19), io))
+}
+
+fn decode(data: &[u8]) -> Result<Block> {
+    let data = ex!(zstd::stream::decode_all(data), io);
+
+    Ok(ex!(borsh::from_slice(&data), io))
+}
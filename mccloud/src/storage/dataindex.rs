@@ -0,0 +1,263 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use hashbrown::HashMap;
+use k256::sha2::{Digest, Sha256};
+
+use super::{hashindex::HashIndex, DataLocation};
+use crate::{
+    blockchain::Block,
+    error::{Error, Result},
+    ex, HashBytes, PubKeyBytes, SignBytes,
+};
+
+const DATA_FILE: &str = "data.db";
+const SIGNS_FILE: &str = "signs.db";
+const AUTHORS_FILE: &str = "authors.db";
+
+/// The header holds the number of indexed blocks and data records.
+const HEADER_SIZE: u64 = 16;
+/// The size of a serialized `DataRecord`.
+const RECORD_SIZE: u64 = 64 + 32 + 8 + 4 + 8;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+struct DataRecord {
+    sign: SignBytes,
+    block: HashBytes,
+    height: u64,
+    index: u32,
+    /// The previous record of the same author plus one, zero if there is none.
+    prev: u64,
+}
+
+impl DataRecord {
+    fn location(&self) -> DataLocation {
+        DataLocation {
+            block: self.block,
+            height: self.height,
+            index: self.index,
+        }
+    }
+}
+
+///
+/// Indices of the data in the blockchain, by author and by signature.
+///
+/// Every data gets a record in the data file. The records of an author are linked from the newest to the oldest,
+/// so the data of an author is found without iterating the blocks. The header of the data file is written last,
+/// so the indices are rebuilt when they do not cover the whole chain after a crash.
+///
+pub struct DataIndex {
+    file: PathBuf,
+    signs: HashIndex,
+    authors: HashIndex,
+    /// How many blocks are indexed.
+    blocks: u64,
+    records: u64,
+}
+
+impl DataIndex {
+    /// Opens the indices in the folder. Returns `None` if they do not cover exactly the number of blocks.
+    pub fn open(folder: &Path, blocks: u64) -> Result<Option<Self>> {
+        let file = folder.join(DATA_FILE);
+        if !file.exists() {
+            return Ok(None);
+        }
+
+        let mut f = ex!(File::open(&file), io);
+        let mut header = [0u8; HEADER_SIZE as usize];
+        if f.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        let (indexed, records) = decode_header(&header);
+        let size = ex!(f.metadata(), io).len();
+        if indexed != blocks || size != HEADER_SIZE + records * RECORD_SIZE {
+            return Ok(None);
+        }
+
+        let signs = ex!(HashIndex::open(&folder.join(SIGNS_FILE)), source);
+        let authors = ex!(HashIndex::open(&folder.join(AUTHORS_FILE)), source);
+        let (Some(signs), Some(authors)) = (signs, authors) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            file,
+            signs,
+            authors,
+            blocks,
+            records,
+        }))
+    }
+
+    /// Creates the indices of the blocks, in the order of their height.
+    pub fn rebuild(folder: &Path, blocks: impl Iterator<Item = Result<Block>>) -> Result<Self> {
+        let mut buffer = Vec::new();
+        let mut signs = Vec::new();
+        let mut authors: HashMap<HashBytes, u64> = HashMap::new();
+        let mut records = 0;
+        let mut indexed = 0;
+
+        for (blk, height) in blocks.zip(0..) {
+            let blk = ex!(blk, source);
+            for (d, index) in blk.data.iter().zip(0..) {
+                let prev = authors.insert(key(&d.author), records).map_or(0, |p| p + 1);
+                signs.push((key(&d.sign), records));
+                let record = DataRecord {
+                    sign: d.sign,
+                    block: blk.hash,
+                    height,
+                    index,
+                    prev,
+                };
+                ex!(borsh::to_writer(&mut buffer, &record), io);
+                records += 1;
+            }
+            indexed = height + 1;
+        }
+
+        let signs = ex!(HashIndex::rebuild(&folder.join(SIGNS_FILE), signs.into_iter()), source);
+        let authors = ex!(
+            HashIndex::rebuild(&folder.join(AUTHORS_FILE), authors.into_iter()),
+            source
+        );
+
+        // the data file is the last one, its header marks the indices as complete
+        let file = folder.join(DATA_FILE);
+        let tmp = file.with_extension("tmp");
+        {
+            let mut f = ex!(
+                OpenOptions::new().create(true).truncate(true).write(true).open(&tmp),
+                io
+            );
+            ex!(f.write_all(&encode_header(indexed, records)), io);
+            ex!(f.write_all(&buffer), io);
+            ex!(f.sync_all(), io);
+        }
+        ex!(std::fs::rename(&tmp, &file), io);
+
+        Ok(Self {
+            file,
+            signs,
+            authors,
+            blocks: indexed,
+            records,
+        })
+    }
+
+    /// Adds the data of the block at the height.
+    pub fn add_block(&mut self, height: u64, blk: &Block) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut records = self.records;
+
+        for (d, index) in blk.data.iter().zip(0..) {
+            let author = key(&d.author);
+            let prev = ex!(self.authors.get(&author), source).map_or(0, |p| p + 1);
+            ex!(self.authors.insert(&author, records), source);
+            ex!(self.signs.insert(&key(&d.sign), records), source);
+            let record = DataRecord {
+                sign: d.sign,
+                block: blk.hash,
+                height,
+                index,
+                prev,
+            };
+            ex!(borsh::to_writer(&mut buffer, &record), io);
+            records += 1;
+        }
+        ex!(self.authors.sync(), source);
+        ex!(self.signs.sync(), source);
+
+        let mut f = ex!(OpenOptions::new().write(true).open(&self.file), io);
+        ex!(f.seek(SeekFrom::Start(HEADER_SIZE + self.records * RECORD_SIZE)), io);
+        ex!(f.write_all(&buffer), io);
+        ex!(f.sync_data(), io);
+        ex!(f.seek(SeekFrom::Start(0)), io);
+        ex!(f.write_all(&encode_header(height + 1, records)), io);
+        ex!(f.sync_data(), io);
+
+        self.blocks = height + 1;
+        self.records = records;
+
+        Ok(())
+    }
+
+    /// Returns where the data with the signature is stored.
+    pub fn find(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        let Some(seq) = ex!(self.signs.get(&key(sign)), source) else {
+            return Ok(None);
+        };
+
+        let mut f = ex!(File::open(&self.file), io);
+        let record = ex!(self.read_record(&mut f, seq), source);
+        if record.sign != *sign {
+            return Ok(None);
+        }
+
+        Ok(Some(record.location()))
+    }
+
+    /// Returns where the data of the author is stored, the newest first. Skips the first `skip` ones.
+    pub fn by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        let mut next = ex!(self.authors.get(&key(author)), source);
+        let mut locations = Vec::new();
+        let mut skip = skip;
+
+        let mut f = ex!(File::open(&self.file), io);
+        while let Some(seq) = next {
+            if locations.len() >= limit {
+                break;
+            }
+
+            let record = ex!(self.read_record(&mut f, seq), source);
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                locations.push(record.location());
+            }
+            next = record.prev.checked_sub(1);
+        }
+
+        Ok(locations)
+    }
+
+    fn read_record(&self, f: &mut File, seq: u64) -> Result<DataRecord> {
+        if seq >= self.records {
+            return Err(Error::corrupted_index(line!(), module_path!(), DATA_FILE));
+        }
+
+        ex!(f.seek(SeekFrom::Start(HEADER_SIZE + seq * RECORD_SIZE)), io);
+        let mut buffer = [0u8; RECORD_SIZE as usize];
+        ex!(f.read_exact(&mut buffer), io);
+
+        Ok(ex!(DataRecord::try_from_slice(&buffer), io))
+    }
+}
+
+/// Keys of the hash tables are sha256 hashes, so they spread evenly.
+fn key(bytes: &[u8]) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(bytes);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sha.finalize());
+    hash
+}
+
+fn encode_header(blocks: u64, records: u64) -> [u8; HEADER_SIZE as usize] {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    header[..8].copy_from_slice(&blocks.to_le_bytes());
+    header[8..].copy_from_slice(&records.to_le_bytes());
+    header
+}
+
+fn decode_header(header: &[u8; HEADER_SIZE as usize]) -> (u64, u64) {
+    let mut blocks = [0u8; 8];
+    let mut records = [0u8; 8];
+    blocks.copy_from_slice(&header[..8]);
+    records.copy_from_slice(&header[8..]);
+    (u64::from_le_bytes(blocks), u64::from_le_bytes(records))
+}
@@ -0,0 +1,472 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use borsh::{BorshDeserialize, BorshSerialize};
+use k256::sha2::{Digest, Sha256};
+
+use super::{
+    dataindex::DataIndex, decode, encode, hashindex::HashIndex, scan_author, scan_data, BlockIterator, DataLocation,
+    Metadata, Recovery, Storage,
+};
+use crate::{
+    blockchain::Block,
+    error::{Error, Result},
+    ex, HashBytes, PubKeyBytes, SignBytes,
+};
+
+/// The index starts with a magic and the version of its format.
+const INDEX_MAGIC: &[u8; 8] = b"mcclindx";
+const INDEX_VERSION: u64 = 2;
+const INDEX_HEADER_SIZE: u64 = 16;
+/// The size of a serialized `IndexEntry`.
+const INDEX_ENTRY_SIZE: usize = 32 + 8 + 8 + 32;
+/// The size of an entry in the first index format, which had neither a header nor checksums.
+const LEGACY_ENTRY_SIZE: usize = 32 + 8 + 8;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+struct IndexEntry {
+    hash: HashBytes,
+    pos: u64,
+    size: u64,
+    /// The hash of the stored block data, to detect torn or corrupted writes.
+    checksum: HashBytes,
+}
+
+fn checksum(data: &[u8]) -> HashBytes {
+    let mut sha = Sha256::new();
+    sha.update(data);
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&sha.finalize());
+    hash
+}
+
+/// The position of the index entry of the block at the height.
+fn entry_pos(height: u64) -> u64 {
+    INDEX_HEADER_SIZE + height * INDEX_ENTRY_SIZE as u64
+}
+
+///
+/// Checks the format version of the index. An empty index gets a header, an index of the first format is
+/// migrated, and an index of a newer format is refused.
+///
+fn check_version(index_file: &Path, db_file: &Path) -> Result<()> {
+    // only the header is read, the index is read completely only when it is migrated
+    let mut header = Vec::with_capacity(INDEX_HEADER_SIZE as usize);
+    ex!(
+        ex!(File::open(index_file), io)
+            .take(INDEX_HEADER_SIZE)
+            .read_to_end(&mut header),
+        io
+    );
+    if header.len() == INDEX_HEADER_SIZE as usize && header[..8] == INDEX_MAGIC[..] {
+        let mut version = [0u8; 8];
+        version.copy_from_slice(&header[8..16]);
+        let version = u64::from_le_bytes(version);
+        if version != INDEX_VERSION {
+            let file = index_file.display().to_string();
+            return Err(Error::unknown_index_version(line!(), module_path!(), &file, version));
+        }
+        return Ok(());
+    }
+
+    let legacy = ex!(std::fs::read(index_file), io);
+    ex!(migrate(index_file, db_file, &legacy), source);
+
+    Ok(())
+}
+
+///
+/// Writes the entries of an index of the first format in the current format, with the checksums of the stored
+/// block data. The new index replaces the old one only when it is complete, so a crash leaves either of them.
+///
+/// Entries without complete block data are dropped, like the recovery would do.
+///
+fn migrate(index_file: &Path, db_file: &Path, legacy: &[u8]) -> Result<()> {
+    let mut db = ex!(File::open(db_file), io);
+    let db_len = ex!(db.metadata(), io).len();
+
+    let mut index =
+        Vec::with_capacity(INDEX_HEADER_SIZE as usize + legacy.len() / LEGACY_ENTRY_SIZE * INDEX_ENTRY_SIZE);
+    index.extend_from_slice(INDEX_MAGIC);
+    index.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+
+    let mut count = 0;
+    for chunk in legacy.chunks_exact(LEGACY_ENTRY_SIZE) {
+        let (hash, pos, size) = ex!(<(HashBytes, u64, u64)>::try_from_slice(chunk), io);
+        if pos + size > db_len {
+            break;
+        }
+
+        let mut data = vec![0u8; size as _];
+        ex!(db.seek(SeekFrom::Start(pos)), io);
+        ex!(db.read_exact(&mut data), io);
+        let entry = IndexEntry {
+            hash,
+            pos,
+            size,
+            checksum: checksum(&data),
+        };
+        ex!(borsh::to_writer(&mut index, &entry), io);
+        count += 1;
+    }
+
+    let tmp = index_file.with_extension("migrate");
+    {
+        let mut file = ex!(File::create(&tmp), io);
+        ex!(file.write_all(&index), io);
+        ex!(file.sync_all(), io);
+    }
+    ex!(std::fs::rename(&tmp, index_file), io);
+
+    if !legacy.is_empty() {
+        tracing::info!("migrated the index of {} blocks to version {}", count, INDEX_VERSION);
+    }
+
+    Ok(())
+}
+
+///
+/// Truncates the index and block files to the last complete block, and returns the number of blocks.
+///
+/// The block data is synced before its index entry is written, so only the end of the files can be torn: a
+/// partial index entry, an entry which points past the block data, or block data without an entry. The index is
+/// checked from its end, until an entry with complete block data is found.
+///
+fn recover(index_file: &PathBuf, db_file: &PathBuf) -> Result<(u64, Recovery)> {
+    let mut index = ex!(OpenOptions::new().read(true).write(true).open(index_file), io);
+    let mut db = ex!(OpenOptions::new().read(true).write(true).open(db_file), io);
+    let index_len = ex!(index.metadata(), io).len();
+    let db_len = ex!(db.metadata(), io).len();
+
+    let indexed = index_len.saturating_sub(INDEX_HEADER_SIZE) / INDEX_ENTRY_SIZE as u64;
+    let mut count = indexed;
+    let mut data_len = 0;
+    while count > 0 {
+        let entry = ex!(read_entry(&mut index, count - 1), source);
+        if entry.pos + entry.size <= db_len {
+            let mut data = vec![0u8; entry.size as _];
+            ex!(db.seek(SeekFrom::Start(entry.pos)), io);
+            ex!(db.read_exact(&mut data), io);
+            if checksum(&data) == entry.checksum {
+                data_len = entry.pos + entry.size;
+                break;
+            }
+        }
+        count -= 1;
+    }
+
+    let recovery = Recovery {
+        blocks: indexed - count,
+        index_bytes: index_len - entry_pos(count),
+        db_bytes: db_len - data_len,
+    };
+
+    if recovery.index_bytes > 0 {
+        ex!(index.set_len(entry_pos(count)), io);
+        ex!(index.sync_all(), io);
+    }
+    if recovery.db_bytes > 0 {
+        ex!(db.set_len(data_len), io);
+        ex!(db.sync_all(), io);
+    }
+
+    Ok((count, recovery))
+}
+
+/// Reads the index entry of the block at the height.
+fn read_entry(file: &mut File, height: u64) -> Result<IndexEntry> {
+    ex!(file.seek(SeekFrom::Start(entry_pos(height))), io);
+    let mut buffer = [0u8; INDEX_ENTRY_SIZE];
+    ex!(file.read_exact(&mut buffer), io);
+
+    Ok(ex!(IndexEntry::try_from_slice(&buffer), io))
+}
+
+/// Reads and decodes the block of an index entry.
+fn read_block<R: Read + Seek>(file: &mut R, entry: &IndexEntry) -> Result<Block> {
+    ex!(file.seek(SeekFrom::Start(entry.pos)), io);
+    let mut buffer = vec![0u8; entry.size as _];
+    ex!(file.read_exact(&mut buffer), io);
+    if checksum(&buffer) != entry.checksum {
+        return Err(Error::corrupted_block(line!(), module_path!(), &entry.hash));
+    }
+
+    decode(&buffer)
+}
+
+struct IndexIterator {
+    file: BufReader<File>,
+}
+
+impl IndexIterator {
+    /// Iterates the index from the block at the height.
+    fn new(file: &PathBuf, from: u64) -> Result<Self> {
+        let file = ex!(File::open(file), io);
+        let mut file = BufReader::new(file);
+        ex!(file.seek(SeekFrom::Start(entry_pos(from))), io);
+        Ok(Self { file })
+    }
+}
+
+impl Iterator for IndexIterator {
+    type Item = IndexEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        IndexEntry::deserialize_reader(&mut self.file).ok()
+    }
+}
+
+struct FileBlocks {
+    file: BufReader<File>,
+    index_it: IndexIterator,
+}
+
+impl FileBlocks {
+    fn new(index: &PathBuf, db: &PathBuf, from: u64) -> Result<Self> {
+        let file = ex!(File::open(db), io);
+        let file = BufReader::new(file);
+        let index_it = ex!(IndexIterator::new(index, from), source);
+
+        Ok(Self { file, index_it })
+    }
+}
+
+impl Iterator for FileBlocks {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = self.index_it.next()?;
+
+        Some(read_block(&mut self.file, &idx))
+    }
+}
+
+///
+/// The blocks in two flat files of the data folder. This is the default storage.
+///
+/// `blocks.db` holds the compressed blocks one after the other, `index.db` a fixed size entry for each block, so
+/// the entry of a height is found without reading the others. The index starts with the version of its format.
+///
+pub struct FileStorage {
+    folder: PathBuf,
+    index_file: PathBuf,
+    db_file: PathBuf,
+    meta: Metadata,
+    block_pos: u64,
+    /// The height of every block, by its hash.
+    hashes: HashIndex,
+    /// The data by author and signature, if enabled.
+    data: Option<DataIndex>,
+}
+
+impl FileStorage {
+    /// Opens the blocks in the folder. With `index_data` the data is indexed by author and signature.
+    pub fn open(folder: &Path, index_data: bool) -> Result<Self> {
+        if !folder.exists() {
+            ex!(std::fs::create_dir_all(folder), io);
+        }
+
+        let index_file = folder.join("index.db");
+        let db_file = folder.join("blocks.db");
+
+        for file in [&index_file, &db_file] {
+            ex!(
+                OpenOptions::new().create(true).truncate(false).write(true).open(file),
+                io
+            );
+        }
+
+        ex!(check_version(&index_file, &db_file), source);
+        let (count, recovery) = ex!(recover(&index_file, &db_file), source);
+
+        let (root, last, block_pos) = if count > 0 {
+            let mut index = ex!(File::open(&index_file), io);
+            let first = ex!(read_entry(&mut index, 0), source);
+            let last = ex!(read_entry(&mut index, count - 1), source);
+
+            (Some(first.hash), Some(last.hash), last.pos + last.size)
+        } else {
+            (None, None, 0)
+        };
+
+        // the hash index is rebuilt when it does not match the chain, after a crash or a recovery
+        let hashes_file = folder.join("hashes.db");
+        let hashes = match ex!(HashIndex::open(&hashes_file), source) {
+            Some(hashes)
+                if hashes.len() == count
+                    && last.map_or(Ok(None), |h| hashes.get(&h)).ok().flatten() == count.checked_sub(1) =>
+            {
+                hashes
+            }
+            _ => {
+                tracing::info!("rebuilding the block hash index of {} blocks", count);
+                let it = ex!(IndexIterator::new(&index_file, 0), source)
+                    .take(count as _)
+                    .zip(0..)
+                    .map(|(e, h)| (e.hash, h));
+                ex!(HashIndex::rebuild(&hashes_file, it), source)
+            }
+        };
+
+        let data = if index_data {
+            match ex!(DataIndex::open(folder, count), source) {
+                Some(data) => Some(data),
+                None => {
+                    tracing::info!("rebuilding the data index of {} blocks", count);
+                    let it = ex!(FileBlocks::new(&index_file, &db_file, 0), source).take(count as _);
+                    Some(ex!(DataIndex::rebuild(folder, it), source))
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            folder: folder.into(),
+            index_file,
+            db_file,
+            meta: Metadata {
+                count,
+                root,
+                last,
+                recovery,
+            },
+            block_pos,
+            hashes,
+            data,
+        })
+    }
+}
+
+impl FileStorage {
+    /// Writes the block data, its index entry and the entries of the hash and data index.
+    fn write(&mut self, blk: &Block, data: &[u8], idx: &IndexEntry) -> Result<()> {
+        // the block data is synced first, so an index entry never points to data which is not on disk
+        {
+            let mut db_file = ex!(OpenOptions::new().append(true).open(&self.db_file), io);
+            ex!(db_file.write_all(data), io);
+            ex!(db_file.sync_data(), io);
+        }
+
+        {
+            let mut idx_file = ex!(OpenOptions::new().append(true).open(&self.index_file), io);
+            ex!(borsh::to_writer(&mut idx_file, idx), io);
+            ex!(idx_file.sync_data(), io);
+        }
+        ex!(self.hashes.insert(&blk.hash, self.meta.count), source);
+        ex!(self.hashes.sync(), source);
+        if let Some(data) = &mut self.data {
+            ex!(data.add_block(self.meta.count, blk), source);
+        }
+
+        Ok(())
+    }
+
+    /// Cuts the files back to the stored blocks, after an append failed partway. The hash and data index are
+    /// rebuilt, if they already took the failed block.
+    fn rollback(&mut self, hash: &HashBytes) -> Result<()> {
+        let count = self.meta.count;
+
+        let index = ex!(OpenOptions::new().write(true).open(&self.index_file), io);
+        ex!(index.set_len(entry_pos(count)), io);
+        ex!(index.sync_all(), io);
+        let db = ex!(OpenOptions::new().write(true).open(&self.db_file), io);
+        ex!(db.set_len(self.block_pos), io);
+        ex!(db.sync_all(), io);
+
+        if self.hashes.get(hash).ok().flatten().is_none() {
+            return Ok(());
+        }
+
+        let it = ex!(IndexIterator::new(&self.index_file, 0), source)
+            .take(count as _)
+            .zip(0..)
+            .map(|(e, h)| (e.hash, h));
+        self.hashes = ex!(HashIndex::rebuild(&self.folder.join("hashes.db"), it), source);
+        if self.data.is_some() {
+            let it = ex!(FileBlocks::new(&self.index_file, &self.db_file, 0), source).take(count as _);
+            self.data = Some(ex!(DataIndex::rebuild(&self.folder, it), source));
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for FileStorage {
+    fn metadata(&self) -> Metadata {
+        self.meta
+    }
+
+    fn append(&mut self, blk: &Block) -> Result<()> {
+        let data = ex!(encode(blk), source);
+
+        self.append_encoded(blk, &data)
+    }
+
+    fn append_encoded(&mut self, blk: &Block, data: &[u8]) -> Result<()> {
+        let idx = IndexEntry {
+            hash: blk.hash,
+            pos: self.block_pos,
+            size: data.len() as _,
+            checksum: checksum(data),
+        };
+
+        if let Err(e) = self.write(blk, data, &idx) {
+            // nothing of the block may stay behind, or the next block would be written after it
+            if let Err(e) = self.rollback(&blk.hash) {
+                tracing::error!("rollback of block {}: {}", hex::encode(blk.hash), e);
+            }
+            return Err(e);
+        }
+
+        if self.meta.root.is_none() {
+            self.meta.root = Some(blk.hash);
+        }
+        self.meta.last = Some(blk.hash);
+        self.meta.count += 1;
+        self.block_pos += idx.size;
+
+        Ok(())
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>> {
+        if height >= self.meta.count {
+            return Ok(None);
+        }
+
+        let mut index = ex!(File::open(&self.index_file), io);
+        let entry = ex!(read_entry(&mut index, height), source);
+        let mut file = ex!(File::open(&self.db_file), io);
+
+        Ok(Some(ex!(read_block(&mut file, &entry), source)))
+    }
+
+    fn height_of(&self, hash: &HashBytes) -> Result<Option<u64>> {
+        Ok(ex!(self.hashes.get(hash), source).filter(|h| *h < self.meta.count))
+    }
+
+    fn iter(&self, from: u64) -> BlockIterator {
+        let count = self.meta.count.saturating_sub(from);
+        match FileBlocks::new(&self.index_file, &self.db_file, from) {
+            Ok(it) => BlockIterator::new(it.take(count as _)),
+            Err(e) => BlockIterator::new(std::iter::once(Err(e))),
+        }
+    }
+
+    fn find_data(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        match &self.data {
+            Some(data) => data.find(sign),
+            None => scan_data(self.iter(0), sign),
+        }
+    }
+
+    fn data_by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        match &self.data {
+            Some(data) => data.by_author(author, skip, limit),
+            None => scan_author(self.iter(0), author, skip, limit),
+        }
+    }
+}
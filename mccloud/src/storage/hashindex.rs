@@ -0,0 +1,217 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    error::{Error, Result},
+    ex, HashBytes,
+};
+
+/// The header holds the number of slots and the number of stored hashes.
+const HEADER_SIZE: u64 = 16;
+/// A slot holds a key and its value plus one, zero marks an empty slot.
+const SLOT_SIZE: u64 = 32 + 8;
+const MIN_SLOTS: u64 = 1024;
+
+///
+/// A persistent hash table from 32 byte keys to numbers, with open addressing. Like block hashes to their height
+/// in the chain.
+///
+/// The table is kept at most half full, so a lookup reads only a few slots. Keys are sha256 hashes, so their
+/// first bytes pick the start slot.
+///
+pub struct HashIndex {
+    file: PathBuf,
+    slots: u64,
+    len: u64,
+}
+
+impl HashIndex {
+    /// Opens an existing index. Returns `None` if the file is missing or not a valid index.
+    pub fn open(file: &Path) -> Result<Option<Self>> {
+        if !file.exists() {
+            return Ok(None);
+        }
+
+        let mut f = ex!(File::open(file), io);
+        let mut header = [0u8; HEADER_SIZE as usize];
+        if f.read_exact(&mut header).is_err() {
+            return Ok(None);
+        }
+        let (slots, len) = decode_header(&header);
+        let size = ex!(f.metadata(), io).len();
+
+        if slots < MIN_SLOTS || !slots.is_power_of_two() || size != HEADER_SIZE + slots * SLOT_SIZE || len * 2 > slots {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            file: file.into(),
+            slots,
+            len,
+        }))
+    }
+
+    /// Creates a new index with the entries. Later entries replace earlier ones with the same key.
+    pub fn rebuild(file: &Path, entries: impl Iterator<Item = (HashBytes, u64)>) -> Result<Self> {
+        let entries: Vec<(HashBytes, u64)> = entries.collect();
+        let slots = (entries.len() as u64 * 2).next_power_of_two().max(MIN_SLOTS);
+
+        Self::build(file, slots, &entries)
+    }
+
+    /// Writes a table with the slots, via a temporary file.
+    fn build(file: &Path, slots: u64, entries: &[(HashBytes, u64)]) -> Result<Self> {
+        let mut table = vec![0u8; (slots * SLOT_SIZE) as usize];
+        let mut len = 0;
+        for (key, value) in entries.iter() {
+            let mut slot = start_slot(key, slots);
+            loop {
+                let pos = (slot * SLOT_SIZE) as usize;
+                let empty = table[pos + 32..pos + 40] == [0u8; 8];
+                if empty || table[pos..pos + 32] == key[..] {
+                    table[pos..pos + 32].copy_from_slice(key);
+                    table[pos + 32..pos + 40].copy_from_slice(&(value + 1).to_le_bytes());
+                    if empty {
+                        len += 1;
+                    }
+                    break;
+                }
+                slot = (slot + 1) & (slots - 1);
+            }
+        }
+
+        let tmp = file.with_extension("tmp");
+        {
+            let mut f = ex!(
+                OpenOptions::new().create(true).truncate(true).write(true).open(&tmp),
+                io
+            );
+            ex!(f.write_all(&encode_header(slots, len)), io);
+            ex!(f.write_all(&table), io);
+            ex!(f.sync_all(), io);
+        }
+        ex!(std::fs::rename(&tmp, file), io);
+
+        Ok(Self {
+            file: file.into(),
+            slots,
+            len,
+        })
+    }
+
+    /// The number of stored keys.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns the value of the key.
+    pub fn get(&self, key: &HashBytes) -> Result<Option<u64>> {
+        let mut f = ex!(File::open(&self.file), io);
+        let (_, value) = ex!(self.find(&mut f, key), source);
+
+        Ok(value)
+    }
+
+    /// Sets the value of the key, and grows the table when it is half full. Not synced, see `sync`.
+    pub fn insert(&mut self, key: &HashBytes, value: u64) -> Result<()> {
+        if (self.len + 1) * 2 > self.slots {
+            ex!(self.grow(), source);
+        }
+
+        let mut f = ex!(OpenOptions::new().read(true).write(true).open(&self.file), io);
+        let (slot, existing) = ex!(self.find(&mut f, key), source);
+
+        let mut buffer = [0u8; SLOT_SIZE as usize];
+        buffer[..32].copy_from_slice(key);
+        buffer[32..].copy_from_slice(&(value + 1).to_le_bytes());
+        ex!(f.seek(SeekFrom::Start(HEADER_SIZE + slot * SLOT_SIZE)), io);
+        ex!(f.write_all(&buffer), io);
+
+        if existing.is_none() {
+            self.len += 1;
+        }
+        ex!(f.seek(SeekFrom::Start(0)), io);
+        ex!(f.write_all(&encode_header(self.slots, self.len)), io);
+
+        Ok(())
+    }
+
+    /// Writes the inserted keys to the disk.
+    pub fn sync(&self) -> Result<()> {
+        let f = ex!(OpenOptions::new().write(true).open(&self.file), io);
+        ex!(f.sync_data(), io);
+
+        Ok(())
+    }
+
+    /// Probes for the key. Returns its slot and value, or the empty slot where it belongs.
+    fn find(&self, f: &mut File, key: &HashBytes) -> Result<(u64, Option<u64>)> {
+        let mut slot = start_slot(key, self.slots);
+        let mut buffer = [0u8; SLOT_SIZE as usize];
+
+        ex!(f.seek(SeekFrom::Start(HEADER_SIZE + slot * SLOT_SIZE)), io);
+        // the table is never full, so there always is an empty slot
+        loop {
+            ex!(f.read_exact(&mut buffer), io);
+            let value = u64::from_le_bytes(buffer[32..].try_into().unwrap_or_default());
+            if value == 0 {
+                return Ok((slot, None));
+            }
+            if buffer[..32] == key[..] {
+                return Ok((slot, Some(value - 1)));
+            }
+
+            slot = (slot + 1) & (self.slots - 1);
+            if slot == 0 {
+                ex!(f.seek(SeekFrom::Start(HEADER_SIZE)), io);
+            }
+        }
+    }
+
+    /// Rebuilds the table with twice the slots.
+    fn grow(&mut self) -> Result<()> {
+        let mut buffer = Vec::new();
+        {
+            let mut f = ex!(File::open(&self.file), io);
+            ex!(f.read_to_end(&mut buffer), io);
+        }
+
+        let mut entries = Vec::with_capacity(self.len as usize);
+        for slot in buffer[HEADER_SIZE as usize..].chunks_exact(SLOT_SIZE as usize) {
+            let value = u64::from_le_bytes(slot[32..].try_into().unwrap_or_default());
+            if value > 0 {
+                let mut key = [0u8; 32];
+                key.copy_from_slice(&slot[..32]);
+                entries.push((key, value - 1));
+            }
+        }
+
+        *self = ex!(Self::build(&self.file, self.slots * 2, &entries), source);
+
+        Ok(())
+    }
+}
+
+fn start_slot(key: &HashBytes, slots: u64) -> u64 {
+    let mut start = [0u8; 8];
+    start.copy_from_slice(&key[..8]);
+    u64::from_le_bytes(start) & (slots - 1)
+}
+
+fn encode_header(slots: u64, len: u64) -> [u8; HEADER_SIZE as usize] {
+    let mut header = [0u8; HEADER_SIZE as usize];
+    header[..8].copy_from_slice(&slots.to_le_bytes());
+    header[8..].copy_from_slice(&len.to_le_bytes());
+    header
+}
+
+fn decode_header(header: &[u8; HEADER_SIZE as usize]) -> (u64, u64) {
+    let mut slots = [0u8; 8];
+    let mut len = [0u8; 8];
+    slots.copy_from_slice(&header[..8]);
+    len.copy_from_slice(&header[8..]);
+    (u64::from_le_bytes(slots), u64::from_le_bytes(len))
+}
@@ -0,0 +1,83 @@
+use std::sync::{Arc, RwLock};
+
+use hashbrown::HashMap;
+
+use super::{BlockIterator, Metadata, Storage};
+use crate::{
+    blockchain::Block,
+    error::{Error, Result},
+    ex, HashBytes,
+};
+
+///
+/// Keeps the blocks only in memory, for tests and ephemeral thin clients. The blocks are lost on shutdown.
+///
+#[derive(Default)]
+pub struct MemoryStorage {
+    blocks: Arc<RwLock<Vec<Block>>>,
+    heights: HashMap<HashBytes, u64>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn metadata(&self) -> Metadata {
+        let blocks = self.blocks.read().unwrap_or_else(|e| e.into_inner());
+
+        Metadata {
+            count: blocks.len() as _,
+            root: blocks.first().map(|b| b.hash),
+            last: blocks.last().map(|b| b.hash),
+            ..Default::default()
+        }
+    }
+
+    fn append(&mut self, blk: &Block) -> Result<()> {
+        let mut blocks = ex!(self.blocks.write(), sync);
+        self.heights.insert(blk.hash, blocks.len() as _);
+        blocks.push(blk.clone());
+
+        Ok(())
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>> {
+        let blocks = ex!(self.blocks.read(), sync);
+
+        Ok(blocks.get(height as usize).cloned())
+    }
+
+    fn height_of(&self, hash: &HashBytes) -> Result<Option<u64>> {
+        Ok(self.heights.get(hash).copied())
+    }
+
+    fn iter(&self, from: u64) -> BlockIterator {
+        BlockIterator::new(MemoryBlocks {
+            blocks: self.blocks.clone(),
+            pos: from as _,
+        })
+    }
+}
+
+struct MemoryBlocks {
+    blocks: Arc<RwLock<Vec<Block>>>,
+    pos: usize,
+}
+
+impl Iterator for MemoryBlocks {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let blocks = match self.blocks.read() {
+            Ok(blocks) => blocks,
+            Err(e) => return Some(Err(Error::sync(line!(), module_path!(), e))),
+        };
+        let blk = blocks.get(self.pos)?.clone();
+        self.pos += 1;
+
+        Some(Ok(blk))
+    }
+}
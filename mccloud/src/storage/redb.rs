@@ -0,0 +1,280 @@
+use std::{path::Path, sync::Arc};
+
+use redb::{Database, ReadableTable, ReadableTableMetadata, TableDefinition};
+
+use super::{decode, encode, scan_author, scan_data, BlockIterator, DataLocation, Metadata, Storage};
+use crate::{
+    blockchain::Block,
+    error::{Error, Result},
+    ex, HashBytes, PubKeyBytes, SignBytes,
+};
+
+/// The name of the database file, inside the data folder.
+const REDB_FILE: &str = "chain.redb";
+
+/// The compressed blocks by height.
+const BLOCKS: TableDefinition<u64, &[u8]> = TableDefinition::new("blocks");
+/// The block hashes by height.
+const HASHES: TableDefinition<u64, &[u8]> = TableDefinition::new("hashes");
+/// The block heights by hash.
+const HEIGHTS: TableDefinition<&[u8], u64> = TableDefinition::new("heights");
+/// The height and position of data by its signature.
+const SIGNS: TableDefinition<&[u8], (u64, u32)> = TableDefinition::new("signs");
+/// The author, height and position of data, so the data of an author is a range.
+const AUTHORS: TableDefinition<&[u8], ()> = TableDefinition::new("authors");
+/// How many blocks the data tables cover.
+const META: TableDefinition<&str, u64> = TableDefinition::new("meta");
+const META_DATA: &str = "data";
+
+///
+/// The blocks in an embedded redb database, for nodes which need indexed access.
+///
+/// Every block is added in one transaction, together with its indices, so the database is never torn.
+///
+pub struct RedbStorage {
+    db: Arc<Database>,
+    meta: Metadata,
+    index_data: bool,
+}
+
+impl RedbStorage {
+    /// Opens the database in the folder. With `index_data` the data is indexed by author and signature.
+    pub fn open(folder: &Path, index_data: bool) -> Result<Self> {
+        if !folder.exists() {
+            ex!(std::fs::create_dir_all(folder), io);
+        }
+
+        let db = ex!(Database::create(folder.join(REDB_FILE)), storage);
+
+        // all tables are created up front, so readers can always open them
+        let txn = ex!(db.begin_write(), storage);
+        ex!(txn.open_table(BLOCKS), storage);
+        ex!(txn.open_table(HASHES), storage);
+        ex!(txn.open_table(HEIGHTS), storage);
+        ex!(txn.open_table(SIGNS), storage);
+        ex!(txn.open_table(AUTHORS), storage);
+        ex!(txn.open_table(META), storage);
+        ex!(txn.commit(), storage);
+
+        let (count, root, last, indexed) = {
+            let txn = ex!(db.begin_read(), storage);
+            let hashes = ex!(txn.open_table(HASHES), storage);
+            let count = ex!(hashes.len(), storage);
+            let root = ex!(read_hash(&hashes, 0), source);
+            let last = match count.checked_sub(1) {
+                Some(height) => ex!(read_hash(&hashes, height), source),
+                None => None,
+            };
+            let meta = ex!(txn.open_table(META), storage);
+            let indexed = ex!(meta.get(META_DATA), storage).map(|v| v.value()).unwrap_or_default();
+
+            (count, root, last, indexed)
+        };
+
+        let storage = Self {
+            db: Arc::new(db),
+            meta: Metadata {
+                count,
+                root,
+                last,
+                ..Default::default()
+            },
+            index_data,
+        };
+
+        if index_data && indexed != count {
+            tracing::info!("rebuilding the data index of {} blocks", count);
+            ex!(storage.rebuild_data(), source);
+        }
+
+        Ok(storage)
+    }
+
+    /// Indexes the data of all blocks again.
+    fn rebuild_data(&self) -> Result<()> {
+        let txn = ex!(self.db.begin_write(), storage);
+        ex!(txn.delete_table(SIGNS), storage);
+        ex!(txn.delete_table(AUTHORS), storage);
+        {
+            let blocks = ex!(txn.open_table(BLOCKS), storage);
+            let mut signs = ex!(txn.open_table(SIGNS), storage);
+            let mut authors = ex!(txn.open_table(AUTHORS), storage);
+            for entry in ex!(blocks.iter(), storage) {
+                let (height, data) = ex!(entry, storage);
+                let blk = ex!(decode(data.value()), source);
+                for (d, index) in blk.data.iter().zip(0..) {
+                    ex!(signs.insert(&d.sign[..], (height.value(), index)), storage);
+                    ex!(
+                        authors.insert(author_key(&d.author, height.value(), index).as_slice(), ()),
+                        storage
+                    );
+                }
+            }
+            let mut meta = ex!(txn.open_table(META), storage);
+            ex!(meta.insert(META_DATA, self.meta.count), storage);
+        }
+        ex!(txn.commit(), storage);
+
+        Ok(())
+    }
+}
+
+impl Storage for RedbStorage {
+    fn metadata(&self) -> Metadata {
+        self.meta
+    }
+
+    fn append(&mut self, blk: &Block) -> Result<()> {
+        let data = ex!(encode(blk), source);
+
+        self.append_encoded(blk, &data)
+    }
+
+    fn append_encoded(&mut self, blk: &Block, data: &[u8]) -> Result<()> {
+        let height = self.meta.count;
+
+        let txn = ex!(self.db.begin_write(), storage);
+        {
+            let mut blocks = ex!(txn.open_table(BLOCKS), storage);
+            ex!(blocks.insert(height, data), storage);
+            let mut hashes = ex!(txn.open_table(HASHES), storage);
+            ex!(hashes.insert(height, &blk.hash[..]), storage);
+            let mut heights = ex!(txn.open_table(HEIGHTS), storage);
+            ex!(heights.insert(&blk.hash[..], height), storage);
+
+            if self.index_data {
+                let mut signs = ex!(txn.open_table(SIGNS), storage);
+                let mut authors = ex!(txn.open_table(AUTHORS), storage);
+                for (d, index) in blk.data.iter().zip(0..) {
+                    ex!(signs.insert(&d.sign[..], (height, index)), storage);
+                    ex!(
+                        authors.insert(author_key(&d.author, height, index).as_slice(), ()),
+                        storage
+                    );
+                }
+                let mut meta = ex!(txn.open_table(META), storage);
+                ex!(meta.insert(META_DATA, height + 1), storage);
+            }
+        }
+        ex!(txn.commit(), storage);
+
+        if self.meta.root.is_none() {
+            self.meta.root = Some(blk.hash);
+        }
+        self.meta.last = Some(blk.hash);
+        self.meta.count += 1;
+
+        Ok(())
+    }
+
+    fn get_by_height(&self, height: u64) -> Result<Option<Block>> {
+        read_block(&self.db, height)
+    }
+
+    fn height_of(&self, hash: &HashBytes) -> Result<Option<u64>> {
+        let txn = ex!(self.db.begin_read(), storage);
+        let heights = ex!(txn.open_table(HEIGHTS), storage);
+
+        Ok(ex!(heights.get(&hash[..]), storage).map(|v| v.value()))
+    }
+
+    fn iter(&self, from: u64) -> BlockIterator {
+        BlockIterator::new(RedbBlocks {
+            db: self.db.clone(),
+            pos: from,
+        })
+    }
+
+    fn find_data(&self, sign: &SignBytes) -> Result<Option<DataLocation>> {
+        if !self.index_data {
+            return scan_data(self.iter(0), sign);
+        }
+
+        let txn = ex!(self.db.begin_read(), storage);
+        let signs = ex!(txn.open_table(SIGNS), storage);
+        let Some((height, index)) = ex!(signs.get(&sign[..]), storage).map(|v| v.value()) else {
+            return Ok(None);
+        };
+        let hashes = ex!(txn.open_table(HASHES), storage);
+
+        Ok(ex!(read_hash(&hashes, height), source).map(|block| DataLocation { block, height, index }))
+    }
+
+    fn data_by_author(&self, author: &PubKeyBytes, skip: usize, limit: usize) -> Result<Vec<DataLocation>> {
+        if !self.index_data {
+            return scan_author(self.iter(0), author, skip, limit);
+        }
+
+        let txn = ex!(self.db.begin_read(), storage);
+        let authors = ex!(txn.open_table(AUTHORS), storage);
+        let hashes = ex!(txn.open_table(HASHES), storage);
+        let start = author_key(author, 0, 0);
+        let end = author_key(author, u64::MAX, u32::MAX);
+
+        let mut locations = Vec::new();
+        for entry in ex!(authors.range(start.as_slice()..=end.as_slice()), storage)
+            .rev()
+            .skip(skip)
+            .take(limit)
+        {
+            let (key, _) = ex!(entry, storage);
+            let (height, index) = parse_author_key(key.value());
+            if let Some(block) = ex!(read_hash(&hashes, height), source) {
+                locations.push(DataLocation { block, height, index });
+            }
+        }
+
+        Ok(locations)
+    }
+}
+
+struct RedbBlocks {
+    db: Arc<Database>,
+    pos: u64,
+}
+
+impl Iterator for RedbBlocks {
+    type Item = Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let blk = read_block(&self.db, self.pos).transpose()?;
+        self.pos += 1;
+
+        Some(blk)
+    }
+}
+
+fn read_block(db: &Database, height: u64) -> Result<Option<Block>> {
+    let txn = ex!(db.begin_read(), storage);
+    let blocks = ex!(txn.open_table(BLOCKS), storage);
+
+    match ex!(blocks.get(height), storage) {
+        Some(data) => Ok(Some(ex!(decode(data.value()), source))),
+        None => Ok(None),
+    }
+}
+
+fn read_hash(hashes: &impl ReadableTable<u64, &'static [u8]>, height: u64) -> Result<Option<HashBytes>> {
+    Ok(ex!(hashes.get(height), storage).map(|v| {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(v.value());
+        hash
+    }))
+}
+
+/// The author, followed by the big endian height and position, so the keys sort by height.
+fn author_key(author: &PubKeyBytes, height: u64, index: u32) -> Vec<u8> {
+    let mut key = Vec::with_capacity(33 + 8 + 4);
+    key.extend_from_slice(author);
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+fn parse_author_key(key: &[u8]) -> (u64, u32) {
+    let mut height = [0u8; 8];
+    let mut index = [0u8; 4];
+    height.copy_from_slice(&key[33..41]);
+    index.copy_from_slice(&key[41..45]);
+    (u64::from_be_bytes(height), u32::from_be_bytes(index))
+}
@@ -0,0 +1,330 @@
+use std::{
+    future::Future,
+    net::{SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::{Arc, LazyLock, Mutex},
+};
+
+use hashbrown::HashMap;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_socks::{tcp::Socks5Stream, TargetAddr};
+
+use crate::{
+    config::{Config, Proxy, TransportKind},
+    error::{Error, Result},
+    ex,
+};
+
+#[cfg(feature = "quic")]
+mod quic;
+#[cfg(feature = "quic")]
+pub use quic::QuicTransport;
+
+pub type ReadHalf = Box<dyn AsyncRead + Send + Unpin>;
+pub type WriteHalf = Box<dyn AsyncWrite + Send + Unpin>;
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The logical channels of a connection. A multiplexing transport carries each on its own stream, so bulk
+/// block sync does not stall gossip and control messages.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Channel {
+    /// Handshake, neighbour discovery and everything not listed below.
+    Control,
+    /// Shared data, shared blocks and membership.
+    Gossip,
+    /// Block sync.
+    Bulk,
+}
+
+impl Channel {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Self::Control => 0,
+            Self::Gossip => 1,
+            Self::Bulk => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(Self::Control),
+            1 => Some(Self::Gossip),
+            2 => Some(Self::Bulk),
+            _ => None,
+        }
+    }
+}
+
+/// A connection, split into its reading and writing half.
+pub struct Stream {
+    pub reader: ReadHalf,
+    pub writer: WriteHalf,
+    /// Additional streams for the gossip and bulk channels, if the transport multiplexes. Messages of a missing
+    /// channel go over the control stream.
+    pub channels: Vec<(Channel, ReadHalf, WriteHalf)>,
+}
+
+impl Stream {
+    pub fn new<S: AsyncRead + AsyncWrite + Send + 'static>(sck: S) -> Self {
+        let (reader, writer) = tokio::io::split(sck);
+        Self {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            channels: Vec::new(),
+        }
+    }
+}
+
+/// Accepts inbound connections of a transport.
+pub trait Listener: Send {
+    /// Waits for the next inbound connection and returns it with the remote address.
+    fn accept(&mut self) -> BoxFuture<'_, Result<(Stream, TargetAddr<'static>)>>;
+}
+
+/// The way peers reach each other.
+pub trait Transport: Send + Sync {
+    /// Starts to listen for inbound connections.
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>>;
+
+    /// Opens a connection to another peer.
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>>;
+
+    /// The address other peers should use to connect to us.
+    fn announce(&self) -> String;
+}
+
+/// Creates the transport selected in the config.
+pub fn from_config(cfg: &Config) -> Result<Arc<dyn Transport>> {
+    Ok(match &cfg.transport {
+        TransportKind::Tcp => Arc::new(TcpTransport::new(cfg.addr, cfg.proxy.clone())),
+        #[cfg(unix)]
+        TransportKind::Unix { path } => Arc::new(UnixTransport::new(path.clone())),
+        TransportKind::Memory { name } => Arc::new(MemoryTransport::new(name.clone())),
+        #[cfg(feature = "quic")]
+        TransportKind::Quic => Arc::new(ex!(QuicTransport::new(cfg.addr, cfg.limits.handshake_timeout), source)),
+    })
+}
+
+fn domain_of(addr: &TargetAddr<'static>) -> Result<String> {
+    match addr {
+        TargetAddr::Domain(d, _) => Ok(d.to_string()),
+        TargetAddr::Ip(ip) => Err(Error::external(
+            line!(),
+            module_path!(),
+            format!("{ip} is not a valid address for this transport"),
+        )),
+    }
+}
+
+///
+/// Plain TCP, optionally via a socks5 proxy. This is the default transport.
+///
+pub struct TcpTransport {
+    addr: SocketAddr,
+    proxy: Option<Proxy>,
+}
+
+impl TcpTransport {
+    pub fn new(addr: SocketAddr, proxy: Option<Proxy>) -> Self {
+        Self { addr, proxy }
+    }
+}
+
+struct TcpAcceptor {
+    listener: TcpListener,
+}
+
+impl Listener for TcpAcceptor {
+    fn accept(&mut self) -> BoxFuture<'_, Result<(Stream, TargetAddr<'static>)>> {
+        Box::pin(async move {
+            let (sck, addr) = ex!(self.listener.accept().await, io);
+            Ok((Stream::new(sck), TargetAddr::Ip(addr)))
+        })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>> {
+        Box::pin(async move {
+            let listener = ex!(TcpListener::bind(self.addr).await, io);
+            Ok(Box::new(TcpAcceptor { listener }) as Box<dyn Listener>)
+        })
+    }
+
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>> {
+        Box::pin(async move {
+            if let Some(proxy) = &self.proxy {
+                let sck = ex!(Socks5Stream::connect(proxy.proxy, addr).await, sync);
+                Ok(Stream::new(sck.into_inner()))
+            } else {
+                let raddr = ex!(addr.to_socket_addrs(), io).next().ok_or(Error::external(
+                    line!(),
+                    module_path!(),
+                    "no socket address".into(),
+                ))?;
+                let sck = ex!(TcpStream::connect(raddr).await, io);
+                Ok(Stream::new(sck))
+            }
+        })
+    }
+
+    fn announce(&self) -> String {
+        if let Some(proxy) = &self.proxy {
+            proxy.announce_by.clone()
+        } else {
+            self.addr.to_string()
+        }
+    }
+}
+
+///
+/// Unix domain sockets, for co-located services. Addresses are written as `<path>:0`.
+///
+#[cfg(unix)]
+pub struct UnixTransport {
+    path: std::path::PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixTransport {
+    pub fn new(path: std::path::PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+#[cfg(unix)]
+struct UnixAcceptor {
+    listener: tokio::net::UnixListener,
+}
+
+#[cfg(unix)]
+impl Listener for UnixAcceptor {
+    fn accept(&mut self) -> BoxFuture<'_, Result<(Stream, TargetAddr<'static>)>> {
+        Box::pin(async move {
+            let (sck, addr) = ex!(self.listener.accept().await, io);
+            let name = addr
+                .as_pathname()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|| "unix".into());
+            Ok((Stream::new(sck), TargetAddr::Domain(name.into(), 0)))
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixTransport {
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>> {
+        Box::pin(async move {
+            if self.path.exists() {
+                ex!(std::fs::remove_file(&self.path), io);
+            }
+            let listener = ex!(tokio::net::UnixListener::bind(&self.path), io);
+            Ok(Box::new(UnixAcceptor { listener }) as Box<dyn Listener>)
+        })
+    }
+
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>> {
+        Box::pin(async move {
+            let path = ex!(domain_of(&addr), source);
+            let sck = ex!(tokio::net::UnixStream::connect(path).await, io);
+            Ok(Stream::new(sck))
+        })
+    }
+
+    fn announce(&self) -> String {
+        format!("{}:0", self.path.to_string_lossy())
+    }
+}
+
+const MEMORY_BUFFER: usize = 64 * 1024;
+
+/// The listening memory peers. Locked only briefly and never across an await, so the acceptor can deregister
+/// itself in drop under the same lock as dial.
+type MemoryRegistry = Mutex<HashMap<String, mpsc::Sender<(tokio::io::DuplexStream, String)>>>;
+
+static MEMORY: LazyLock<MemoryRegistry> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+///
+/// An in process transport, mostly for tests. Peers are addressed by name, written as `<name>:0`.
+///
+pub struct MemoryTransport {
+    name: String,
+}
+
+impl MemoryTransport {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+struct MemoryAcceptor {
+    name: String,
+    tx: mpsc::Sender<(tokio::io::DuplexStream, String)>,
+    rx: mpsc::Receiver<(tokio::io::DuplexStream, String)>,
+}
+
+impl Drop for MemoryAcceptor {
+    fn drop(&mut self) {
+        let mut registry = MEMORY.lock().unwrap_or_else(|e| e.into_inner());
+        // a new listener with the same name may have replaced us already
+        if registry.get(&self.name).is_some_and(|tx| tx.same_channel(&self.tx)) {
+            registry.remove(&self.name);
+        }
+    }
+}
+
+impl Listener for MemoryAcceptor {
+    fn accept(&mut self) -> BoxFuture<'_, Result<(Stream, TargetAddr<'static>)>> {
+        Box::pin(async move {
+            match self.rx.recv().await {
+                Some((sck, from)) => Ok((Stream::new(sck), TargetAddr::Domain(from.into(), 0))),
+                None => Err(Error::sync(line!(), module_path!(), "memory transport closed")),
+            }
+        })
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>> {
+        Box::pin(async move {
+            let (tx, rx) = mpsc::channel(16);
+            MEMORY
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(self.name.clone(), tx.clone());
+            Ok(Box::new(MemoryAcceptor {
+                name: self.name.clone(),
+                tx,
+                rx,
+            }) as Box<dyn Listener>)
+        })
+    }
+
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>> {
+        Box::pin(async move {
+            let name = ex!(domain_of(&addr), source);
+            let to = MEMORY.lock().unwrap_or_else(|e| e.into_inner()).get(&name).cloned();
+
+            match to {
+                Some(to) => {
+                    let (local, remote) = tokio::io::duplex(MEMORY_BUFFER);
+                    ex!(to.send((remote, self.name.clone())).await, sync);
+                    Ok(Stream::new(local))
+                }
+                None => Err(Error::external(
+                    line!(),
+                    module_path!(),
+                    format!("no memory peer named {name}"),
+                )),
+            }
+        })
+    }
+
+    fn announce(&self) -> String {
+        format!("{}:0", self.name)
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    ClientConfig, Endpoint, ServerConfig,
+};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    select,
+    sync::mpsc,
+    time,
+};
+use tokio_socks::TargetAddr;
+
+use super::{BoxFuture, Channel, Listener, ReadHalf, Stream, Transport, WriteHalf};
+use crate::{
+    error::{Error, Result},
+    ex,
+};
+
+const ALPN: &[u8] = b"mccloud";
+const SERVER_NAME: &str = "mccloud";
+
+///
+/// QUIC with one stream per channel.
+///
+/// The TLS certificate is self signed, created at startup and not bound to the node key. TLS only encrypts the
+/// connection; it does not tell who is on the other side, so anyone can answer a dial. The peers authenticate
+/// each other with their secp256k1 node keys in the handshake on the control stream, and all frames are encrypted
+/// again with the session keys of that handshake.
+///
+pub struct QuicTransport {
+    endpoint: Endpoint,
+    timeout: Duration,
+}
+
+/// Accepts any certificate, as the certificates are self signed and not bound to the node key. It only checks that
+/// the TLS handshake is signed by the certificate. The peer is authenticated by the mccloud handshake afterwards,
+/// which a dial must not skip.
+#[derive(Debug)]
+struct AcceptAnyCert {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn server_config(provider: Arc<CryptoProvider>) -> Result<ServerConfig> {
+    let cert = ex!(rcgen::generate_simple_self_signed(vec![SERVER_NAME.into()]), encrypt);
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(cert.signing_key.serialize_der()));
+
+    let crypto = ex!(
+        rustls::ServerConfig::builder_with_provider(provider).with_protocol_versions(&[&rustls::version::TLS13]),
+        encrypt
+    )
+    .with_no_client_auth();
+    let mut crypto = ex!(crypto.with_single_cert(vec![cert.cert.der().clone()], key), encrypt);
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+    let crypto = ex!(QuicServerConfig::try_from(crypto), encrypt);
+    Ok(ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+fn client_config(provider: Arc<CryptoProvider>) -> Result<ClientConfig> {
+    let verifier = Arc::new(AcceptAnyCert {
+        provider: provider.clone(),
+    });
+    let mut crypto = ex!(
+        rustls::ClientConfig::builder_with_provider(provider).with_protocol_versions(&[&rustls::version::TLS13]),
+        encrypt
+    )
+    .dangerous()
+    .with_custom_certificate_verifier(verifier)
+    .with_no_client_auth();
+    crypto.alpn_protocols = vec![ALPN.to_vec()];
+
+    let crypto = ex!(QuicClientConfig::try_from(crypto), encrypt);
+    Ok(ClientConfig::new(Arc::new(crypto)))
+}
+
+impl QuicTransport {
+    /// Binds the UDP socket used for inbound and outbound connections.
+    pub fn new(addr: SocketAddr, timeout: Duration) -> Result<Self> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut endpoint = ex!(Endpoint::server(ex!(server_config(provider.clone()), source), addr), io);
+        endpoint.set_default_client_config(ex!(client_config(provider), source));
+
+        Ok(Self { endpoint, timeout })
+    }
+}
+
+/// How many set up connections may wait for the peer to accept them.
+const ACCEPT_QUEUE: usize = 16;
+
+struct QuicAcceptor {
+    rx: mpsc::Receiver<(Stream, TargetAddr<'static>)>,
+}
+
+/// Accepts the connections of the endpoint, until the acceptor is dropped. Each connection is set up in its own
+/// task, so a slow client does not hold up the others.
+async fn accept_loop(endpoint: Endpoint, timeout: Duration, tx: mpsc::Sender<(Stream, TargetAddr<'static>)>) {
+    loop {
+        let incoming = select! {
+            _ = tx.closed() => return,
+            incoming = endpoint.accept() => incoming,
+        };
+        let Some(incoming) = incoming else {
+            return;
+        };
+
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            match time::timeout(timeout, accept_incoming(incoming)).await {
+                Ok(Ok(res)) => {
+                    let _ = tx.send(res).await;
+                }
+                Ok(Err(e)) => tracing::warn!("quic accept: {e}"),
+                Err(e) => tracing::warn!("quic accept: {e}"),
+            }
+        });
+    }
+}
+
+async fn accept_channels(conn: quinn::Connection) -> Result<Stream> {
+    let mut control = None;
+    let mut channels = Vec::new();
+
+    while control.is_none() || channels.len() < 2 {
+        let (writer, mut reader) = ex!(conn.accept_bi().await, sync);
+        let tag = ex!(reader.read_u8().await, io);
+        let reader: ReadHalf = Box::new(reader);
+        let writer: WriteHalf = Box::new(writer);
+
+        match Channel::from_tag(tag) {
+            Some(Channel::Control) if control.is_none() => control = Some((reader, writer)),
+            Some(channel) if channel != Channel::Control && channels.iter().all(|(c, _, _)| *c != channel) => {
+                channels.push((channel, reader, writer))
+            }
+            Some(_) => return Err(Error::protocol(line!(), module_path!(), "repeated quic channel")),
+            None => return Err(Error::protocol(line!(), module_path!(), "unknown quic channel")),
+        }
+    }
+
+    let (reader, writer) = control.unwrap();
+    Ok(Stream {
+        reader,
+        writer,
+        channels,
+    })
+}
+
+async fn accept_incoming(incoming: quinn::Incoming) -> Result<(Stream, TargetAddr<'static>)> {
+    let conn = ex!(incoming.await, sync);
+    let addr = conn.remote_address();
+    let stream = ex!(accept_channels(conn).await, source);
+
+    Ok((stream, TargetAddr::Ip(addr)))
+}
+
+impl Listener for QuicAcceptor {
+    fn accept(&mut self) -> BoxFuture<'_, Result<(Stream, TargetAddr<'static>)>> {
+        Box::pin(async move {
+            match self.rx.recv().await {
+                Some(res) => Ok(res),
+                None => Err(Error::sync(line!(), module_path!(), "quic endpoint closed")),
+            }
+        })
+    }
+}
+
+impl Transport for QuicTransport {
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>> {
+        Box::pin(async move {
+            let (tx, rx) = mpsc::channel(ACCEPT_QUEUE);
+            tokio::spawn(accept_loop(self.endpoint.clone(), self.timeout, tx));
+            Ok(Box::new(QuicAcceptor { rx }) as Box<dyn Listener>)
+        })
+    }
+
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>> {
+        Box::pin(async move {
+            let raddr = ex!(addr.to_socket_addrs(), io).next().ok_or(Error::external(
+                line!(),
+                module_path!(),
+                "no socket address".into(),
+            ))?;
+            let conn = ex!(ex!(self.endpoint.connect(raddr, SERVER_NAME), sync).await, sync);
+
+            let mut streams = Vec::new();
+            for channel in [Channel::Control, Channel::Gossip, Channel::Bulk] {
+                let (mut writer, reader) = ex!(conn.open_bi().await, sync);
+                // a quic stream is only visible to the other side, after something was written to it
+                ex!(writer.write_u8(channel.tag()).await, io);
+                streams.push((channel, Box::new(reader) as ReadHalf, Box::new(writer) as WriteHalf));
+            }
+
+            let (_, reader, writer) = streams.remove(0);
+            Ok(Stream {
+                reader,
+                writer,
+                channels: streams,
+            })
+        })
+    }
+
+    fn announce(&self) -> String {
+        self.endpoint.local_addr().map(|a| a.to_string()).unwrap_or_default()
+    }
+}
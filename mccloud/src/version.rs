@@ -1,4 +1,7 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    ops::{BitAnd, BitOr},
+};
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
@@ -32,3 +35,129 @@ impl Display for Version {
         write!(f, "v{}.{}.{}", self.major, self.minor, self.patch,)
     }
 }
+
+/// The oldest wire protocol version this build can talk to. It is only raised, when the greeting or frame layout of
+/// that version is no longer decoded. New messages go behind a capability instead.
+///
+/// Version 4 is the first with the preamble. Earlier versions can not be told apart from a foreign protocol.
+pub const PROTOCOL_MIN: u16 = 4;
+/// The newest wire protocol version this build speaks.
+pub const PROTOCOL_MAX: u16 = 4;
+
+/// The bytes in front of each preamble.
+const PREAMBLE_MAGIC: &[u8; 4] = b"mccl";
+/// The size of the preamble: magic, min and max version, capabilities.
+pub const PREAMBLE_SIZE: usize = 16;
+
+/// A set of optional protocol features. Messages of a feature are only sent to peers which advertise it.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Capabilities(u64);
+
+impl Capabilities {
+    pub const NONE: Self = Self(0);
+    /// Understands `Rekey` and renews the session keys of long lived connections.
+    pub const REKEY: Self = Self(1 << 0);
+    /// Answers `Ping` with `Pong`.
+    pub const PING: Self = Self(1 << 1);
+    /// Exchanges address records with `Addresses`.
+    pub const ADDRESSES: Self = Self(1 << 2);
+    /// Answers `RequestAddresses` with a sample of its address book.
+    pub const EXCHANGE: Self = Self(1 << 3);
+    /// Everything this build supports.
+    pub const ALL: Self = Self(Self::REKEY.0 | Self::PING.0 | Self::ADDRESSES.0 | Self::EXCHANGE.0);
+
+    pub const fn bits(&self) -> u64 {
+        self.0
+    }
+
+    pub const fn from_bits(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns true if all capabilities of `other` are in this set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Self;
+
+    fn bitand(self, o: Self) -> Self {
+        Self(self.0 & o.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, o: Self) -> Self {
+        Self(self.0 | o.0)
+    }
+}
+
+/// What a peer advertises in its greeting: the range of protocol versions it speaks and its capabilities.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Protocol {
+    pub min: u16,
+    pub max: u16,
+    pub capabilities: Capabilities,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Self {
+            min: PROTOCOL_MIN,
+            max: PROTOCOL_MAX,
+            capabilities: Capabilities::ALL,
+        }
+    }
+}
+
+impl Protocol {
+    /// Returns the highest protocol version both sides speak, with the capabilities both sides have. Returns
+    /// None if the version ranges do not overlap.
+    pub fn negotiate(&self, other: &Protocol) -> Option<(u16, Capabilities)> {
+        let version = self.max.min(other.max);
+
+        if version < self.min.max(other.min) {
+            None
+        } else {
+            Some((version, self.capabilities & other.capabilities))
+        }
+    }
+
+    /// Encodes the preamble, which each side sends before anything else. Its layout never changes, so peers of any
+    /// two versions can tell if they have a common protocol version, before they decode a greeting.
+    pub fn preamble(&self) -> [u8; PREAMBLE_SIZE] {
+        let mut bytes = [0u8; PREAMBLE_SIZE];
+        bytes[..4].copy_from_slice(PREAMBLE_MAGIC);
+        bytes[4..6].copy_from_slice(&self.min.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.max.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.capabilities.0.to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a preamble. Returns None if it does not start with the magic bytes.
+    pub fn from_preamble(bytes: &[u8; PREAMBLE_SIZE]) -> Option<Self> {
+        if &bytes[..4] != PREAMBLE_MAGIC {
+            return None;
+        }
+
+        Some(Self {
+            min: u16::from_le_bytes([bytes[4], bytes[5]]),
+            max: u16::from_le_bytes([bytes[6], bytes[7]]),
+            capabilities: Capabilities(u64::from_le_bytes(bytes[8..].try_into().unwrap())),
+        })
+    }
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "protocol {}..={} caps {:#x}",
+            self.min, self.max, self.capabilities.0
+        )
+    }
+}
@@ -0,0 +1,129 @@
+use k256::SecretKey;
+use mccloud::{PubKeyBytes, TargetAddr};
+use std::time::Duration;
+
+mod utils;
+
+use utils::create_key;
+
+#[tokio::test]
+async fn permissioned() {
+    let _e = utils::init_log("data/access_permissioned.log").entered();
+
+    let keys: Vec<(PubKeyBytes, SecretKey)> = (0..3).map(|_| create_key()).collect();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let mut i = 0;
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.key = Some(keys[i].1.to_bytes().into());
+            if i == 0 {
+                cfg.access.permissioned = true;
+                cfg.access.allow = vec![keys[1].0];
+            }
+            i += 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert!(clients.contains(&keys[1].0));
+    assert!(
+        !clients.contains(&keys[2].0),
+        "peer outside the allow list was accepted"
+    );
+
+    assert!(peers[0].allow_peer(keys[2].0).await);
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(peers[0].client_pubkeys().await.contains(&keys[2].0));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn deny_at_runtime() {
+    let _e = utils::init_log("data/access_deny.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    assert!(peers[0].deny_peer(peers[1].pubkey()).await);
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(
+        peers[0].client_pubkeys().await.is_empty(),
+        "denied peer is still connected"
+    );
+    assert!(!peers[0].known_pubkeys().await.contains(&peers[1].pubkey()));
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(
+        peers[0].client_pubkeys().await.is_empty(),
+        "denied peer could reconnect"
+    );
+
+    assert!(peers[0].undeny_peer(&peers[1].pubkey()).await);
+    assert_eq!(peers[0].denied_peers().await, Vec::<PubKeyBytes>::new());
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn sync_denied_history() {
+    let _e = utils::init_log("data/access_sync_denied_history.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    peers[1].share(b"before the deny".to_vec()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    // the deny list keeps out new data of the peer, not the history everyone agreed on
+    let denied = peers[1].pubkey();
+    let late = cl.create_with(1, false, |cfg| cfg.access.deny = vec![denied]).await;
+    let mut rx = late[0].last_block_receiver();
+    late[0].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let blocks = utils::all_blocks(&late[0]).await;
+    assert!(blocks.iter().any(|b| b.data.iter().any(|d| d.author == denied)));
+
+    cl.shutdown();
+    cl.cleanup();
+}
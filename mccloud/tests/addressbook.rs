@@ -0,0 +1,148 @@
+use mccloud::{Peer, TargetAddr};
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn rejoin_after_restart() {
+    let _e = utils::init_log("data/addressbook_rejoin.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(3, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // peer 1 knows peer 0 from its connection, and peer 2 from the records of peer 0
+    let book: Vec<_> = peers[1].address_book().await.into_iter().map(|r| r.pubkey).collect();
+    assert!(book.contains(&peers[0].pubkey()));
+    assert!(book.contains(&peers[2].pubkey()));
+
+    peers[1].shutdown_async().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let restarted = Peer::new_async(peers[1].cfg.clone()).await.unwrap();
+    assert_eq!(restarted.address_book().await.len(), 2);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let clients = restarted.client_pubkeys().await;
+    assert!(
+        clients.contains(&peers[0].pubkey()),
+        "did not rejoin via the address book"
+    );
+    assert!(clients.contains(&peers[2].pubkey()));
+
+    restarted.shutdown().unwrap();
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn exchange_heals_line() {
+    let _e = utils::init_log("data/addressbook_exchange.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl
+        .create_with(5, false, |cfg| {
+            cfg.relationship.count = 4;
+            cfg.relationship.time = Duration::from_millis(100);
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    for i in 1..peers.len() {
+        peers[i].connect(TargetAddr::Ip(peers[i - 1].cfg.addr)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+
+    for (i, p) in peers.iter().enumerate() {
+        assert_eq!(
+            p.client_pubkeys().await.len(),
+            4,
+            "peer({i}) is not connected to all others"
+        );
+    }
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn anchors_after_restart() {
+    let _e = utils::init_log("data/addressbook_anchors.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.relationship.count = 1;
+            cfg.relationship.anchors = 1;
+            cfg.relationship.time = Duration::from_millis(100);
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // peer 1 is the longest connected, but peer 2 the most recently seen
+    peers[0].connect(TargetAddr::Ip(peers[1].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    peers[0].connect(TargetAddr::Ip(peers[2].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    peers[0].shutdown_async().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let restarted = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let clients = restarted.client_pubkeys().await;
+    assert_eq!(clients.len(), 1);
+    assert!(
+        clients.contains(&peers[1].pubkey()),
+        "did not connect to the anchor first"
+    );
+
+    restarted.shutdown().unwrap();
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn rotate_outbound() {
+    let _e = utils::init_log("data/addressbook_rotate.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(30);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.relationship.count = 1;
+            cfg.relationship.anchors = 0;
+            cfg.relationship.rotate = Duration::from_millis(500);
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[2].connect(TargetAddr::Ip(peers[1].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    // peer 0 learns about peer 2 from peer 1
+    peers[0].connect(TargetAddr::Ip(peers[1].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert_eq!(clients.len(), 1);
+    assert!(clients.contains(&peers[2].pubkey()), "the connection was not rotated");
+
+    cl.shutdown();
+    cl.cleanup();
+}
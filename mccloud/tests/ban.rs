@@ -0,0 +1,103 @@
+use mccloud::{
+    ban::{Banned, Bans},
+    config::{Config, Scoring},
+    error::ErrorKind,
+    TargetAddr,
+};
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn relay_denied_data() {
+    let _e = utils::init_log("data/ban_relay_denied.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(3, false).await;
+
+    // peer 1 relays the data of peer 2, which peer 0 does not accept
+    peers[0].deny_peer(peers[2].pubkey()).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    peers[2].connect(TargetAddr::Ip(peers[1].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    peers[2].share(b"first".to_vec()).await.unwrap();
+    peers[2].share(b"second".to_vec()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // the relay did nothing wrong
+    assert_eq!(peers[0].score(&peers[1].pubkey()).await, 100);
+    assert!(peers[0].bans().await.is_empty());
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[test]
+fn ban_misbehaving_peer() {
+    let _e = utils::init_log("data/ban_misbehaving.log").entered();
+
+    let mut bans = Bans::new(Scoring {
+        score: 100,
+        protocol: 60,
+        regain: Duration::from_millis(100),
+        ..Config::default().scoring
+    });
+    let (pubkey, _) = utils::create_key();
+    let host = Banned::Host("192.0.2.1".into());
+
+    assert!(!bans.penalize(&pubkey, Some(host.clone()), ErrorKind::Protocol));
+    assert_eq!(bans.score(&pubkey), 40);
+
+    // neither idle peers nor relays of denied data lose points
+    assert!(!bans.penalize(&pubkey, Some(host.clone()), ErrorKind::Timeout));
+    assert!(!bans.penalize(&pubkey, Some(host.clone()), ErrorKind::Denied));
+    assert_eq!(bans.score(&pubkey), 40);
+
+    std::thread::sleep(Duration::from_millis(250));
+    assert_eq!(bans.score(&pubkey), 42);
+
+    assert!(bans.penalize(&pubkey, Some(host.clone()), ErrorKind::Protocol));
+    assert!(bans.is_banned(&Banned::Pubkey(pubkey)));
+    assert!(bans.is_banned(&host));
+    assert_eq!(bans.score(&pubkey), 100);
+
+    bans.clear();
+    assert!(bans.list().is_empty());
+}
+
+#[tokio::test]
+async fn manual_ban() {
+    let _e = utils::init_log("data/ban_manual.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    let banned = Banned::Pubkey(peers[1].pubkey());
+    peers[0].ban(banned.clone(), Duration::from_millis(300)).await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(peers[0].client_pubkeys().await.is_empty());
+
+    // the ban runs out
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(peers[0].bans().await.is_empty());
+    assert!(!peers[0].unban(&banned).await);
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    cl.shutdown();
+    cl.cleanup();
+}
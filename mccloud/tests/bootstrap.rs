@@ -0,0 +1,116 @@
+use mccloud::config::Discovery;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::net::TcpListener;
+
+mod utils;
+
+#[tokio::test]
+async fn retry_seeds() {
+    let _e = utils::init_log("data/bootstrap_seeds.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let seed = ([127, 0, 0, 1], 29093).into();
+    let peers = cl
+        .create_with(1, false, |cfg| {
+            cfg.bootstrap.seeds = vec!["127.0.0.1:29093".into()];
+            cfg.bootstrap.retry = Duration::from_millis(100);
+        })
+        .await;
+
+    // the seed is not up yet, on the first try
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(peers[0].client_pubkeys().await.is_empty());
+
+    let seeds = cl.create(1, false).await;
+    assert_eq!(seeds[0].cfg.addr, seed);
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+
+    assert!(
+        peers[0].client_pubkeys().await.contains(&seeds[0].pubkey()),
+        "seed was not retried"
+    );
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn lan_discovery() {
+    let _e = utils::init_log("data/bootstrap_discovery.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.discovery = Some(Discovery {
+                group: SocketAddrV4::new(Ipv4Addr::new(239, 255, 29, 92), 29190),
+                interface: Ipv4Addr::LOCALHOST,
+                interval: Duration::from_millis(50),
+            });
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    for p in peers.iter() {
+        assert_eq!(
+            p.client_pubkeys().await.len(),
+            2,
+            "peer {} did not find the others",
+            p.pubhex()
+        );
+    }
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn forged_beacon() {
+    let _e = utils::init_log("data/bootstrap_forged_beacon.log").entered();
+
+    let group = SocketAddrV4::new(Ipv4Addr::new(239, 255, 29, 92), 29191);
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl
+        .create_with(1, false, |cfg| {
+            cfg.discovery = Some(Discovery {
+                group,
+                interface: Ipv4Addr::LOCALHOST,
+                interval: Duration::from_millis(50),
+            });
+        })
+        .await;
+
+    // only a peer with a higher public key is dialed
+    let pubkey = loop {
+        let (pubkey, _) = utils::create_key();
+        if pubkey > peers[0].pubkey() {
+            break pubkey;
+        }
+    };
+
+    // a beacon in the name of that peer, which points to our listener
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let beacon = borsh::to_vec(&(peers[0].network(), pubkey, port, now, [7u8; 64])).unwrap();
+
+    let sck = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).unwrap();
+    sck.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+    sck.bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)).into()).unwrap();
+    let sck: UdpSocket = sck.into();
+    for _ in 0..5 {
+        sck.send_to(&beacon, group).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let dialed = tokio::time::timeout(Duration::from_millis(300), listener.accept()).await;
+    assert!(dialed.is_err(), "forged beacon was dialed");
+
+    cl.shutdown();
+    cl.cleanup();
+}
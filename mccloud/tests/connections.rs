@@ -0,0 +1,111 @@
+use mccloud::IntoTargetAddr;
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn simultaneous_connect() {
+    let _e = utils::init_log("data/connections_simultaneous.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    let (r0, r1) = tokio::join!(
+        peers[0].connect(peers[1].cfg.addr.into_target_addr().unwrap()),
+        peers[1].connect(peers[0].cfg.addr.into_target_addr().unwrap()),
+    );
+    r0.unwrap();
+    r1.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+    assert!(peers[1].client_pubkeys().await.contains(&peers[0].pubkey()));
+
+    // a reconnect for the closed duplicate is dropped, as the peer is still connected
+    tokio::time::sleep(peers[0].cfg.relationship.reconnect + Duration::from_millis(250)).await;
+
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+    assert!(peers[1].client_pubkeys().await.contains(&peers[0].pubkey()));
+    assert!(peers[0].pending_reconnects().await.is_empty());
+    assert!(peers[1].pending_reconnects().await.is_empty());
+    assert_eq!(peers[0].known_pubkeys().await.len(), 1);
+    assert_eq!(peers[1].known_pubkeys().await.len(), 1);
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn inbound_eviction() {
+    let _e = utils::init_log("data/connections_inbound.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.connections.inbound = 1;
+            // no outbound connections to peers learned from the address book
+            cfg.relationship.count = 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    let addr = peers[0].cfg.addr.into_target_addr().unwrap();
+    peers[1].connect(addr.to_owned()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    peers[2].connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert_eq!(clients.len(), 1);
+    assert!(
+        clients.contains(&peers[2].pubkey()),
+        "the new peer did not evict the old one"
+    );
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn outbound_limit() {
+    let _e = utils::init_log("data/connections_outbound.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.connections.outbound = 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[0]
+        .connect(peers[1].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[0]
+        .connect(peers[2].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(peers[0].client_pubkeys().await.len(), 1);
+
+    // persistent peers are not limited
+    peers[0]
+        .connect_persistent(peers[2].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(peers[0].client_pubkeys().await.len(), 2);
+
+    cl.shutdown();
+    cl.cleanup();
+}
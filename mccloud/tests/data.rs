@@ -9,8 +9,8 @@ async fn send_single_data() {
 
     let mut cl = utils::cluster::Cluster::new(10);
 
-    let peers = cl.create(2, false);
-    let clients = cl.create(1, true);
+    let peers = cl.create(2, false).await;
+    let clients = cl.create(1, true).await;
 
     tokio::time::sleep(Duration::from_millis(200)).await;
 
@@ -25,7 +25,7 @@ async fn send_single_data() {
 
     tokio::time::sleep(Duration::from_millis(200)).await;
 
-    clients[0].share(b"my data".to_vec().into()).await.unwrap();
+    clients[0].share(b"my data".to_vec()).await.unwrap();
 
     tokio::time::sleep(Duration::from_millis(500)).await;
 
@@ -39,13 +39,13 @@ async fn data_reboot() {
 
     let mut cl = utils::cluster::Cluster::new(0);
 
-    let mut peers = cl.create(1, false);
+    let mut peers = cl.create(1, false).await;
     let sleep = 100;
 
     tokio::time::sleep(Duration::from_millis(sleep)).await;
 
     let cfg = peers[0].cfg.clone();
-    peers[0].share(b"bla bla".to_vec().into()).await.unwrap();
+    peers[0].share(b"bla bla".to_vec()).await.unwrap();
 
     tokio::time::sleep(Duration::from_millis(sleep)).await;
 
@@ -53,11 +53,11 @@ async fn data_reboot() {
 
     tokio::time::sleep(Duration::from_millis(sleep)).await;
 
-    peers[0] = Peer::new(cfg).unwrap();
+    peers[0] = Peer::new_async(cfg).await.unwrap();
 
     tokio::time::sleep(Duration::from_millis(sleep)).await;
 
-    peers[0].share(b"bla bla".to_vec().into()).await.unwrap();
+    peers[0].share(b"bla bla".to_vec()).await.unwrap();
 
     tokio::time::sleep(Duration::from_millis(sleep)).await;
 
@@ -9,8 +9,8 @@ async fn five_peers() {
 
     let mut cl = utils::cluster::Cluster::new(0);
 
-    let three = cl.create(3, false);
-    let two = cl.create(2, false);
+    let three = cl.create(3, false).await;
+    let two = cl.create(2, false).await;
 
     let time = Duration::from_millis(250);
 
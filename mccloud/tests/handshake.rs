@@ -0,0 +1,98 @@
+use mccloud::handshake::Handshake;
+
+mod utils;
+
+use utils::create_key;
+
+#[test]
+fn handshake_valid() {
+    let _e = utils::init_log("data/handshake_valid.log").entered();
+
+    let (pubkey, prikey) = create_key();
+    let alice = Handshake::new();
+    let bob = Handshake::new();
+
+    let (alice_send, alice_recv) = alice.session_keys(&bob.ephemeral).unwrap();
+    let (bob_send, bob_recv) = bob.session_keys(&alice.ephemeral).unwrap();
+    assert_eq!(alice_send, bob_recv);
+    assert_eq!(alice_recv, bob_send);
+    assert_ne!(alice_send, alice_recv);
+
+    let sign = alice.sign(&bob.ephemeral, &pubkey, &prikey).unwrap();
+    bob.verify(&alice.ephemeral, &pubkey, &sign).unwrap();
+}
+
+#[test]
+fn handshake_forged_signature() {
+    let _e = utils::init_log("data/handshake_forged_signature.log").entered();
+
+    let (pubkey, prikey) = create_key();
+    let alice = Handshake::new();
+    let bob = Handshake::new();
+
+    let mut sign = alice.sign(&bob.ephemeral, &pubkey, &prikey).unwrap();
+    sign[10] ^= 1;
+    assert!(bob.verify(&alice.ephemeral, &pubkey, &sign).is_err());
+
+    assert!(bob.verify(&alice.ephemeral, &pubkey, &[0u8; 64]).is_err());
+}
+
+#[test]
+fn handshake_mismatched_pubkey() {
+    let _e = utils::init_log("data/handshake_mismatched_pubkey.log").entered();
+
+    let (pubkey, prikey) = create_key();
+    let (other, _) = create_key();
+    let alice = Handshake::new();
+    let bob = Handshake::new();
+
+    // signed with our key, but claims to be another peer
+    let sign = alice.sign(&bob.ephemeral, &other, &prikey).unwrap();
+    assert!(bob.verify(&alice.ephemeral, &other, &sign).is_err());
+
+    // signed for our key, but presented as another peer
+    let sign = alice.sign(&bob.ephemeral, &pubkey, &prikey).unwrap();
+    assert!(bob.verify(&alice.ephemeral, &other, &sign).is_err());
+}
+
+#[test]
+fn handshake_mismatched_ephemeral() {
+    let _e = utils::init_log("data/handshake_mismatched_ephemeral.log").entered();
+
+    let (pubkey, prikey) = create_key();
+    let alice = Handshake::new();
+    let bob = Handshake::new();
+    let mallory = Handshake::new();
+
+    // a man in the middle swapped the ephemeral key of alice for its own
+    let (bob_send, bob_recv) = bob.session_keys(&mallory.ephemeral).unwrap();
+    let (alice_send, alice_recv) = alice.session_keys(&bob.ephemeral).unwrap();
+    assert_ne!(alice_send, bob_recv);
+    assert_ne!(alice_recv, bob_send);
+
+    // the signature of alice does not cover the ephemeral key bob saw
+    let sign = alice.sign(&bob.ephemeral, &pubkey, &prikey).unwrap();
+    assert!(bob.verify(&mallory.ephemeral, &pubkey, &sign).is_err());
+
+    assert!(alice.session_keys(&[0u8; 33]).is_err());
+}
+
+#[test]
+fn handshake_replayed() {
+    let _e = utils::init_log("data/handshake_replayed.log").entered();
+
+    let (pubkey, prikey) = create_key();
+    let alice = Handshake::new();
+    let bob = Handshake::new();
+
+    let sign = alice.sign(&bob.ephemeral, &pubkey, &prikey).unwrap();
+    bob.verify(&alice.ephemeral, &pubkey, &sign).unwrap();
+
+    // the same greeting replayed to a new connection of bob
+    let bob = Handshake::new();
+    assert!(bob.verify(&alice.ephemeral, &pubkey, &sign).is_err());
+
+    // and to another peer
+    let carol = Handshake::new();
+    assert!(carol.verify(&alice.ephemeral, &pubkey, &sign).is_err());
+}
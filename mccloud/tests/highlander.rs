@@ -4,10 +4,7 @@ use std::{
 };
 
 use bytesize::ByteSize;
-use k256::{
-    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
-    SecretKey,
-};
+use k256::SecretKey;
 use mccloud::{
     highlander::{Game, Highlander},
     PubKeyBytes,
@@ -16,14 +13,7 @@ use rayon::prelude::*;
 
 mod utils;
 
-fn create_key() -> (PubKeyBytes, SecretKey) {
-    let private = SecretKey::random(&mut OsRng);
-    let public = private.public_key().to_encoded_point(true);
-    let bytes = public.to_bytes();
-    let mut pubkey = [0u8; 33];
-    pubkey.copy_from_slice(&bytes);
-    (pubkey, private)
-}
+use utils::create_key;
 
 #[test]
 fn even_numbered() {
@@ -31,7 +21,7 @@ fn even_numbered() {
 
     let mut hl0 = Highlander::new();
     let mut hl1 = Highlander::new();
-    let keys = vec![create_key(), create_key()];
+    let keys = [create_key(), create_key()];
     let pubkeys: Vec<PubKeyBytes> = keys.iter().map(|n| n.0).collect();
 
     hl0.populate_roster(pubkeys.iter());
@@ -68,7 +58,7 @@ fn odd_numbered() {
     let mut hl0 = Highlander::new();
     let mut hl1 = Highlander::new();
     let mut hl2 = Highlander::new();
-    let keys = vec![create_key(), create_key(), create_key()];
+    let keys = [create_key(), create_key(), create_key()];
     let pubkeys: Vec<PubKeyBytes> = keys.iter().map(|k| k.0).collect();
 
     hl0.populate_roster(pubkeys.iter());
@@ -103,7 +93,7 @@ fn odd_numbered() {
     }
 }
 
-fn compress_zst(data: &Vec<u8>, lvl: i32) {
+fn compress_zst(data: &[u8], lvl: i32) {
     let start = SystemTime::now();
     // let compressed = zstd::stream::encode_all(&*data, 1).unwrap();
     let cursor = Cursor::new(Vec::new());
@@ -120,14 +110,13 @@ fn nine() {
     let _e = utils::init_log("data/highlander_nine.log").entered();
 
     let num = 9;
-    let mut hls: Vec<Highlander> = (0..num).into_iter().map(|_| Highlander::new()).collect();
-    let keys: Vec<(PubKeyBytes, SecretKey)> = (0..num).into_iter().map(|_| create_key()).collect();
+    let mut hls: Vec<Highlander> = (0..num).map(|_| Highlander::new()).collect();
+    let keys: Vec<(PubKeyBytes, SecretKey)> = (0..num).map(|_| create_key()).collect();
     let pubkeys: Vec<PubKeyBytes> = keys.iter().map(|k| k.0).collect();
 
     hls.par_iter_mut().for_each(|h| h.populate_roster(pubkeys.iter()));
 
     let games: Vec<Game> = (0..num)
-        .into_iter()
         .map(|i| hls[i].create_game(&keys[i].0, &keys[i].1).unwrap())
         .collect();
 
@@ -142,7 +131,7 @@ fn nine() {
     }
 
     tracing::info!("choices {:?}", games[0].rounds);
-    let res: Vec<PubKeyBytes> = (0..num).into_iter().map(|i| hls[i].evaluate()).collect();
+    let res: Vec<PubKeyBytes> = (0..num).map(|i| hls[i].evaluate()).collect();
 
     let mut all_equal = true;
     for i in 1..num {
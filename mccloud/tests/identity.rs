@@ -0,0 +1,50 @@
+use k256::{
+    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
+    SecretKey,
+};
+use mccloud::{config::Config, Peer};
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn identity_survives_restart() {
+    let _e = utils::init_log("data/identity_survives_restart.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+
+    let mut peers = cl.create(1, false).await;
+    let pubkey = peers[0].pubkey();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[0].shutdown().unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[0] = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+
+    assert_eq!(peers[0].pubkey(), pubkey);
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn identity_from_config() {
+    let _e = utils::init_log("data/identity_from_config.log").entered();
+
+    let key = SecretKey::random(&mut OsRng);
+    let cfg = Config {
+        addr: ([127, 0, 0, 1], 29102).into(),
+        folder: "data/identity29102".into(),
+        key: Some(key.to_bytes().into()),
+        ..Default::default()
+    };
+    let peer = Peer::new_async(cfg).await.unwrap();
+
+    assert_eq!(&peer.pubkey()[..], key.public_key().to_encoded_point(true).as_bytes());
+    assert!(!peer.cfg.folder.join(mccloud::identity::KEY_FILE).exists());
+
+    peer.shutdown().unwrap();
+    std::fs::remove_dir_all(&peer.cfg.folder).unwrap();
+}
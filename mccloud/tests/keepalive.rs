@@ -0,0 +1,95 @@
+use k256::{elliptic_curve::rand_core::OsRng, SecretKey};
+use mccloud::{
+    config::{Config, Keepalive, TransportKind},
+    transport::MemoryTransport,
+    IntoTargetAddr, Peer, TargetAddr,
+};
+use std::{sync::Arc, time::Duration};
+use utils::stall::{Stall, StallingTransport};
+
+mod utils;
+
+#[tokio::test]
+async fn measure_rtt() {
+    let _e = utils::init_log("data/keepalive_rtt.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.keepalive.interval = Duration::from_millis(50);
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let rtt = peers[1]
+        .client_rtt(&peers[0].pubkey())
+        .await
+        .expect("no round trip time");
+    assert!(rtt < Duration::from_secs(1));
+    assert!(peers[0].client_rtts().await.contains_key(&peers[1].pubkey()));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn drop_dead_connection() {
+    let _e = utils::init_log("data/keepalive_dead.log").entered();
+
+    let stall = Stall::new();
+    let name = |i: u16| format!("keepalive-{i}");
+
+    let mut configs = utils::configs::ServerConfigs::new(10);
+    let mut peers = Vec::new();
+    for i in 0..2 {
+        let cfg = Config {
+            transport: TransportKind::Memory { name: name(i) },
+            keepalive: Keepalive {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_millis(300),
+            },
+            ..configs.next().unwrap()
+        };
+
+        let peer = if i == 0 {
+            let transport = StallingTransport {
+                inner: MemoryTransport::new(name(0)),
+                slow: name(1),
+                stall: stall.clone(),
+            };
+            Peer::with_transport(cfg, SecretKey::random(&mut OsRng), Arc::new(transport)).unwrap()
+        } else {
+            Peer::new_async(cfg).await.unwrap()
+        };
+        peers.push(peer);
+    }
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let addr = format!("{}:0", name(1));
+    peers[0].connect(addr.into_target_addr().unwrap()).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(peers[1].client_pubkeys().await.contains(&peers[0].pubkey()));
+
+    // peer 0 no longer answers, but the connection stays open
+    stall.set(true);
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert!(
+        !peers[1].client_pubkeys().await.contains(&peers[0].pubkey()),
+        "dead connection was not dropped"
+    );
+
+    for p in peers.iter() {
+        p.shutdown().unwrap();
+    }
+    for p in peers.iter() {
+        let _ = std::fs::remove_dir_all(&p.cfg.folder);
+    }
+}
@@ -0,0 +1,82 @@
+use mccloud::{
+    config::Config,
+    keystore::{KdfParams, Keystore},
+    Peer,
+};
+
+mod utils;
+
+fn fast_kdf() -> KdfParams {
+    KdfParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    }
+}
+
+#[test]
+fn keystore_roundtrip() {
+    let _e = utils::init_log("data/keystore_roundtrip.log").entered();
+
+    let file = std::path::PathBuf::from("data/keystore_roundtrip/keys.db");
+    let raw = [7u8; 32];
+
+    let mut ks = Keystore::open(&file).unwrap();
+    ks.set_kdf_params(fast_kdf());
+    let pubkey = ks.import("author", &raw, b"secret").unwrap();
+    ks.generate("node", b"other").unwrap();
+    ks.save().unwrap();
+
+    let ks = Keystore::open(&file).unwrap();
+    let mut names = ks.names();
+    names.sort();
+    assert_eq!(names, vec!["author".to_string(), "node".to_string()]);
+    assert_eq!(ks.entry("author").unwrap().pubkey, pubkey);
+    assert_eq!(ks.export("author", b"secret").unwrap(), raw);
+    assert!(ks.unlock("author", b"wrong").is_err());
+    assert!(ks.unlock("missing", b"secret").is_err());
+    assert!(ks.unlock("author", b"").is_err());
+
+    std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn keystore_empty_passphrase() {
+    let _e = utils::init_log("data/keystore_empty_passphrase.log").entered();
+
+    let mut ks = Keystore::open(std::path::Path::new("data/keystore_empty_passphrase/keys.db")).unwrap();
+    ks.set_kdf_params(fast_kdf());
+
+    assert!(ks.generate("node", b"").is_err());
+    assert!(ks.import("author", &[7u8; 32], b"").is_err());
+    assert!(ks.names().is_empty());
+}
+
+#[tokio::test]
+async fn peer_from_keystore() {
+    let _e = utils::init_log("data/peer_from_keystore.log").entered();
+
+    let file = std::path::PathBuf::from("data/peer_from_keystore/keys.db");
+    let mut ks = Keystore::open(&file).unwrap();
+    ks.set_kdf_params(fast_kdf());
+    let pubkey = ks.generate("node", b"secret").unwrap();
+
+    let cfg = Config {
+        addr: ([127, 0, 0, 1], 29103).into(),
+        folder: "data/peer_from_keystore/node".into(),
+        ..Default::default()
+    };
+    assert!(Peer::from_keystore(cfg.clone(), &ks, "node", b"wrong").is_err());
+    assert!(Peer::from_keystore_async(cfg.clone(), &ks, "node", b"wrong")
+        .await
+        .is_err());
+    assert!(Peer::from_keystore_async(cfg.clone(), &ks, "other", b"secret")
+        .await
+        .is_err());
+
+    let peer = Peer::from_keystore_async(cfg, &ks, "node", b"secret").await.unwrap();
+    assert_eq!(peer.pubkey(), pubkey);
+
+    peer.shutdown().unwrap();
+    std::fs::remove_dir_all(file.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,104 @@
+use mccloud::{config::Limits, Protocol};
+use std::time::{Duration, Instant};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+fn limits() -> Limits {
+    Limits {
+        greeting: 4096,
+        data: 4096,
+        block: 8192,
+        handshake_timeout: Duration::from_millis(300),
+        idle_timeout: Duration::from_secs(5),
+    }
+}
+
+#[tokio::test]
+async fn oversized_handshake() {
+    let _e = utils::init_log("data/limits_oversized_handshake.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create_with(1, false, |cfg| cfg.limits = limits()).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    let start = Instant::now();
+    sck.write_all(&Protocol::default().preamble()).await.unwrap();
+    sck.write_all(&u32::MAX.to_le_bytes()).await.unwrap();
+
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(2), sck.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "oversized peer was not dropped");
+    // dropped for the size, not by the handshake timeout
+    assert!(start.elapsed() < limits().handshake_timeout);
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn stalled_handshake() {
+    let _e = utils::init_log("data/limits_stalled_handshake.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl.create_with(1, false, |cfg| cfg.limits = limits()).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(2), sck.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "stalled peer was not dropped");
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn concurrent_handshakes() {
+    let _e = utils::init_log("data/limits_concurrent_handshakes.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl
+        .create_with(1, false, |cfg| {
+            cfg.limits = limits();
+            cfg.connections.handshakes = 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // takes the only handshake slot, without ever answering
+    let mut stalled = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    let mut preamble = [0u8; mccloud::PREAMBLE_SIZE];
+    stalled.read_exact(&mut preamble).await.unwrap();
+
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    let start = Instant::now();
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(2), sck.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "connection over the limit was not dropped");
+    // dropped before the handshake, not by its timeout
+    assert!(buf.is_empty());
+    assert!(start.elapsed() < limits().handshake_timeout);
+
+    // the slot is free again, once the stalled handshake timed out
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(2), stalled.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "stalled peer was not dropped");
+
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(2), sck.read_exact(&mut preamble))
+        .await
+        .unwrap()
+        .unwrap();
+
+    cl.shutdown();
+    cl.cleanup();
+}
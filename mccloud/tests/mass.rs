@@ -11,7 +11,7 @@ mod utils;
 async fn mass_testing(cnt: usize, seed: u16) {
     let mut cl = Cluster::new(seed);
 
-    let peers = cl.create(cnt, false);
+    let peers = cl.create(cnt, false).await;
 
     tokio::time::sleep(Duration::from_millis(200)).await;
 
@@ -39,7 +39,7 @@ async fn mass_testing(cnt: usize, seed: u16) {
                 // break;
                 for (j, q) in peers.iter().enumerate() {
                     if i != j && !all_known.contains(&q.pubkey()) {
-                        check.entry(i).or_insert_with(|| HashSet::new()).insert(j);
+                        check.entry(i).or_insert_with(HashSet::new).insert(j);
                     }
                 }
             }
@@ -56,7 +56,7 @@ async fn mass_testing(cnt: usize, seed: u16) {
     let mut rx = peers[1].last_block_receiver();
 
     let start = SystemTime::now();
-    peers[1].share(b"bunny hop".to_vec().into()).await.unwrap();
+    peers[1].share(b"bunny hop".to_vec()).await.unwrap();
     match rx.recv().await {
         Ok(blk) => {
             let s = String::from_utf8_lossy(&blk.data[0].data);
@@ -0,0 +1,180 @@
+use k256::{elliptic_curve::rand_core::OsRng, SecretKey};
+use mccloud::{
+    config::{Config, Keepalive, TransportKind},
+    transport::MemoryTransport,
+    IntoTargetAddr, Peer, TargetAddr,
+};
+use std::{sync::Arc, time::Duration};
+use utils::stall::{Stall, StallingTransport};
+
+mod utils;
+
+#[tokio::test]
+async fn leave_on_shutdown() {
+    let _e = utils::init_log("data/membership_leave.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(3, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(peers[1].known_pubkeys().await.contains(&peers[2].pubkey()));
+    assert!(peers[2].known_pubkeys().await.contains(&peers[1].pubkey()));
+
+    peers[2].shutdown().unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(!peers[0].known_pubkeys().await.contains(&peers[2].pubkey()));
+    assert!(
+        !peers[1].known_pubkeys().await.contains(&peers[2].pubkey()),
+        "leave was not passed on"
+    );
+    assert!(peers[1].known_pubkeys().await.contains(&peers[0].pubkey()));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn stale_announcement() {
+    let _e = utils::init_log("data/membership_stale.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let mut i = 0;
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            if i == 0 {
+                cfg.membership.max_age = Duration::from_millis(50);
+            }
+            i += 1;
+        })
+        .await;
+
+    // the announcement of peer 1 is older than peer 0 accepts, once they connect
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+    assert!(
+        peers[0].known_pubkeys().await.is_empty(),
+        "stale announcement was accepted"
+    );
+    assert!(peers[1].known_pubkeys().await.contains(&peers[0].pubkey()));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn refresh_announcement() {
+    let _e = utils::init_log("data/membership_refresh.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let mut i = 0;
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            if i == 0 {
+                cfg.membership.refresh = Duration::from_millis(100);
+            } else {
+                cfg.membership.refresh = Duration::from_millis(150);
+                cfg.membership.max_age = Duration::from_millis(300);
+            }
+            i += 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert!(
+        peers[1].known_pubkeys().await.contains(&peers[0].pubkey()),
+        "refreshed announcement expired"
+    );
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn confirm_observed_leave() {
+    let _e = utils::init_log("data/membership_confirm.log").entered();
+
+    let stall = Stall::new();
+    let name = |i: u16| format!("membership-{i}");
+
+    let mut configs = utils::configs::ServerConfigs::new(30);
+    let mut peers = Vec::new();
+    for i in 0..3 {
+        let mut cfg = Config {
+            transport: TransportKind::Memory { name: name(i) },
+            keepalive: Keepalive {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_millis(300),
+            },
+            ..configs.next().unwrap()
+        };
+        cfg.relationship.count = 1;
+        cfg.relationship.leave_after = Duration::from_secs(1);
+
+        let peer = if i == 2 {
+            let transport = StallingTransport {
+                inner: MemoryTransport::new(name(2)),
+                slow: name(1),
+                stall: stall.clone(),
+            };
+            Peer::with_transport(cfg, SecretKey::random(&mut OsRng), Arc::new(transport)).unwrap()
+        } else {
+            Peer::new_async(cfg).await.unwrap()
+        };
+        peers.push(peer);
+    }
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // a line of 0 - 1 - 2
+    let addr = format!("{}:0", name(1));
+    peers[0]
+        .connect(addr.clone().into_target_addr().unwrap())
+        .await
+        .unwrap();
+    peers[2].connect(addr.into_target_addr().unwrap()).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(peers[0].known_pubkeys().await.contains(&peers[2].pubkey()));
+
+    // peer 2 goes silent, peer 1 drops it and sends its leave after one second
+    stall.set(true);
+
+    tokio::time::sleep(Duration::from_millis(1800)).await;
+    assert!(!peers[1].known_pubkeys().await.contains(&peers[2].pubkey()));
+    assert!(
+        peers[0].known_pubkeys().await.contains(&peers[2].pubkey()),
+        "observed leave was applied without confirmation"
+    );
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert!(
+        !peers[0].known_pubkeys().await.contains(&peers[2].pubkey()),
+        "observed leave was not confirmed"
+    );
+
+    for p in peers.iter() {
+        p.shutdown().unwrap();
+    }
+    for p in peers.iter() {
+        let _ = std::fs::remove_dir_all(&p.cfg.folder);
+    }
+}
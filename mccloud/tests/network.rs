@@ -0,0 +1,130 @@
+use mccloud::{blockchain::network_id, Peer, TargetAddr};
+use std::{path::Path, time::Duration};
+
+mod utils;
+
+#[tokio::test]
+async fn separate_networks() {
+    let _e = utils::init_log("data/network_separate.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let mut i = 0;
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.network = if i < 2 { "alpha".into() } else { "beta".into() };
+            i += 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert!(clients.contains(&peers[1].pubkey()));
+    assert!(
+        !clients.contains(&peers[2].pubkey()),
+        "peer of another network was accepted"
+    );
+    assert_eq!(peers[0].network(), network_id("alpha"));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn data_is_bound_to_network() {
+    let _e = utils::init_log("data/network_data.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl.create(1, false).await;
+
+    let data = peers[0].create_data(b"alpha only".to_vec()).unwrap();
+    data.verify(&peers[0].network()).unwrap();
+    assert!(data.verify(&network_id("beta")).is_err());
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn chain_of_another_network() {
+    let _e = utils::init_log("data/network_chain.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    peers[1].share(b"genesis".to_vec()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    cl.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut cfg = peers[0].cfg.clone();
+    cfg.network = "beta".into();
+    assert!(
+        Peer::new_async(cfg).await.is_err(),
+        "opened the blockchain of another network"
+    );
+
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn legacy_chain() {
+    let _e = utils::init_log("data/network_legacy.log").entered();
+
+    // three blocks of the first release, signed without network id by the key of ones
+    let mut cfg = utils::configs::ServerConfigs::new(30).next().unwrap();
+    cfg.key = Some([1u8; 32]);
+    let _ = std::fs::remove_dir_all(&cfg.folder);
+    std::fs::create_dir_all(&cfg.folder).unwrap();
+    for file in ["index.db", "blocks.db"] {
+        std::fs::copy(Path::new("tests/fixtures/baseline").join(file), cfg.folder.join(file)).unwrap();
+    }
+
+    assert!(Peer::new_async(cfg.clone()).await.is_err(), "opened a legacy chain");
+
+    cfg.legacy_signatures = true;
+    let peer = Peer::new_async(cfg.clone()).await.unwrap();
+    assert_eq!(utils::all_blocks(&peer).await.len(), 3);
+
+    // the chain goes on with blocks signed for the network
+    let mut rx = peer.last_block_receiver();
+    peer.share(b"fourth".to_vec()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    let blocks = utils::all_blocks(&peer).await;
+    assert_eq!(blocks.len(), 4);
+    assert!(blocks[2].verify(&peer.network()).is_err());
+    blocks[2].verify_legacy().unwrap();
+    blocks[3].verify(&peer.network()).unwrap();
+    assert!(blocks[3].verify_legacy().is_err());
+
+    peer.shutdown().unwrap();
+    drop(peer);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let peer = Peer::new_async(cfg.clone()).await.unwrap();
+    assert_eq!(utils::all_blocks(&peer).await.len(), 4);
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    let _ = std::fs::remove_dir_all(&cfg.folder);
+}
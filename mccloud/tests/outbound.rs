@@ -0,0 +1,114 @@
+use k256::{elliptic_curve::rand_core::OsRng, SecretKey};
+use mccloud::{
+    config::{Config, Outbound, SlowPeer, TransportKind},
+    transport::MemoryTransport,
+    IntoTargetAddr, Peer,
+};
+use std::{sync::Arc, time::Duration};
+use utils::stall::{Stall, StallingTransport};
+
+mod utils;
+
+async fn stalled_peer(seed: u16, slow_peer: SlowPeer) -> (Vec<Arc<Peer>>, Arc<Stall>) {
+    let stall = Stall::new();
+    let name = |i: u16| format!("outbound-{seed}-{i}");
+
+    let mut configs = utils::configs::ServerConfigs::new(seed);
+    let mut peers = Vec::new();
+    for i in 0..3 {
+        let cfg = Config {
+            transport: TransportKind::Memory { name: name(i) },
+            outbound: Outbound { capacity: 4, slow_peer },
+            ..configs.next().unwrap()
+        };
+
+        let peer = if i == 0 {
+            let transport = StallingTransport {
+                inner: MemoryTransport::new(name(0)),
+                slow: name(1),
+                stall: stall.clone(),
+            };
+            Peer::with_transport(cfg, SecretKey::random(&mut OsRng), Arc::new(transport)).unwrap()
+        } else {
+            Peer::new_async(cfg).await.unwrap()
+        };
+        peers.push(peer);
+    }
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    for i in 1..3 {
+        let addr = format!("{}:0", name(i));
+        peers[0].connect(addr.into_target_addr().unwrap()).await.unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(peers[0].client_pubkeys().await.len(), 2);
+
+    stall.set(true);
+
+    // sharing must never wait for the stalled peer
+    for i in 0..32u32 {
+        tokio::time::timeout(Duration::from_secs(1), peers[0].share(i.to_le_bytes().to_vec()))
+            .await
+            .unwrap()
+            .unwrap();
+        // give the healthy connections time to drain their queues
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    (peers, stall)
+}
+
+fn cleanup(peers: &[Arc<Peer>]) {
+    for p in peers {
+        p.shutdown().unwrap();
+    }
+    for p in peers {
+        let _ = std::fs::remove_dir_all(&p.cfg.folder);
+    }
+}
+
+#[tokio::test]
+async fn slow_peer_disconnect() {
+    let _e = utils::init_log("data/outbound_disconnect.log").entered();
+
+    let (peers, _) = stalled_peer(0, SlowPeer::Disconnect).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert!(!clients.contains(&peers[1].pubkey()), "slow peer was not disconnected");
+    assert!(clients.contains(&peers[2].pubkey()));
+
+    cleanup(&peers);
+}
+
+#[tokio::test]
+async fn slow_peer_drop() {
+    let _e = utils::init_log("data/outbound_drop.log").entered();
+
+    let (peers, stall) = stalled_peer(10, SlowPeer::Drop).await;
+
+    let clients = peers[0].client_pubkeys().await;
+    assert!(clients.contains(&peers[1].pubkey()), "slow peer was disconnected");
+    assert!(clients.contains(&peers[2].pubkey()));
+
+    // once the peer reads again, its queue drains and new messages reach it
+    let mut rx = peers[1].last_block_receiver();
+    stall.set(false);
+    peers[0].share(b"drained".to_vec()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let blk = rx.recv().await.unwrap();
+            if blk.data.iter().any(|d| d.data == b"drained") {
+                break;
+            }
+        }
+    })
+    .await
+    .expect("queue of the slow peer did not drain");
+    assert!(peers[0].client_pubkeys().await.contains(&peers[1].pubkey()));
+
+    cleanup(&peers);
+}
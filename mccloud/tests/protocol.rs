@@ -0,0 +1,215 @@
+use mccloud::{
+    config::{Config, Limits},
+    handshake::Handshake,
+    Capabilities, Protocol, TargetAddr, PREAMBLE_SIZE,
+};
+use std::time::{Duration, Instant};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+mod utils;
+
+#[test]
+fn negotiate_overlapping_ranges() {
+    let old = Protocol {
+        min: 1,
+        max: 2,
+        capabilities: Capabilities::REKEY,
+    };
+    let new = Protocol {
+        min: 2,
+        max: 4,
+        capabilities: Capabilities::REKEY | Capabilities::from_bits(1 << 10),
+    };
+
+    assert_eq!(old.negotiate(&new), Some((2, Capabilities::REKEY)));
+    assert_eq!(new.negotiate(&old), Some((2, Capabilities::REKEY)));
+}
+
+#[test]
+fn negotiate_disjoint_ranges() {
+    let old = Protocol {
+        min: 1,
+        max: 1,
+        capabilities: Capabilities::ALL,
+    };
+    let new = Protocol {
+        min: 2,
+        max: 3,
+        capabilities: Capabilities::ALL,
+    };
+
+    assert_eq!(old.negotiate(&new), None);
+    assert_eq!(new.negotiate(&old), None);
+}
+
+#[test]
+fn capabilities_contains() {
+    let caps = Capabilities::REKEY | Capabilities::from_bits(1 << 3);
+
+    assert!(caps.contains(Capabilities::REKEY));
+    assert!(caps.contains(Capabilities::NONE));
+    assert!(!Capabilities::NONE.contains(Capabilities::REKEY));
+}
+
+#[tokio::test]
+async fn peers_negotiate() {
+    let _e = utils::init_log("data/protocol_negotiate.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let expected = Protocol::default();
+    let negotiated = peers[1].client_protocol(&peers[0].pubkey()).await;
+    assert_eq!(negotiated, Some((expected.max, expected.capabilities)));
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[test]
+fn preamble_roundtrip() {
+    let protocol = Protocol {
+        min: 4,
+        max: 9,
+        capabilities: Capabilities::ALL | Capabilities::from_bits(1 << 40),
+    };
+
+    assert_eq!(Protocol::from_preamble(&protocol.preamble()), Some(protocol));
+    assert_eq!(Protocol::from_preamble(&[0u8; PREAMBLE_SIZE]), None);
+}
+
+fn limits() -> Limits {
+    Limits {
+        handshake_timeout: Duration::from_secs(2),
+        ..Config::default().limits
+    }
+}
+
+/// Reads the plain text handshake, which follows the preamble.
+async fn read_handshake(sck: &mut TcpStream) {
+    let mut size = [0u8; 4];
+    sck.read_exact(&mut size).await.unwrap();
+    let mut data = vec![0u8; u32::from_le_bytes(size) as usize];
+    sck.read_exact(&mut data).await.unwrap();
+
+    // the variant tag of the borsh encoded Message::Handshake
+    assert_eq!(data[0], 0);
+}
+
+/// Writes the plain text handshake with our ephemeral key.
+async fn write_handshake(sck: &mut TcpStream, handshake: &Handshake) {
+    let mut data = 34u32.to_le_bytes().to_vec();
+    data.push(0);
+    data.extend(handshake.ephemeral);
+    sck.write_all(&data).await.unwrap();
+}
+
+/// Connects with a raw socket and sends the preamble of another version. Returns our preamble, after which the
+/// handshake was read.
+async fn greet_with(cl: &mut utils::cluster::Cluster, protocol: &Protocol) -> (TcpStream, Protocol) {
+    let peers = cl.create_with(1, false, |cfg| cfg.limits = limits()).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    sck.write_all(&protocol.preamble()).await.unwrap();
+
+    let mut preamble = [0u8; PREAMBLE_SIZE];
+    sck.read_exact(&mut preamble).await.unwrap();
+    read_handshake(&mut sck).await;
+
+    (sck, Protocol::from_preamble(&preamble).unwrap())
+}
+
+#[tokio::test]
+async fn newer_peer_handshakes() {
+    let _e = utils::init_log("data/protocol_newer_peer.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let newer = Protocol {
+        min: Protocol::default().max,
+        max: Protocol::default().max + 5,
+        capabilities: Capabilities::ALL | Capabilities::from_bits(1 << 40),
+    };
+    let (mut sck, ours) = greet_with(&mut cl, &newer).await;
+    assert_eq!(ours, Protocol::default());
+
+    write_handshake(&mut sck, &Handshake::new()).await;
+
+    // the peer goes on with the encrypted greeting of the common version
+    let mut header = [0u8; 5];
+    tokio::time::timeout(Duration::from_secs(1), sck.read_exact(&mut header))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(u32::from_le_bytes(header[..4].try_into().unwrap()) > 0);
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn disjoint_peer_dropped() {
+    let _e = utils::init_log("data/protocol_disjoint_peer.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let future = Protocol {
+        min: Protocol::default().max + 1,
+        max: Protocol::default().max + 5,
+        capabilities: Capabilities::ALL,
+    };
+    let start = Instant::now();
+    let (mut sck, _) = greet_with(&mut cl, &future).await;
+    write_handshake(&mut sck, &Handshake::new()).await;
+
+    // dropped without a greeting and before the handshake timeout
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(1), sck.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "peer of another version was not dropped");
+    assert!(buf.is_empty());
+    assert!(start.elapsed() < limits().handshake_timeout);
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn peer_without_preamble_dropped() {
+    let _e = utils::init_log("data/protocol_without_preamble.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(30);
+    let peers = cl.create_with(1, false, |cfg| cfg.limits = limits()).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // a peer before version 4 starts with the size of its plain text handshake
+    let mut sck = TcpStream::connect(peers[0].cfg.addr).await.unwrap();
+    let start = Instant::now();
+    let mut old = 34u32.to_le_bytes().to_vec();
+    old.extend([0u8; 34]);
+    sck.write_all(&old).await.unwrap();
+
+    let mut preamble = [0u8; PREAMBLE_SIZE];
+    sck.read_exact(&mut preamble).await.unwrap();
+    assert!(Protocol::from_preamble(&preamble).is_some());
+    read_handshake(&mut sck).await;
+
+    // no greeting follows, the socket is closed or reset
+    let mut buf = Vec::new();
+    let res = tokio::time::timeout(Duration::from_secs(1), sck.read_to_end(&mut buf)).await;
+    assert!(res.is_ok(), "peer without preamble was not dropped");
+    assert!(buf.is_empty());
+    assert!(start.elapsed() < limits().handshake_timeout);
+
+    cl.shutdown();
+    cl.cleanup();
+}
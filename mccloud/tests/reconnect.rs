@@ -1,3 +1,4 @@
+use k256::{elliptic_curve::rand_core::OsRng, SecretKey};
 use mccloud::{IntoTargetAddr, Peer};
 use std::time::Duration;
 
@@ -9,7 +10,7 @@ async fn reconnect_1() {
 
     let mut cl = utils::cluster::Cluster::new(0);
 
-    let mut peers = cl.create(2, false);
+    let mut peers = cl.create(2, false).await;
 
     tokio::time::sleep(Duration::from_millis(250)).await;
 
@@ -26,7 +27,7 @@ async fn reconnect_1() {
 
     assert_eq!(all_kn_cnt01, 0);
 
-    peers[0] = Peer::new(peers[0].cfg.clone()).unwrap();
+    peers[0] = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
 
     tokio::time::sleep(Duration::from_secs(3)).await;
 
@@ -46,7 +47,7 @@ async fn reconnect_2() {
 
     let mut cl = utils::cluster::Cluster::new(10);
 
-    let mut peers = cl.create(2, false);
+    let mut peers = cl.create(2, false).await;
 
     tokio::time::sleep(Duration::from_millis(250)).await;
 
@@ -67,7 +68,7 @@ async fn reconnect_2() {
     //assert_eq!(all_kn_cnt01, 0);
 
     tracing::info!("-- start listening again --");
-    peers[1] = Peer::new(peers[1].cfg.clone()).unwrap();
+    peers[1] = Peer::new_async(peers[1].cfg.clone()).await.unwrap();
     tokio::time::sleep(Duration::from_millis(250)).await;
 
     peers[1]
@@ -86,3 +87,173 @@ async fn reconnect_2() {
     cl.shutdown();
     cl.cleanup();
 }
+
+#[tokio::test]
+async fn reconnect_persistent() {
+    let _e = utils::init_log("data/reconnect_persistent.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+
+    let mut peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.relationship.reconnect = Duration::from_millis(50);
+            cfg.relationship.max_reconnect = Duration::from_millis(200);
+            cfg.relationship.retry = 0;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[1]
+        .connect_persistent(peers[0].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(peers[1].pending_reconnects().await.is_empty());
+
+    peers[0].shutdown().unwrap();
+    // longer than the retries of a normal connection would last
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+
+    let pending = peers[1].pending_reconnects().await;
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0].persistent);
+    assert!(pending[0].attempt > 0);
+
+    peers[0] = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(750)).await;
+
+    assert!(peers[1].client_pubkeys().await.contains(&peers[0].pubkey()));
+    assert!(peers[1].pending_reconnects().await.is_empty());
+
+    peers[0].shutdown().unwrap();
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn reconnect_give_up() {
+    let _e = utils::init_log("data/reconnect_give_up.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(30);
+
+    let peers = cl
+        .create_with(1, false, |cfg| {
+            cfg.relationship.reconnect = Duration::from_millis(50);
+            cfg.relationship.max_reconnect = Duration::from_millis(100);
+            cfg.relationship.retry = 2;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    // nobody is listening there
+    peers[0]
+        .connect("127.0.0.1:29131".into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let pending = peers[0].pending_reconnects().await;
+    assert_eq!(pending.len(), 1);
+    assert!(!pending[0].persistent);
+
+    tokio::time::sleep(Duration::from_millis(750)).await;
+    assert!(peers[0].pending_reconnects().await.is_empty());
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn reconnect_other_key() {
+    let _e = utils::init_log("data/reconnect_other_key.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(40);
+
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.relationship.reconnect = Duration::from_millis(50);
+            cfg.relationship.max_reconnect = Duration::from_millis(100);
+            cfg.relationship.retry = 20;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[1]
+        .connect(peers[0].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[0].shutdown().unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    // another peer took over the address, and does not dial itself
+    let mut cfg = peers[0].cfg.clone();
+    cfg.key = Some(SecretKey::random(&mut OsRng).to_bytes().into());
+    cfg.relationship.count = 0;
+    let other = Peer::new_async(cfg).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(750)).await;
+
+    assert!(!peers[1].client_pubkeys().await.contains(&other.pubkey()));
+    assert!(peers[1].pending_reconnects().await.is_empty());
+
+    other.shutdown().unwrap();
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn reconnect_persistent_inbound() {
+    let _e = utils::init_log("data/reconnect_persistent_inbound.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(50);
+
+    let mut peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.relationship.reconnect = Duration::from_millis(50);
+            cfg.relationship.max_reconnect = Duration::from_millis(200);
+            cfg.relationship.retry = 0;
+        })
+        .await;
+    // the peer with the lower key keeps its outbound connection, so the persistent dial of the other one loses
+    let (lo, hi) = if peers[0].pubkey() < peers[1].pubkey() {
+        (0, 1)
+    } else {
+        (1, 0)
+    };
+
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    peers[lo]
+        .connect(peers[hi].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    peers[hi]
+        .connect_persistent(peers[lo].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert!(peers[hi].client_pubkeys().await.contains(&peers[lo].pubkey()));
+    assert!(peers[hi].pending_reconnects().await.is_empty());
+
+    peers[lo].shutdown().unwrap();
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+
+    let pending = peers[hi].pending_reconnects().await;
+    assert_eq!(pending.len(), 1);
+    assert!(pending[0].persistent);
+
+    peers[lo] = Peer::new_async(peers[lo].cfg.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(750)).await;
+
+    assert!(peers[hi].client_pubkeys().await.contains(&peers[lo].pubkey()));
+    assert!(peers[hi].pending_reconnects().await.is_empty());
+
+    peers[lo].shutdown().unwrap();
+    cl.shutdown();
+    cl.cleanup();
+}
@@ -0,0 +1,48 @@
+use mccloud::{config::Rekey, IntoTargetAddr};
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn rekey_every_message() {
+    let _e = utils::init_log("data/rekey_every_message.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.rekey = Rekey {
+                messages: 1,
+                time: Duration::from_secs(60),
+            };
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    peers[1]
+        .connect(peers[0].cfg.addr.into_target_addr().unwrap())
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    utils::assert_all_known(&peers, 1).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    peers[1].share(b"first".to_vec()).await.unwrap();
+    let blk = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(blk.data[0].data, b"first");
+
+    peers[0].share(b"second".to_vec()).await.unwrap();
+    let blk = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(blk.data[0].data, b"second");
+
+    cl.shutdown();
+    cl.cleanup();
+}
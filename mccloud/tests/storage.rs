@@ -0,0 +1,315 @@
+use mccloud::{
+    blockchain::Recovery,
+    config::StorageKind,
+    storage::{FileStorage, Storage},
+    Peer, TargetAddr,
+};
+use std::{fs::OpenOptions, io::Write, path::Path, time::Duration};
+
+mod utils;
+
+#[tokio::test]
+async fn recover_torn_writes() {
+    let _e = utils::init_log("data/storage_recover.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    peers[1].share(b"durable".to_vec()).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+
+    cl.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let folder = &peers[0].cfg.folder;
+    let index_len = std::fs::metadata(folder.join("index.db")).unwrap().len();
+    let db_len = std::fs::metadata(folder.join("blocks.db")).unwrap().len();
+
+    // an index entry whose block data was never written, and a partial block
+    let mut index = OpenOptions::new().append(true).open(folder.join("index.db")).unwrap();
+    index.write_all(&[7u8; 80]).unwrap();
+    index.write_all(&[7u8; 20]).unwrap();
+    let mut db = OpenOptions::new().append(true).open(folder.join("blocks.db")).unwrap();
+    db.write_all(&[7u8; 50]).unwrap();
+
+    let peer = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    assert_eq!(
+        peer.chain_recovery().await,
+        Recovery {
+            blocks: 1,
+            index_bytes: 100,
+            db_bytes: 50
+        }
+    );
+    assert_eq!(utils::all_blocks(&peer).await.len(), 1);
+    assert_eq!(std::fs::metadata(folder.join("index.db")).unwrap().len(), index_len);
+    assert_eq!(std::fs::metadata(folder.join("blocks.db")).unwrap().len(), db_len);
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    let peer = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    assert_eq!(peer.chain_recovery().await, Recovery::default());
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    // the last block data does not match its checksum
+    let mut data = std::fs::read(folder.join("blocks.db")).unwrap();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    std::fs::write(folder.join("blocks.db"), data).unwrap();
+
+    let peer = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    assert_eq!(peer.chain_recovery().await.blocks, 1);
+    assert_eq!(utils::all_blocks(&peer).await.len(), 0);
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    cl.cleanup();
+}
+
+#[test]
+fn open_baseline_store() {
+    let _e = utils::init_log("data/storage_baseline.log").entered();
+
+    // three blocks, written by the first release without index header and checksums
+    let folder = Path::new("data/storage_baseline");
+    let _ = std::fs::remove_dir_all(folder);
+    std::fs::create_dir_all(folder).unwrap();
+    for file in ["index.db", "blocks.db"] {
+        std::fs::copy(Path::new("tests/fixtures/baseline").join(file), folder.join(file)).unwrap();
+    }
+
+    let storage = FileStorage::open(folder, true).unwrap();
+    let meta = storage.metadata();
+    assert_eq!(meta.count, 3);
+    assert_eq!(meta.recovery, Recovery::default());
+    assert_eq!(
+        hex::encode(meta.last.unwrap()),
+        "8b7be720b0aff7cc813097ed3322c12e9c1f1cfdcf71b70729a7712c7e45fd36"
+    );
+
+    let blocks: Vec<_> = storage.iter(0).map(|b| b.unwrap()).collect();
+    let data: Vec<_> = blocks.iter().map(|b| b.data[0].data.clone()).collect();
+    assert_eq!(data, [&b"first"[..], b"second", b"third"]);
+    assert_eq!(storage.height_of(&blocks[1].hash).unwrap(), Some(1));
+    let location = storage.find_data(&blocks[2].data[0].sign).unwrap().unwrap();
+    assert_eq!(location.height, 2);
+    drop(storage);
+
+    // the migrated index is kept
+    let storage = FileStorage::open(folder, false).unwrap();
+    assert_eq!(storage.metadata().count, 3);
+    assert_eq!(std::fs::metadata(folder.join("index.db")).unwrap().len(), 16 + 3 * 80);
+    drop(storage);
+
+    // an index of a newer format is refused, and left as it is
+    let mut index = std::fs::read(folder.join("index.db")).unwrap();
+    index[8] = 99;
+    std::fs::write(folder.join("index.db"), &index).unwrap();
+    assert!(FileStorage::open(folder, false).is_err());
+    assert_eq!(std::fs::read(folder.join("index.db")).unwrap(), index);
+
+    let _ = std::fs::remove_dir_all(folder);
+}
+
+#[tokio::test]
+async fn lookup_blocks() {
+    let _e = utils::init_log("data/storage_lookup.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(2);
+    let peers = cl.create(2, false).await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    for i in 0..3u8 {
+        peers[1].share(vec![i]).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let hashes: Vec<_> = utils::all_blocks(&peers[0]).await.iter().map(|b| b.hash).collect();
+    assert_eq!(hashes.len(), 3);
+
+    for (height, hash) in hashes.iter().enumerate() {
+        let blk = peers[0].get_block_by_height(height as _).await.unwrap().unwrap();
+        assert_eq!(&blk.hash, hash);
+        let blk = peers[0].get_block_by_hash(hash).await.unwrap().unwrap();
+        assert_eq!(&blk.hash, hash);
+        assert!(peers[0].contains_block(hash).await.unwrap());
+    }
+    assert!(peers[0].get_block_by_height(3).await.unwrap().is_none());
+    assert!(peers[0].get_block_by_hash(&[1u8; 32]).await.unwrap().is_none());
+    assert!(!peers[0].contains_block(&[1u8; 32]).await.unwrap());
+
+    cl.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // a lost hash index is rebuilt from the chain
+    std::fs::remove_file(peers[0].cfg.folder.join("hashes.db")).unwrap();
+    let peer = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    let blk = peer.get_block_by_hash(&hashes[1]).await.unwrap().unwrap();
+    assert_eq!(blk.hash, hashes[1]);
+    assert_eq!(blk.parent, Some(hashes[0]));
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn data_indices() {
+    let _e = utils::init_log("data/storage_data.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(4);
+    let mut i = 0;
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.index_data = i == 0;
+            i += 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    for (p, data) in [(1, b"one"), (0, b"two"), (1, b"six")] {
+        peers[p].share(data.to_vec()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let blocks = utils::all_blocks(&peers[0]).await;
+    let author = peers[1].pubkey();
+
+    // the indexed peer answers like the one reading all blocks
+    for p in peers.iter() {
+        let found = p.data_by_author(&author, 0, 10).await.unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].block, blocks[2].hash);
+        assert_eq!(found[1].block, blocks[0].hash);
+        assert_eq!(found[1].height, 0);
+
+        let page = p.data_by_author(&author, 1, 1).await.unwrap();
+        assert_eq!(page, found[1..]);
+
+        let d = &blocks[1].data[0];
+        let location = p.find_data(&d.sign).await.unwrap().unwrap();
+        assert_eq!(location.block, blocks[1].hash);
+        assert_eq!(location.height, 1);
+        assert_eq!(location.index, 0);
+        assert!(p.find_data(&[1u8; 64]).await.unwrap().is_none());
+    }
+
+    cl.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // lost indices are rebuilt from the chain
+    std::fs::remove_file(peers[0].cfg.folder.join("data.db")).unwrap();
+    let peer = Peer::new_async(peers[0].cfg.clone()).await.unwrap();
+    let found = peer.data_by_author(&author, 0, 10).await.unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].block, blocks[2].hash);
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    cl.cleanup();
+}
+
+/// Shares blocks between two peers on the storage, and restarts the first one.
+async fn roundtrip(seed: u16, storage: StorageKind) -> usize {
+    let mut cl = utils::cluster::Cluster::new(seed);
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.storage = storage.clone();
+            cfg.index_data = true;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    for data in [&b"left"[..], &b"right"[..]] {
+        peers[1].share(data.to_vec()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let blocks = utils::all_blocks(&peers[0]).await;
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].parent, Some(blocks[0].hash));
+
+    let blk = peers[0].get_block_by_hash(&blocks[1].hash).await.unwrap().unwrap();
+    assert_eq!(blk.data[0].data, b"right");
+    assert!(peers[0].get_block_by_height(2).await.unwrap().is_none());
+
+    let location = peers[0].find_data(&blocks[0].data[0].sign).await.unwrap().unwrap();
+    assert_eq!(location.block, blocks[0].hash);
+    let found = peers[0].data_by_author(&peers[1].pubkey(), 0, 10).await.unwrap();
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].block, blocks[1].hash);
+
+    cl.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // a database may stay locked, as long as its peer is alive
+    let cfgs: Vec<_> = peers.iter().map(|p| p.cfg.clone()).collect();
+    drop(peers);
+    drop(cl);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let peer = Peer::new_async(cfgs[0].clone()).await.unwrap();
+    let kept = utils::all_blocks(&peer).await.len();
+    peer.shutdown().unwrap();
+    drop(peer);
+
+    for cfg in cfgs {
+        let _ = std::fs::remove_dir_all(&cfg.folder);
+    }
+    kept
+}
+
+#[tokio::test]
+async fn memory_storage() {
+    let _e = utils::init_log("data/storage_memory.log").entered();
+
+    assert_eq!(roundtrip(6, StorageKind::Memory).await, 0, "memory storage kept blocks");
+}
+
+#[cfg(feature = "redb")]
+#[tokio::test]
+async fn redb_storage() {
+    let _e = utils::init_log("data/storage_redb.log").entered();
+
+    assert_eq!(roundtrip(8, StorageKind::Redb).await, 2);
+}
@@ -9,7 +9,7 @@ async fn three_peers() {
 
     let mut cl = utils::cluster::Cluster::new(0);
 
-    let mut peers = cl.create(3, false);
+    let mut peers = cl.create(3, false).await;
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
@@ -26,7 +26,7 @@ async fn three_peers() {
 
     utils::assert_all_known(&peers, 2).await;
 
-    peers[0].share(b"bunnys gonna bunny".to_vec().into()).await.unwrap();
+    peers[0].share(b"bunnys gonna bunny".to_vec()).await.unwrap();
 
     tokio::time::sleep(gather_time).await;
 
@@ -38,11 +38,11 @@ async fn three_peers() {
     tracing::debug!("-- check all_known --");
     utils::assert_all_known(&peers, 1).await;
 
-    peers[0].share(b"no, bunnys gonna hop!".to_vec().into()).await.unwrap();
+    peers[0].share(b"no, bunnys gonna hop!".to_vec()).await.unwrap();
 
     tokio::time::sleep(gather_time).await;
 
-    peers[2] = Peer::new(peers[2].cfg.clone()).unwrap();
+    peers[2] = Peer::new_async(peers[2].cfg.clone()).await.unwrap();
     peers[2].connect(p1addr).await.unwrap();
 
     tokio::time::sleep(Duration::from_millis(200)).await;
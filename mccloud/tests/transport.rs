@@ -0,0 +1,147 @@
+#[cfg(feature = "quic")]
+use mccloud::TargetAddr;
+use mccloud::{
+    config::TransportKind,
+    transport::{MemoryTransport, Transport},
+    IntoTargetAddr,
+};
+use std::time::Duration;
+
+mod utils;
+
+#[tokio::test]
+async fn memory_transport() {
+    let _e = utils::init_log("data/transport_memory.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(0);
+    let mut i = 0;
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.transport = TransportKind::Memory {
+                name: format!("memory-{i}"),
+            };
+            i += 1;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    peers[1]
+        .connect("memory-0:0".into_target_addr().unwrap())
+        .await
+        .unwrap();
+    peers[2]
+        .connect("memory-0:0".into_target_addr().unwrap())
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    utils::assert_all_known(&peers, 2).await;
+
+    let mut rx = peers[2].last_block_receiver();
+    peers[1].share(b"in memory".to_vec()).await.unwrap();
+    let blk = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(blk.data[0].data, b"in memory");
+
+    cl.shutdown();
+    cl.cleanup();
+}
+
+#[tokio::test]
+async fn memory_relisten() {
+    let _e = utils::init_log("data/transport_memory_relisten.log").entered();
+
+    let server = MemoryTransport::new("relisten".into());
+    let client = MemoryTransport::new("relisten-client".into());
+
+    let old = server.listen().await.unwrap();
+    let mut new = server.listen().await.unwrap();
+    // dropping the replaced listener must not deregister the new one
+    drop(old);
+
+    client.connect("relisten:0".into_target_addr().unwrap()).await.unwrap();
+    let (_, from) = tokio::time::timeout(Duration::from_secs(1), new.accept())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(from, "relisten-client:0".into_target_addr().unwrap());
+
+    drop(new);
+    assert!(client.connect("relisten:0".into_target_addr().unwrap()).await.is_err());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_transport() {
+    let _e = utils::init_log("data/transport_unix.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(10);
+    let peers = cl
+        .create_with(2, false, |cfg| {
+            cfg.transport = TransportKind::Unix {
+                path: cfg.folder.with_extension("sock"),
+            };
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let addr = format!("{}:0", peers[0].cfg.folder.with_extension("sock").display());
+    peers[1].connect(addr.into_target_addr().unwrap()).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    utils::assert_all_known(&peers, 1).await;
+
+    cl.shutdown();
+    cl.cleanup();
+
+    for p in peers.iter() {
+        let _ = std::fs::remove_file(p.cfg.folder.with_extension("sock"));
+    }
+}
+
+#[cfg(feature = "quic")]
+#[tokio::test]
+async fn quic_transport() {
+    let _e = utils::init_log("data/transport_quic.log").entered();
+
+    let mut cl = utils::cluster::Cluster::new(20);
+    let peers = cl
+        .create_with(3, false, |cfg| {
+            cfg.transport = TransportKind::Quic;
+        })
+        .await;
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    peers[1].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let mut rx = peers[0].last_block_receiver();
+    peers[1].share(b"over quic".to_vec()).await.unwrap();
+    let blk = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(blk.data[0].data, b"over quic");
+
+    // the late peer syncs the existing block on the bulk stream
+    let mut rx = peers[2].last_block_receiver();
+    peers[2].connect(TargetAddr::Ip(peers[0].cfg.addr)).await.unwrap();
+    let synced = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(synced.hash, blk.hash);
+
+    utils::wait_all_known(&peers, 2, Duration::from_secs(5)).await;
+
+    cl.shutdown();
+    cl.cleanup();
+}
@@ -9,8 +9,8 @@ async fn two_peers() {
 
     let mut cl = utils::cluster::Cluster::new(0);
 
-    let peers = cl.create(2, false);
-    let clients = cl.create(1, true);
+    let peers = cl.create(2, false).await;
+    let clients = cl.create(1, true).await;
 
     tokio::time::sleep(Duration::from_millis(200)).await;
 
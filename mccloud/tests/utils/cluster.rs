@@ -15,24 +15,31 @@ pub struct Cluster {
 
 impl Cluster {
     pub fn new(seed: u16) -> Self {
-        let cl = Self {
+        Self {
             server_configs: ServerConfigs::new(seed),
             client_configs: ClientConfigs::new(seed),
             fat_peers: Vec::new(),
             thin_peers: Vec::new(),
-        };
+        }
+    }
 
-        cl
+    pub async fn create(&mut self, cnt: usize, thin: bool) -> Vec<Arc<Peer>> {
+        self.create_with(cnt, thin, |_| {}).await
     }
 
-    pub fn create(&mut self, cnt: usize, thin: bool) -> Vec<Arc<Peer>> {
+    pub async fn create_with<F: FnMut(&mut Config)>(&mut self, cnt: usize, thin: bool, mut f: F) -> Vec<Arc<Peer>> {
         let iter: &mut dyn Iterator<Item = Config> = if thin {
             &mut self.client_configs
         } else {
             &mut self.server_configs
         };
+        let configs: Vec<Config> = iter.take(cnt).collect();
 
-        let peers: Vec<Arc<Peer>> = iter.take(cnt).map(|c| Peer::new(c).unwrap()).collect();
+        let mut peers: Vec<Arc<Peer>> = Vec::new();
+        for mut c in configs {
+            f(&mut c);
+            peers.push(Peer::new_async(c).await.unwrap());
+        }
 
         if thin {
             self.thin_peers.extend(peers.iter().cloned());
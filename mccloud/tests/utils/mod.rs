@@ -8,13 +8,19 @@ use std::{
         Arc, LazyLock, Mutex, Once, RwLock,
     },
     thread::ThreadId,
+    time::{Duration, Instant},
 };
 
-use mccloud::Peer;
+use k256::{
+    elliptic_curve::{rand_core::OsRng, sec1::ToEncodedPoint},
+    SecretKey,
+};
+use mccloud::{blockchain::Block, Peer, PubKeyBytes};
 use tracing::{field::Visit, span};
 
 pub mod cluster;
 pub mod configs;
+pub mod stall;
 
 static LOGFILE: LazyLock<Mutex<File>> = LazyLock::new(|| {
     // let date = time::OffsetDateTime::now_utc();
@@ -123,14 +129,14 @@ impl tracing::Subscriber for MockSubscriber {
 
                 if let Some(mfile) = id2file {
                     let mut log = mfile.lock().unwrap();
-                    log.write(msg.as_bytes()).unwrap();
+                    log.write_all(msg.as_bytes()).unwrap();
                 } else {
                     let mut log = LOGFILE.lock().unwrap();
-                    log.write(msg.as_bytes()).unwrap();
+                    log.write_all(msg.as_bytes()).unwrap();
                 }
             } else {
                 let mut log = LOGFILE.lock().unwrap();
-                log.write(msg.as_bytes()).unwrap();
+                log.write_all(msg.as_bytes()).unwrap();
             }
         } else {
             let mut log = LOGFILE.lock().unwrap();
@@ -211,7 +217,7 @@ impl Visit for FileVisitor {
 }
 
 #[allow(dead_code)]
-pub async fn assert_all_known(peers: &Vec<Arc<Peer>>, cnt: usize) {
+pub async fn assert_all_known(peers: &[Arc<Peer>], cnt: usize) {
     for (i, p) in peers.iter().enumerate() {
         if !p.is_shutdown() {
             let all_kn_cnt = p.known_pubkeys().await.len();
@@ -219,3 +225,42 @@ pub async fn assert_all_known(peers: &Vec<Arc<Peer>>, cnt: usize) {
         }
     }
 }
+
+/// Polls until all running peers know the expected number of peers, then asserts it.
+#[allow(dead_code)]
+pub async fn wait_all_known(peers: &[Arc<Peer>], cnt: usize, timeout: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        let mut done = true;
+        for p in peers.iter().filter(|p| !p.is_shutdown()) {
+            done &= p.known_pubkeys().await.len() == cnt;
+        }
+        if done {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_all_known(peers, cnt).await;
+}
+
+/// Reads all blocks of the peer, from the root block on.
+#[allow(dead_code)]
+pub async fn all_blocks(peer: &Peer) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut stream = peer.block_iter().await;
+    while let Some(blk) = stream.next().await {
+        blocks.push(blk.unwrap());
+    }
+
+    blocks
+}
+
+/// Creates a random key pair.
+#[allow(dead_code)]
+pub fn create_key() -> (PubKeyBytes, SecretKey) {
+    let private = SecretKey::random(&mut OsRng);
+    let mut pubkey: PubKeyBytes = [0u8; 33];
+    pubkey.copy_from_slice(private.public_key().to_encoded_point(true).as_bytes());
+    (pubkey, private)
+}
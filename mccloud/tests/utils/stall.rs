@@ -0,0 +1,93 @@
+#![allow(dead_code)]
+
+use mccloud::{
+    transport::{BoxFuture, Listener, MemoryTransport, Stream, Transport, WriteHalf},
+    Result, TargetAddr,
+};
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+use tokio::io::AsyncWrite;
+
+/// Stalls and resumes the writers it is shared with.
+#[derive(Default)]
+pub struct Stall {
+    stalled: Mutex<(bool, Vec<Waker>)>,
+}
+
+impl Stall {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Stalls the writers, or resumes them and wakes the pending writes.
+    pub fn set(&self, stalled: bool) {
+        let mut s = self.stalled.lock().unwrap();
+        s.0 = stalled;
+        if !stalled {
+            s.1.drain(..).for_each(Waker::wake);
+        }
+    }
+}
+
+/// A writer which never finishes a write, once stalled. Like a peer which stopped reading its socket.
+pub struct StallingWriter {
+    pub inner: WriteHalf,
+    pub stall: Arc<Stall>,
+}
+
+impl AsyncWrite for StallingWriter {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        {
+            let mut s = self.stall.stalled.lock().unwrap();
+            if s.0 {
+                s.1.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A memory transport, where the connection to one peer can be stalled.
+pub struct StallingTransport {
+    pub inner: MemoryTransport,
+    /// The name of the peer, whose connection stalls.
+    pub slow: String,
+    pub stall: Arc<Stall>,
+}
+
+impl Transport for StallingTransport {
+    fn listen(&self) -> BoxFuture<'_, Result<Box<dyn Listener>>> {
+        self.inner.listen()
+    }
+
+    fn connect(&self, addr: TargetAddr<'static>) -> BoxFuture<'_, Result<Stream>> {
+        Box::pin(async move {
+            let slow = matches!(&addr, TargetAddr::Domain(d, _) if *d == self.slow);
+            let mut stream = self.inner.connect(addr).await?;
+            if slow {
+                stream.writer = Box::new(StallingWriter {
+                    inner: stream.writer,
+                    stall: self.stall.clone(),
+                });
+            }
+            Ok(stream)
+        })
+    }
+
+    fn announce(&self) -> String {
+        self.inner.announce()
+    }
+}